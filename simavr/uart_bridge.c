@@ -0,0 +1,234 @@
+// UART bridge for running team firmware under simavr (see `--dev simavr:<firmware.elf>`)
+//
+// Loads an .elf into a simulated AVR, connects its UART to a pseudo terminal and prints
+// on stdout, one per line:
+//   MCU <name> <hz>                   the AVR simulated and its clock, once the firmware is loaded
+//   PTY <path>                        the terminal to open
+//   RX <first> <last> <hex>           a line the PC sent, with the cycles its first and last
+//   TX <first> <last> <hex>           byte crossed the UART (RX: handed to it, TX: sent by it)
+//   EXIT <state> <cycle>              the simulation stopped (sleeping with interrupts off, crashed)
+// Lines are split after <LF>, ace411_sudoku turns the cycles between P and D into solve times
+//
+// Build: make -C simavr (needs libsimavr and libelf, e.g. apt install libsimavr-dev libelf-dev)
+// Usage: simavr-uart-bridge [-m <mcu>] [-f <hz>] <firmware.elf>
+
+#define _GNU_SOURCE
+#include <fcntl.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <termios.h>
+#include <unistd.h>
+
+#include <simavr/avr_uart.h>
+#include <simavr/sim_avr.h>
+#include <simavr/sim_elf.h>
+#include <simavr/sim_io.h>
+#include <simavr/sim_irq.h>
+
+// Defaults for .elf files without an .mmcu section, the STK-500's ATmega16 at 8 MHz
+#define DEFAULT_MCU "atmega16"
+#define DEFAULT_FREQUENCY 8000000
+// Longest line traced, longer ones are split
+#define MAX_LINE 1024
+// Instructions run between two polls of the pseudo terminal
+#define POLL_EVERY 1024
+
+// Bytes of one direction of the UART up to the next <LF>
+struct line {
+	uint8_t data[MAX_LINE];
+	size_t len;
+	avr_cycle_count_t first;
+};
+
+struct bridge {
+	avr_t *avr;
+	int pty;
+	avr_irq_t *input;
+	// Whether the UART takes another byte (its receive FIFO has room)
+	int xon;
+	// Bytes from the PC not handed to the UART yet
+	uint8_t pending[MAX_LINE];
+	size_t pending_len;
+	struct line rx;
+	struct line tx;
+};
+
+static void trace(const char *dir, struct line *line, avr_cycle_count_t last)
+{
+	printf("%s %llu %llu ", dir, (unsigned long long)line->first, (unsigned long long)last);
+	for (size_t i = 0; i < line->len; i++)
+		printf("%02x", line->data[i]);
+	printf("\n");
+	fflush(stdout);
+	line->len = 0;
+}
+
+// Adds a byte to a line, tracing the line once it ends
+static void add(const char *dir, struct line *line, uint8_t byte, avr_cycle_count_t cycle)
+{
+	if (line->len == 0)
+		line->first = cycle;
+	line->data[line->len++] = byte;
+	if (byte == '\n' || line->len == MAX_LINE)
+		trace(dir, line, cycle);
+}
+
+// Byte sent by the AVR
+static void uart_output(struct avr_irq_t *irq, uint32_t value, void *param)
+{
+	struct bridge *b = param;
+	uint8_t byte = value;
+
+	(void)irq;
+	if (write(b->pty, &byte, 1) != 1)
+		perror("write");
+	add("TX", &b->tx, byte, b->avr->cycle);
+}
+
+// Hands pending bytes to the UART while it takes them
+static void pump(struct bridge *b)
+{
+	size_t sent = 0;
+
+	while (b->xon && sent < b->pending_len) {
+		uint8_t byte = b->pending[sent++];
+		add("RX", &b->rx, byte, b->avr->cycle);
+		avr_raise_irq(b->input, byte);
+	}
+	memmove(b->pending, b->pending + sent, b->pending_len - sent);
+	b->pending_len -= sent;
+}
+
+static void uart_xon(struct avr_irq_t *irq, uint32_t value, void *param)
+{
+	struct bridge *b = param;
+
+	(void)irq;
+	(void)value;
+	b->xon = 1;
+	pump(b);
+}
+
+static void uart_xoff(struct avr_irq_t *irq, uint32_t value, void *param)
+{
+	struct bridge *b = param;
+
+	(void)irq;
+	(void)value;
+	b->xon = 0;
+}
+
+// Reads what the PC wrote to the pseudo terminal, without blocking
+static void poll_pty(struct bridge *b)
+{
+	ssize_t n;
+
+	if (b->pending_len == sizeof(b->pending))
+		return;
+	n = read(b->pty, b->pending + b->pending_len, sizeof(b->pending) - b->pending_len);
+	if (n > 0) {
+		b->pending_len += n;
+		pump(b);
+	}
+}
+
+// Opens a raw, non-blocking pseudo terminal, returning its master side
+static int open_pty(char *name, size_t size)
+{
+	struct termios raw;
+	int fd = posix_openpt(O_RDWR | O_NOCTTY);
+
+	if (fd < 0 || grantpt(fd) < 0 || unlockpt(fd) < 0 || !ptsname(fd))
+		return -1;
+	snprintf(name, size, "%s", ptsname(fd));
+	if (tcgetattr(fd, &raw) == 0) {
+		cfmakeraw(&raw);
+		tcsetattr(fd, TCSANOW, &raw);
+	}
+	fcntl(fd, F_SETFL, fcntl(fd, F_GETFL) | O_NONBLOCK);
+	return fd;
+}
+
+int main(int argc, char *argv[])
+{
+	static struct bridge b;
+	elf_firmware_t firmware;
+	const char *mcu = DEFAULT_MCU;
+	unsigned long frequency = DEFAULT_FREQUENCY;
+	char pty[128];
+	uint32_t flags = 0;
+	int opt, state;
+	long steps = 0;
+
+	while ((opt = getopt(argc, argv, "m:f:")) != -1) {
+		switch (opt) {
+		case 'm':
+			mcu = optarg;
+			break;
+		case 'f':
+			frequency = strtoul(optarg, NULL, 10);
+			break;
+		default:
+			fprintf(stderr, "Usage: %s [-m <mcu>] [-f <hz>] <firmware.elf>\n", argv[0]);
+			return 2;
+		}
+	}
+	if (optind != argc - 1) {
+		fprintf(stderr, "Usage: %s [-m <mcu>] [-f <hz>] <firmware.elf>\n", argv[0]);
+		return 2;
+	}
+
+	memset(&firmware, 0, sizeof(firmware));
+	if (elf_read_firmware(argv[optind], &firmware) != 0) {
+		fprintf(stderr, "Unable to load %s\n", argv[optind]);
+		return 1;
+	}
+	if (!firmware.mmcu[0])
+		snprintf(firmware.mmcu, sizeof(firmware.mmcu), "%s", mcu);
+	if (!firmware.frequency)
+		firmware.frequency = frequency;
+
+	b.avr = avr_make_mcu_by_name(firmware.mmcu);
+	if (!b.avr) {
+		fprintf(stderr, "Unknown AVR '%s'\n", firmware.mmcu);
+		return 1;
+	}
+	avr_init(b.avr);
+	avr_load_firmware(b.avr, &firmware);
+
+	// simavr prints UART output on its own unless told not to
+	avr_ioctl(b.avr, AVR_IOCTL_UART_GET_FLAGS('0'), &flags);
+	flags &= ~AVR_UART_FLAG_STDIO;
+	avr_ioctl(b.avr, AVR_IOCTL_UART_SET_FLAGS('0'), &flags);
+
+	b.input = avr_io_getirq(b.avr, AVR_IOCTL_UART_GETIRQ('0'), UART_IRQ_INPUT);
+	avr_irq_register_notify(avr_io_getirq(b.avr, AVR_IOCTL_UART_GETIRQ('0'), UART_IRQ_OUTPUT),
+				uart_output, &b);
+	avr_irq_register_notify(avr_io_getirq(b.avr, AVR_IOCTL_UART_GETIRQ('0'), UART_IRQ_OUT_XON),
+				uart_xon, &b);
+	avr_irq_register_notify(avr_io_getirq(b.avr, AVR_IOCTL_UART_GETIRQ('0'), UART_IRQ_OUT_XOFF),
+				uart_xoff, &b);
+
+	b.pty = open_pty(pty, sizeof(pty));
+	if (b.pty < 0) {
+		perror("Unable to open a pseudo terminal");
+		return 1;
+	}
+	b.xon = 1;
+	printf("MCU %s %lu\n", firmware.mmcu, (unsigned long)firmware.frequency);
+	printf("PTY %s\n", pty);
+	fflush(stdout);
+
+	do {
+		if (++steps % POLL_EVERY == 0)
+			poll_pty(&b);
+		state = avr_run(b.avr);
+	} while (state != cpu_Done && state != cpu_Crashed);
+
+	printf("EXIT %s %llu\n", state == cpu_Done ? "done" : "crashed",
+	       (unsigned long long)b.avr->cycle);
+	fflush(stdout);
+	return state == cpu_Done ? 0 : 1;
+}
@@ -0,0 +1,303 @@
+use colored::*;
+use log::info;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::session::{self, Event};
+use crate::solver::{self, Algorithm};
+use crate::{play_again_on_reconnect, Difficulty, Port, Result, SudokuAvr};
+
+// Widest histogram bar in characters
+const BAR_WIDTH: usize = 40;
+// Percentile reported besides the median
+const PERCENTILE: f64 = 0.95;
+
+// What bench plays its boards against
+pub enum Target<'a> {
+    // A device or the emulator, timed like a tournament
+    Avr(&'a mut Port),
+    // A host side solver, timed on the PC
+    Solver { algorithm: Algorithm, budget: u64 },
+}
+
+impl Target<'_> {
+    // Name of the target in the CSV and the session log, the firmware for simulated ones
+    pub fn name(&self) -> String {
+        match self {
+            Target::Avr(port) => match port.simulation() {
+                Some(simulation) => String::from(simulation.firmware()),
+                None => port.name().unwrap_or_else(|| String::from("AVR")),
+            },
+            Target::Solver { algorithm, .. } => format!("solver {}", algorithm),
+        }
+    }
+}
+
+// One timed board
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub difficulty: Difficulty,
+    /* Index of the board among the ones of its difficulty */
+    pub board: usize,
+    pub time: Duration,
+    pub valid: bool,
+    /* Simulated cycles, for firmware under simavr */
+    pub cycles: Option<u64>,
+}
+
+// Statistics of the times of one difficulty, in milliseconds
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Summary {
+    pub samples: usize,
+    /* Samples without a valid solution, their times are included */
+    pub invalid: usize,
+    pub min_ms: f64,
+    pub median_ms: f64,
+    pub mean_ms: f64,
+    pub p95_ms: f64,
+    pub max_ms: f64,
+    /* Sample standard deviation, 0 for a single sample */
+    pub std_dev_ms: f64,
+}
+
+impl Summary {
+    // Summarises samples, None if there are none
+    pub fn of(samples: &[&Sample]) -> Option<Self> {
+        let mut ms: Vec<f64> = samples
+            .iter()
+            .map(|sample| sample.time.as_secs_f64() * 1000.0)
+            .collect();
+        if ms.is_empty() {
+            return None;
+        }
+        ms.sort_by(|a, b| a.total_cmp(b));
+        let n = ms.len();
+        let mean = ms.iter().sum::<f64>() / n as f64;
+        let variance = match n {
+            1 => 0.0,
+            _ => ms.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / (n - 1) as f64,
+        };
+        Some(Summary {
+            samples: n,
+            invalid: samples.iter().filter(|sample| !sample.valid).count(),
+            min_ms: ms[0],
+            median_ms: match n % 2 {
+                0 => (ms[n / 2 - 1] + ms[n / 2]) / 2.0,
+                _ => ms[n / 2],
+            },
+            mean_ms: mean,
+            // Nearest rank: the smallest time at least PERCENTILE of the samples do not exceed
+            p95_ms: ms[((PERCENTILE * n as f64).ceil() as usize).clamp(1, n) - 1],
+            max_ms: ms[n - 1],
+            std_dev_ms: variance.sqrt(),
+        })
+    }
+}
+
+// Plays (or solves) one board and times it
+pub fn play(target: &mut Target, sudoku: &SudokuAvr, board: usize) -> Result<Sample> {
+    let (time, valid, cycles) = match target {
+        Target::Avr(port) => {
+            let (timing, valid) = play_again_on_reconnect(port, sudoku, board)?;
+            (
+                timing.solve(),
+                valid,
+                timing.simulated.map(|simulated| simulated.cycles),
+            )
+        }
+        Target::Solver { algorithm, budget } => {
+            let (solve, valid) = solver::attempt(*algorithm, sudoku, *budget)?;
+            (solve.elapsed, valid == Some(true), None)
+        }
+    };
+    Ok(Sample {
+        difficulty: sudoku.dif.clone(),
+        board,
+        time,
+        valid,
+        cycles,
+    })
+}
+
+// Writes the samples as CSV: target,difficulty,board,ms,valid,cycles
+pub fn write_csv(path: &Path, target: &str, samples: &[Sample]) -> Result<()> {
+    let mut f = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(path)?;
+    writeln!(f, "target,difficulty,board,ms,valid,cycles")?;
+    for sample in samples {
+        writeln!(
+            f,
+            "{},{},{},{:.3},{},{}",
+            target,
+            sample.difficulty,
+            sample.board,
+            sample.time.as_secs_f64() * 1000.0,
+            sample.valid,
+            sample.cycles.map(|c| c.to_string()).unwrap_or_default()
+        )?;
+    }
+    info!("Samples written to {}", path.display());
+    Ok(())
+}
+
+// Prints the summary of every difficulty with a histogram of bins bars, recording them in the
+// session log
+pub fn print_summaries(target: &str, samples: &[Sample], bins: usize) {
+    let mut by_difficulty: BTreeMap<Difficulty, Vec<&Sample>> = BTreeMap::new();
+    for sample in samples {
+        by_difficulty
+            .entry(sample.difficulty.clone())
+            .or_default()
+            .push(sample);
+    }
+
+    for (difficulty, samples) in &by_difficulty {
+        let summary = match Summary::of(samples) {
+            Some(summary) => summary,
+            None => continue,
+        };
+        println!(
+            "{}",
+            format!("{}: {} boards on {}", difficulty, summary.samples, target)
+                .white()
+                .bold()
+        );
+        println!(
+            "  {:>14}{:>14}{:>14}{:>14}{:>14}{:>14}",
+            "Min", "Median", "Mean", "p95", "Max", "Std dev"
+        );
+        println!(
+            "  {:>11.3} ms{:>11.3} ms{:>11.3} ms{:>11.3} ms{:>11.3} ms{:>11.3} ms",
+            summary.min_ms,
+            summary.median_ms,
+            summary.mean_ms,
+            summary.p95_ms,
+            summary.max_ms,
+            summary.std_dev_ms
+        );
+        if summary.invalid > 0 {
+            println!(
+                "  {}",
+                format!("{} without a valid solution", summary.invalid)
+                    .red()
+                    .bold()
+            );
+        }
+        print_histogram(samples, &summary, bins);
+        session::record(Event::Bench {
+            target: String::from(target),
+            difficulty: difficulty.to_string(),
+            summary,
+        });
+    }
+}
+
+// Bars of equal width between the fastest and the slowest sample, one line each
+fn print_histogram(samples: &[&Sample], summary: &Summary, bins: usize) {
+    let bins = bins.max(1);
+    let width = (summary.max_ms - summary.min_ms) / bins as f64;
+    let mut counts = vec![0usize; bins];
+    for sample in samples {
+        let ms = sample.time.as_secs_f64() * 1000.0;
+        let bin = match width > 0.0 {
+            true => (((ms - summary.min_ms) / width) as usize).min(bins - 1),
+            false => 0,
+        };
+        counts[bin] += 1;
+    }
+    // All samples alike: a single bar says it all
+    let shown = if width > 0.0 { bins } else { 1 };
+    let most = counts.iter().copied().max().unwrap_or(1).max(1);
+    for (bin, &count) in counts.iter().enumerate().take(shown) {
+        let from = summary.min_ms + width * bin as f64;
+        let bar = "#".repeat((count * BAR_WIDTH).div_ceil(most));
+        println!(
+            "  {:>11.3} - {:>11.3} ms |{:<width$}| {}",
+            from,
+            from + width,
+            bar,
+            count,
+            width = BAR_WIDTH
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(ms: &[u64]) -> Vec<Sample> {
+        ms.iter()
+            .enumerate()
+            .map(|(board, &ms)| Sample {
+                difficulty: Difficulty::Easy,
+                board,
+                time: Duration::from_millis(ms),
+                valid: true,
+                cycles: None,
+            })
+            .collect()
+    }
+
+    fn summary(ms: &[u64]) -> Option<Summary> {
+        summary_of(&samples(ms))
+    }
+
+    fn summary_of(samples: &[Sample]) -> Option<Summary> {
+        Summary::of(&samples.iter().collect::<Vec<_>>())
+    }
+
+    fn assert_near(got: f64, expected: f64) {
+        assert!((got - expected).abs() < 1e-9, "{} != {}", got, expected);
+    }
+
+    #[test]
+    fn even_number_of_samples() {
+        let ms: Vec<u64> = (1..=20).rev().collect();
+        let summary = summary(&ms).unwrap();
+        assert_eq!(summary.samples, 20);
+        assert_eq!(summary.invalid, 0);
+        assert_near(summary.min_ms, 1.0);
+        assert_near(summary.median_ms, 10.5);
+        assert_near(summary.mean_ms, 10.5);
+        assert_near(summary.p95_ms, 19.0);
+        assert_near(summary.max_ms, 20.0);
+        assert_near(summary.std_dev_ms, 35f64.sqrt());
+    }
+
+    #[test]
+    fn odd_number_of_samples() {
+        let mut samples = samples(&[3, 1, 2]);
+        samples[0].valid = false;
+        let summary = summary_of(&samples).unwrap();
+        // Invalid samples are counted and still timed
+        assert_eq!(summary.invalid, 1);
+        assert_near(summary.max_ms, 3.0);
+        assert_near(summary.median_ms, 2.0);
+        // ceil(0.95 * 3) = 3rd of 3
+        assert_near(summary.p95_ms, 3.0);
+        assert_near(summary.std_dev_ms, 1.0);
+    }
+
+    #[test]
+    fn single_sample() {
+        let summary = summary(&[7]).unwrap();
+        assert_near(summary.min_ms, 7.0);
+        assert_near(summary.median_ms, 7.0);
+        assert_near(summary.p95_ms, 7.0);
+        assert_near(summary.max_ms, 7.0);
+        assert_near(summary.std_dev_ms, 0.0);
+    }
+
+    #[test]
+    fn no_samples() {
+        assert!(summary(&[]).is_none());
+    }
+}
@@ -0,0 +1,134 @@
+use log::{debug, info, warn};
+use serialport::ClearBuffer;
+use std::time::Duration;
+
+use crate::grid::{self, Shape};
+use crate::{read_uart, send_uart, write_uart, Error, Port, Result, OK};
+
+// Asks the firmware whether it takes bulk frames, answered with OK if it does
+pub const QUERY: &[u8] = b"W?\r\n";
+// Asks for the whole board in a single Q frame
+pub const READ: &[u8] = b"Q\r\n";
+// Reply of the firmware to a bulk frame with a wrong checksum
+pub const ERROR: &[u8] = b"ER\r\n";
+// How long legacy firmware gets to answer QUERY, it usually stays silent
+const QUERY_TIMEOUT: Duration = Duration::from_millis(300);
+// Times a bulk frame is sent or requested before giving up on a bad checksum
+const ATTEMPTS: usize = 3;
+
+// Checksum of a bulk frame: the sum of its symbols modulo 256
+pub fn checksum(symbols: &[u8]) -> u8 {
+    symbols.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+// Builds a [<CMD><SYMBOLS><CHK><CR><LF>] frame holding every Cell
+// One symbol per Cell in row major order (as in board files), then the checksum as two hex digits
+pub fn frame(cmd: u8, values: &[u8]) -> Vec<u8> {
+    let symbols: Vec<u8> = values.iter().map(|&v| grid::encode_symbol(v)).collect();
+    let mut frame = vec![cmd];
+    frame.extend_from_slice(&symbols);
+    frame.extend(format!("{:02X}\r\n", checksum(&symbols)).into_bytes());
+    frame
+}
+
+// Parses a bulk frame of a board of the given shape into its values
+pub fn parse_frame(cmd: u8, frame: &[u8], shape: Shape) -> Result<Vec<u8>> {
+    let cells = shape.cells();
+    let bad = || {
+        Error::Parse(format!(
+            "Invalid bulk frame {}",
+            String::from_utf8_lossy(frame).escape_debug()
+        ))
+    };
+    if frame.len() != cells + 5 || frame[0] != cmd || !frame.ends_with(b"\r\n") {
+        return Err(bad());
+    }
+    let symbols = &frame[1..=cells];
+    let expected = std::str::from_utf8(&frame[cells + 1..cells + 3])
+        .ok()
+        .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        .ok_or_else(bad)?;
+    if checksum(symbols) != expected {
+        return Err(Error::Parse(format!(
+            "Bulk frame checksum {:02X}, expected {:02X}",
+            checksum(symbols),
+            expected
+        )));
+    }
+    symbols
+        .iter()
+        .map(|&s| grid::decode_symbol(s).filter(|&v| (v as usize) <= shape.size()))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(bad)
+}
+
+// Whether the firmware takes W / Q frames, asking it with W? the first time
+// Legacy firmware ignores W? (or answers something else): the per-cell protocol is used then
+pub fn supported(port: &mut Port) -> Result<bool> {
+    if let Some(bulk) = port.bulk() {
+        return Ok(bulk);
+    }
+    let timeout = port.timeout();
+    port.set_timeout(QUERY_TIMEOUT)?;
+    port.clear(ClearBuffer::All)?;
+    send_uart(port, QUERY)?;
+    let answer = read_uart(port, OK.len() as i32);
+    port.set_timeout(timeout)?;
+    let bulk = match answer {
+        Ok(data) => data == OK,
+        Err(Error::Timeout) => false,
+        Err(e) => return Err(e),
+    };
+    // Whatever legacy firmware made of W? must not be taken as the reply to the next command
+    port.clear(ClearBuffer::All)?;
+    info!(
+        "Firmware {} bulk transfers",
+        if bulk { "supports" } else { "does not support" }
+    );
+    port.set_bulk(Some(bulk));
+    Ok(bulk)
+}
+
+// Sends every Cell in one W frame, resending it while the AVR reports a bad checksum
+// returns the frame sent
+pub fn send(port: &mut Port, values: &[u8]) -> Result<Vec<u8>> {
+    let frame = frame(b'W', values);
+    let mut reply = Vec::new();
+    for attempt in 1..=ATTEMPTS {
+        write_uart(port, &frame)?;
+        reply = read_uart(port, OK.len() as i32)?;
+        if reply == OK {
+            return Ok(frame);
+        }
+        warn!(
+            "Bulk download rejected ({}), attempt {}/{}",
+            String::from_utf8_lossy(&reply).escape_debug(),
+            attempt,
+            ATTEMPTS
+        );
+        port.clear(ClearBuffer::Input)?;
+    }
+    Err(Error::protocol(OK, &reply))
+}
+
+// Reads every Cell of the AVR's board with a single Q frame, asking again on a bad checksum
+pub fn receive(port: &mut Port, shape: Shape) -> Result<Vec<u8>> {
+    let len = shape.cells() + 5;
+    let mut last = Error::Timeout;
+    for attempt in 1..=ATTEMPTS {
+        write_uart(port, READ)?;
+        let data = read_uart(port, len as i32)?;
+        match parse_frame(b'Q', &data, shape) {
+            Ok(values) => return Ok(values),
+            Err(e) => {
+                debug!(
+                    "Bulk readback attempt {}/{} failed: {}",
+                    attempt, ATTEMPTS, e
+                );
+                last = e;
+            }
+        }
+        port.clear(ClearBuffer::Input)?;
+    }
+    Err(last)
+}
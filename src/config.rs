@@ -0,0 +1,203 @@
+use log::debug;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::value::{Table, Value};
+
+use crate::port::Adapter;
+use crate::{Difficulty, Error, Result};
+
+// Config file looked for in the working directory, then in the user's config directory
+pub const CONFIG_FILE: &str = "ace411_sudoku.toml";
+// Subdirectory of the user's config directory (~/.config on Linux)
+const CONFIG_DIR: &str = "ace411_sudoku";
+// Output directories unless the config says otherwise
+pub const EXPORT_DIR: &str = "exports";
+pub const TOURNAMENT_DIR: &str = "tournament";
+
+// Contents of the config file
+// Top level settings apply to every run, [profiles.<name>] tables override them, e.g.
+//   profile = "stk500-lab3"
+//   difficulty = "Medium"
+//   [profiles.stk500-lab3]
+//   dev = "/dev/ttyUSB0"
+//   baud_rate = 9600
+// Unknown keys are errors, a typo would otherwise fall back to the defaults unnoticed
+#[derive(Debug, Default)]
+pub struct Config {
+    /* Profile used when --profile is not given */
+    pub profile: Option<String>,
+    pub defaults: Profile,
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+// Settings of a profile, everything left out falls back to the top level of the file
+// and then to the built in defaults
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub dev: Option<String>,
+    pub baud_rate: Option<u32>,
+    pub stop_bits: Option<u8>,
+    pub data_bits: Option<u8>,
+    /* None, Even or Odd */
+    pub parity: Option<String>,
+    /* Read timeout of the port in milliseconds */
+    pub timeout_ms: Option<u64>,
+    /* Sleep after every frame in milliseconds, for slow firmware */
+    pub delay_ms: Option<u64>,
+    /* Board frames sent before waiting for their OKs */
+    pub window: Option<usize>,
+    /* Use bulk frames if the firmware supports them */
+    pub bulk: Option<bool>,
+    /* Frame every line with a sequence number and a CRC */
+    pub crc: Option<bool>,
+    /* Skip the I and W? handshakes, assuming the legacy command set */
+    pub legacy: Option<bool>,
+    /* USB adapters --dev auto looks for besides the known ones */
+    pub adapters: Option<Vec<Adapter>>,
+    /* Let --dev auto send AT to every candidate */
+    pub probe: Option<bool>,
+    /* Look for the baud rate, parity and stop bits the AVR answers to */
+    pub detect: Option<bool>,
+    /* Seconds to wait for a disconnected device to come back, 0 to give up right away */
+    pub reconnect_s: Option<u64>,
+    /* UART bridge, AVR and clock (in Hz) for --dev simavr:<firmware.elf> */
+    pub simavr_bridge: Option<String>,
+    pub simavr_mcu: Option<String>,
+    pub simavr_clock_hz: Option<u64>,
+    /* Difficulty of `run` when --difficulty is not given */
+    pub difficulty: Option<Difficulty>,
+    pub export_dir: Option<String>,
+    pub tournament_dir: Option<String>,
+    pub log_dir: Option<String>,
+}
+
+impl Config {
+    // Reads the config file at path, or the first one found in the default locations
+    // Without a path and without a file in the default locations every setting is unset
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match Config::find() {
+                Some(path) => path,
+                None => {
+                    debug!("No config file found");
+                    return Ok(Config::default());
+                }
+            },
+        };
+        debug!("Reading config {}", path.display());
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))?;
+        Config::parse(&contents).map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))
+    }
+
+    // The top level keys besides profile and profiles are the default Profile
+    // (serde's flatten would accept any key there, see Profile's deny_unknown_fields)
+    fn parse(contents: &str) -> std::result::Result<Self, String> {
+        let mut table: Table = toml::from_str(contents).map_err(|e| e.to_string())?;
+        let profile = match table.remove("profile") {
+            Some(Value::String(name)) => Some(name),
+            Some(other) => return Err(format!("profile must be a string, got {}", other)),
+            None => None,
+        };
+        let profiles = match table.remove("profiles") {
+            Some(Value::Table(profiles)) => profiles
+                .into_iter()
+                .map(|(name, profile)| match profile.try_into() {
+                    Ok(profile) => Ok((name, profile)),
+                    Err(e) => Err(format!("profiles.{}: {}", name, e)),
+                })
+                .collect::<std::result::Result<BTreeMap<String, Profile>, String>>()?,
+            Some(other) => return Err(format!("profiles must be a table, got {}", other)),
+            None => BTreeMap::new(),
+        };
+        let defaults = Value::Table(table).try_into().map_err(|e| e.to_string())?;
+        Ok(Config {
+            profile,
+            defaults,
+            profiles,
+        })
+    }
+
+    // First config file of the default locations that exists
+    fn find() -> Option<PathBuf> {
+        let local = PathBuf::from(CONFIG_FILE);
+        let user = dirs::config_dir().map(|dir| dir.join(CONFIG_DIR).join("config.toml"));
+        std::iter::once(local)
+            .chain(user)
+            .find(|path| path.is_file())
+    }
+
+    // Settings of the named profile (or the file's default profile) on top of the top level ones
+    pub fn profile(&self, name: Option<&str>) -> Result<Profile> {
+        let name = match name.or(self.profile.as_deref()) {
+            Some(name) => name,
+            None => return Ok(self.defaults.clone()),
+        };
+        match self.profiles.get(name) {
+            Some(profile) => Ok(profile.over(&self.defaults)),
+            None => Err(Error::Config(format!(
+                "No profile '{}' (known: {})",
+                name,
+                self.profiles
+                    .keys()
+                    .cloned()
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ))),
+        }
+    }
+}
+
+impl Profile {
+    // Settings of self, with the ones it leaves out taken from base
+    pub fn over(&self, base: &Profile) -> Profile {
+        Profile {
+            dev: self.dev.clone().or_else(|| base.dev.clone()),
+            baud_rate: self.baud_rate.or(base.baud_rate),
+            stop_bits: self.stop_bits.or(base.stop_bits),
+            data_bits: self.data_bits.or(base.data_bits),
+            parity: self.parity.clone().or_else(|| base.parity.clone()),
+            timeout_ms: self.timeout_ms.or(base.timeout_ms),
+            delay_ms: self.delay_ms.or(base.delay_ms),
+            window: self.window.or(base.window),
+            bulk: self.bulk.or(base.bulk),
+            crc: self.crc.or(base.crc),
+            legacy: self.legacy.or(base.legacy),
+            adapters: self.adapters.clone().or_else(|| base.adapters.clone()),
+            probe: self.probe.or(base.probe),
+            detect: self.detect.or(base.detect),
+            reconnect_s: self.reconnect_s.or(base.reconnect_s),
+            simavr_bridge: self
+                .simavr_bridge
+                .clone()
+                .or_else(|| base.simavr_bridge.clone()),
+            simavr_mcu: self.simavr_mcu.clone().or_else(|| base.simavr_mcu.clone()),
+            simavr_clock_hz: self.simavr_clock_hz.or(base.simavr_clock_hz),
+            difficulty: self.difficulty.clone().or_else(|| base.difficulty.clone()),
+            export_dir: self.export_dir.clone().or_else(|| base.export_dir.clone()),
+            tournament_dir: self
+                .tournament_dir
+                .clone()
+                .or_else(|| base.tournament_dir.clone()),
+            log_dir: self.log_dir.clone().or_else(|| base.log_dir.clone()),
+        }
+    }
+
+    pub fn export_dir(&self) -> &str {
+        self.export_dir.as_deref().unwrap_or(EXPORT_DIR)
+    }
+
+    pub fn tournament_dir(&self) -> &str {
+        self.tournament_dir.as_deref().unwrap_or(TOURNAMENT_DIR)
+    }
+
+    pub fn log_dir(&self) -> &str {
+        self.log_dir
+            .as_deref()
+            .unwrap_or(crate::session::DEFAULT_DIR)
+    }
+}
@@ -0,0 +1,392 @@
+use colored::*;
+use serde::Serialize;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::ops::AddAssign;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::session::{self, Event};
+use crate::solver::{self, Algorithm};
+use crate::{Difficulty, Error, Result, SudokuAvr};
+
+// Clock of the STK-500's ATmega16 in MHz
+pub const DEFAULT_CLOCK_MHZ: f64 = 8.0;
+// SRAM of the ATmega16, the stack included
+pub const SRAM_BYTES: u64 = 1024;
+
+// ATmega16 cycles per operation, from the instruction set manual
+// LD / ST from SRAM
+const LOAD: u64 = 2;
+const STORE: u64 = 2;
+// CP / CPC per byte compared
+const COMPARE: u64 = 1;
+// Conditional branch, taken (2) more often than not (1) in search loops
+const BRANCH: u64 = 2;
+// Arithmetic and logic per byte
+const ALU: u64 = 1;
+// RCALL and RET
+const CALL: u64 = 7;
+
+// Abstract operations of a solve, counted per byte the ATmega16 has to move or compute on
+// 16 bit masks (boards over 8x8) and 16 bit node indices (dancing links) count twice
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct Ops {
+    /* Bytes loaded from SRAM */
+    pub reads: u64,
+    /* Bytes stored to SRAM */
+    pub writes: u64,
+    /* Bytes compared */
+    pub compares: u64,
+    /* Conditional branches */
+    pub branches: u64,
+    /* Bytes of arithmetic and logic */
+    pub alu: u64,
+    /* Function calls */
+    pub calls: u64,
+}
+
+impl Ops {
+    // Estimated ATmega16 cycles
+    pub fn cycles(&self) -> u64 {
+        self.reads * LOAD
+            + self.writes * STORE
+            + self.compares * COMPARE
+            + self.branches * BRANCH
+            + self.alu * ALU
+            + self.calls * CALL
+    }
+
+    // Estimated time on an ATmega16 clocked at clock_mhz
+    pub fn time(&self, clock_mhz: f64) -> Duration {
+        Duration::from_secs_f64(self.cycles() as f64 / (clock_mhz * 1e6))
+    }
+}
+
+impl AddAssign for Ops {
+    fn add_assign(&mut self, other: Ops) {
+        self.reads += other.reads;
+        self.writes += other.writes;
+        self.compares += other.compares;
+        self.branches += other.branches;
+        self.alu += other.alu;
+        self.calls += other.calls;
+    }
+}
+
+// Counts Ops through a shared reference, most of a search only reads its state
+// The solvers are generic over it so that plain solves run without counting (see NoCount)
+pub(crate) trait Count: Default {
+    fn add(&self, count: impl FnOnce(&mut Ops));
+
+    fn ops(&self) -> Ops;
+
+    fn read(&self, bytes: u64) {
+        self.add(|ops| ops.reads += bytes);
+    }
+
+    fn write(&self, bytes: u64) {
+        self.add(|ops| ops.writes += bytes);
+    }
+
+    // A compare of bytes and the branch on its outcome
+    fn test(&self, bytes: u64) {
+        self.add(|ops| {
+            ops.compares += bytes;
+            ops.branches += 1;
+        });
+    }
+
+    fn alu(&self, bytes: u64) {
+        self.add(|ops| ops.alu += bytes);
+    }
+
+    fn call(&self) {
+        self.add(|ops| ops.calls += 1);
+    }
+}
+
+// Counts every operation, for estimates
+#[derive(Debug, Default)]
+pub(crate) struct Counter(Cell<Ops>);
+
+impl Count for Counter {
+    fn add(&self, count: impl FnOnce(&mut Ops)) {
+        let mut ops = self.0.get();
+        count(&mut ops);
+        self.0.set(ops);
+    }
+
+    fn ops(&self) -> Ops {
+        self.0.get()
+    }
+}
+
+// Counts nothing, so that timed solves measure the solver alone
+#[derive(Debug, Default)]
+pub(crate) struct NoCount;
+
+impl Count for NoCount {
+    fn add(&self, _count: impl FnOnce(&mut Ops)) {}
+
+    fn ops(&self) -> Ops {
+        Ops::default()
+    }
+}
+
+// Solve time of a board in a tournament results file
+#[derive(Debug, Clone)]
+pub struct Played {
+    /* Difficulty the results file gives the board, checked against the board estimated */
+    pub difficulty: Difficulty,
+    pub time: Duration,
+}
+
+// Reads the solve times of a tournament results file (team_<team>.txt), by board index
+pub fn load_results(path: &Path) -> Result<BTreeMap<usize, Played>> {
+    let text = fs::read_to_string(path)?;
+    let invalid = |line: &str| {
+        Error::Parse(format!(
+            "Invalid line '{}' in {}",
+            line.trim(),
+            path.display()
+        ))
+    };
+    let mut results = BTreeMap::new();
+    let mut board = None;
+    let mut difficulty = None;
+    for line in text.lines() {
+        if let Some(i) = line.strip_prefix("Board: ") {
+            board = Some(i.trim().parse().map_err(|_| invalid(line))?);
+        } else if let Some(dif) = line.strip_prefix("Difficulty: ") {
+            difficulty = Some(dif.trim().parse().map_err(|_| invalid(line))?);
+        } else if let Some(time) = line.strip_prefix("Time to solve: ") {
+            let secs: f64 = time
+                .trim()
+                .trim_end_matches('s')
+                .trim()
+                .parse()
+                .map_err(|_| invalid(line))?;
+            let i = board.take().ok_or_else(|| invalid(line))?;
+            let difficulty = difficulty.take().ok_or_else(|| invalid(line))?;
+            results.insert(
+                i,
+                Played {
+                    difficulty,
+                    time: Duration::from_secs_f64(secs),
+                },
+            );
+        }
+    }
+    if results.is_empty() {
+        return Err(Error::Parse(format!(
+            "No solve times in {}",
+            path.display()
+        )));
+    }
+    Ok(results)
+}
+
+// Fails unless every board of the results is the board of the same index, by difficulty
+// Boards from another directory (or board files besides it) would be paired with the wrong times
+fn check_results(boards: &[(String, SudokuAvr)], results: &BTreeMap<usize, Played>) -> Result<()> {
+    for (&i, played) in results {
+        match boards.get(i) {
+            Some((_, sudoku)) if sudoku.dif == played.difficulty => (),
+            Some((name, sudoku)) => {
+                return Err(Error::Parse(format!(
+                    "Board {} of the results is {}, but {} is {}: not the tournament's boards",
+                    i, played.difficulty, name, sudoku.dif
+                )))
+            }
+            None => {
+                return Err(Error::Parse(format!(
+                    "The results have a board {}, but there are only {} boards: not the tournament's boards",
+                    i,
+                    boards.len()
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+// Means of the estimates of one algorithm over the boards of one difficulty
+#[derive(Default)]
+struct Totals {
+    boards: u64,
+    ops: Ops,
+    /* The most SRAM a board needed, a mean could hide one that does not fit */
+    sram: u64,
+    /* Estimated and tournament time of the boards with a tournament time */
+    compared: u64,
+    estimated: Duration,
+    avr: Duration,
+}
+
+fn print_header(compare: bool) {
+    print!(
+        "  {:<14}{:>12}{:>12}{:>12}{:>12}{:>14}{:>14}{:>8}",
+        "Algorithm", "Reads", "Writes", "Compares", "Branches", "Cycles", "Estimate", "SRAM"
+    );
+    match compare {
+        true => println!("{:>14}{:>8}", "AVR", "Ratio"),
+        false => println!(),
+    }
+}
+
+// One line of estimates, means if divided by more than one board (except sram)
+// avr: the tournament time (a mean as well) and its ratio to the estimate, if known
+fn print_row(
+    algorithm: Algorithm,
+    ops: &Ops,
+    sram: u64,
+    boards: u64,
+    clock_mhz: f64,
+    avr: Option<(Duration, f64)>,
+    note: ColoredString,
+) {
+    let boards = boards.max(1);
+    let mean = Ops {
+        reads: ops.reads / boards,
+        writes: ops.writes / boards,
+        compares: ops.compares / boards,
+        branches: ops.branches / boards,
+        alu: ops.alu / boards,
+        calls: ops.calls / boards,
+    };
+    let sram = match sram > SRAM_BYTES {
+        true => format!("{:>8}", sram).red().bold(),
+        false => format!("{:>8}", sram).normal(),
+    };
+    print!(
+        "  {:<14}{:>12}{:>12}{:>12}{:>12}{:>14}{:>11.3} ms{}",
+        algorithm.to_string(),
+        mean.reads,
+        mean.writes,
+        mean.compares,
+        mean.branches,
+        mean.cycles(),
+        mean.time(clock_mhz).as_secs_f64() * 1000.0,
+        sram
+    );
+    if let Some((avr, ratio)) = avr {
+        print!("{:>11.3} ms{:>7.2}x", avr.as_secs_f64() * 1000.0, ratio);
+    }
+    println!("  {}", note);
+}
+
+// How many times slower the AVR was than estimated
+fn ratio(avr: Duration, estimated: Duration) -> f64 {
+    avr.as_secs_f64() / estimated.as_secs_f64().max(f64::MIN_POSITIVE)
+}
+
+// Solves every board with every algorithm while counting operations, printing the estimated
+// ATmega16 cycles, time at clock_mhz and SRAM of each solve and their means per difficulty
+// results: tournament solve times by board index, compared against the estimates; the boards must
+// be the tournament's, in the order it played them
+pub fn print_estimates(
+    boards: &[(String, SudokuAvr)],
+    algorithms: &[Algorithm],
+    budget: u64,
+    clock_mhz: f64,
+    results: Option<&BTreeMap<usize, Played>>,
+) -> Result<()> {
+    if let Some(results) = results {
+        check_results(boards, results)?;
+    }
+    let compare = results.is_some();
+    let mut totals: BTreeMap<(Difficulty, usize), Totals> = BTreeMap::new();
+
+    for (i, (name, sudoku)) in boards.iter().enumerate() {
+        let avr = results.and_then(|results| results.get(&i).map(|played| played.time));
+        println!(
+            "{}",
+            format!(
+                "{}: {} {} {}{}",
+                name,
+                sudoku.dif,
+                sudoku.variant(),
+                sudoku.shape(),
+                match (compare, avr) {
+                    (true, None) => format!(", no tournament time for board {}", i),
+                    _ => String::new(),
+                }
+            )
+            .white()
+            .bold()
+        );
+        print_header(compare);
+        for (a, &algorithm) in algorithms.iter().enumerate() {
+            let (solve, valid) = solver::attempt_counted(algorithm, sudoku, budget)?;
+            let estimated = solve.ops.time(clock_mhz);
+            print_row(
+                algorithm,
+                &solve.ops,
+                solve.sram,
+                1,
+                clock_mhz,
+                avr.map(|avr| (avr, ratio(avr, estimated))),
+                solver::note(valid, solve.complete),
+            );
+            session::record(Event::Estimate {
+                board: name.clone(),
+                algorithm,
+                ops: solve.ops,
+                cycles: solve.ops.cycles(),
+                estimate_us: estimated.as_micros(),
+                sram: solve.sram,
+                avr_us: avr.map(|avr| avr.as_micros()),
+            });
+            let total = totals.entry((sudoku.dif.clone(), a)).or_default();
+            total.boards += 1;
+            total.ops += solve.ops;
+            total.sram = total.sram.max(solve.sram);
+            if let Some(avr) = avr {
+                total.compared += 1;
+                total.estimated += estimated;
+                total.avr += avr;
+            }
+        }
+    }
+
+    let mut difficulty = None;
+    for ((dif, a), total) in &totals {
+        if difficulty != Some(dif) {
+            println!(
+                "{}",
+                format!("{}: mean of {} boards", dif, total.boards)
+                    .white()
+                    .bold()
+            );
+            print_header(compare);
+            difficulty = Some(dif);
+        }
+        // The tournament time and ratio only cover the boards with a tournament time
+        let avr = (total.compared > 0).then(|| {
+            (
+                total.avr / total.compared as u32,
+                ratio(total.avr, total.estimated),
+            )
+        });
+        print_row(
+            algorithms[*a],
+            &total.ops,
+            total.sram,
+            total.boards,
+            clock_mhz,
+            avr,
+            "".normal(),
+        );
+    }
+    println!(
+        "{}",
+        format!(
+            "Estimates at {} MHz, SRAM over the ATmega16's {} bytes in red (the most of a difficulty's boards)",
+            clock_mhz, SRAM_BYTES
+        )
+        .white()
+    );
+    Ok(())
+}
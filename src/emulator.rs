@@ -0,0 +1,435 @@
+use log::{debug, info};
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::bulk;
+use crate::firmware;
+use crate::grid::{self, Shape};
+use crate::killer::Cage;
+use crate::link;
+use crate::variant::Variant;
+use crate::{Port, Result, DONE, OK};
+
+// Device name opening the emulator instead of a real port, "emu:<size>" for other board sizes
+pub const DEVICE: &str = "emu";
+// Name the emulator reports to I
+const FIRMWARE: &str = "ace411_sudoku emulator";
+// Commands the emulator supports, reported to I
+const COMMANDS: &[&str] = &[
+    "AT", "C", "P", "S", "T", "B", "D", "K", "R", "N", "W", "Q", "F", "I", "U",
+];
+// Node budget the emulated AVR spends on solving a board
+const SOLVE_BUDGET: u64 = 1_000_000;
+
+// Software stand-in for the AVR side of the protocol
+// Stores the downloaded board and cages, solves on P and answers readbacks
+// Clones share their state, like clones of a real port
+#[derive(Clone)]
+pub struct Emulator {
+    name: String,
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    shape: Shape,
+    /* Row major values, 0 for empty Cells */
+    board: Vec<u8>,
+    /* Rules of the board, see Emulator::set_variant() */
+    variant: Variant,
+    cages: Vec<Cage>,
+    /* Bytes written by the PC that do not form a complete frame yet */
+    input: Vec<u8>,
+    /* Bytes sent by the emulated AVR, waiting to be read by the PC */
+    output: VecDeque<u8>,
+    /* Frames left of an S or R readback, one is sent for every T */
+    readback: VecDeque<Vec<u8>>,
+    baud_rate: u32,
+    data_bits: DataBits,
+    flow_control: FlowControl,
+    parity: Parity,
+    stop_bits: StopBits,
+    timeout: Duration,
+    /* Framed mode once F1 arrived, see link::Link */
+    link: Option<EmuLink>,
+}
+
+// Sequence numbers of the emulator's side of a framed link
+#[derive(Default)]
+struct EmuLink {
+    tx_seq: u8,
+    rx_seq: Option<u8>,
+}
+
+// Opens the emulator if dev names it ("emu" for 9x9 boards, "emu:<size>" otherwise)
+// returns None for every other device name
+pub fn open(dev: &str, baud_rate: u32) -> Option<Result<Port>> {
+    let size = match dev.strip_prefix(DEVICE)? {
+        "" => Ok(9),
+        rest => rest
+            .strip_prefix(':')
+            .and_then(|size| size.parse::<usize>().ok())
+            .ok_or_else(|| crate::Error::Parse(format!("Invalid emulator device '{}'", dev))),
+    };
+    Some(size.and_then(Shape::from_size).map(|shape| {
+        info!("Using the AVR emulator for {} boards", shape);
+        let emulator = Emulator::new(dev, shape, baud_rate);
+        let mut port = Port::new(Box::new(emulator.clone()));
+        port.set_emulator(Some(emulator));
+        port
+    }))
+}
+
+impl Emulator {
+    pub fn new(name: &str, shape: Shape, baud_rate: u32) -> Self {
+        Emulator {
+            name: String::from(name),
+            state: Arc::new(Mutex::new(State {
+                shape,
+                board: vec![0; shape.cells()],
+                variant: Variant::Classic,
+                cages: Vec::new(),
+                input: Vec::new(),
+                output: VecDeque::new(),
+                readback: VecDeque::new(),
+                baud_rate,
+                data_bits: DataBits::Eight,
+                flow_control: FlowControl::None,
+                parity: Parity::None,
+                stop_bits: StopBits::One,
+                timeout: Duration::from_millis(0),
+                link: None,
+            })),
+        }
+    }
+
+    // Tells the emulator the rules of the board being downloaded, the protocol has no frame
+    // for them (cages excepted, they still come as K frames)
+    pub fn set_variant(&self, variant: &Variant) {
+        self.state().variant = variant.clone();
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl State {
+    // Sends a frame (including its <CR><LF>), wrapped in framed mode
+    fn reply(&mut self, data: &[u8]) {
+        match self.link.as_mut() {
+            Some(emu_link) => {
+                let frame = link::wrap(emu_link.tx_seq, &data[..data.len() - 2]);
+                emu_link.tx_seq = (emu_link.tx_seq + 1) % 16;
+                self.output.extend(frame);
+            }
+            None => self.output.extend(data.iter().copied()),
+        }
+    }
+
+    // Unwraps a frame in framed mode: ACKs it and handles its payload, NAKs it on a bad CRC
+    // ACKs and NAKs of the PC are dropped, the emulator's frames always arrive intact
+    fn handle_framed(&mut self, frame: &[u8]) {
+        if matches!(frame.first(), Some(b'+') | Some(b'-')) {
+            return;
+        }
+        let emu_link = match self.link.as_mut() {
+            Some(emu_link) => emu_link,
+            None => return self.handle(frame),
+        };
+        match link::unwrap(frame) {
+            Some((seq, payload)) => {
+                self.output.extend(link::ack(seq));
+                if emu_link.rx_seq == Some(seq) {
+                    debug!("Emulator dropped a repeated frame");
+                    return;
+                }
+                emu_link.rx_seq = Some(seq);
+                let line: Vec<u8> = payload.iter().chain(b"\r\n").copied().collect();
+                self.handle(&line);
+            }
+            None => {
+                let seq = frame
+                    .get(1)
+                    .and_then(|&s| (s as char).to_digit(16))
+                    .unwrap_or(0) as u8;
+                self.output.extend(link::nak(seq));
+            }
+        }
+    }
+
+    // Sends the next frame of a readback, D once there are none left
+    fn next_readback(&mut self) {
+        match self.readback.pop_front() {
+            Some(frame) => self.reply(&frame),
+            None => self.reply(DONE),
+        }
+    }
+
+    // Sends a progress report with the Cells filled so far, the emulator never backtracks visibly
+    fn report_progress(&mut self) {
+        let filled = self.board.iter().filter(|&&v| v != 0).count();
+        self.reply(format!("U{};0\r\n", filled).as_bytes());
+    }
+
+    // Fills the board using the stored Cells, under the variant set and the cages received
+    fn solve(&mut self) {
+        let variant = match (&self.variant, self.cages.is_empty()) {
+            (_, false) => Variant::Killer {
+                cages: self.cages.clone(),
+            },
+            (Variant::Killer { .. }, true) => Variant::Classic,
+            (variant, true) => variant.clone(),
+        };
+        match grid::count_with_budget(self.shape, &variant, &self.board, 1, SOLVE_BUDGET) {
+            Ok(grid::Count {
+                first: Some(solution),
+                ..
+            }) => self.board = solution,
+            _ => debug!("Emulator could not solve the board"),
+        }
+    }
+
+    // Handles one complete frame, including its <CR><LF>
+    fn handle(&mut self, frame: &[u8]) {
+        let size = self.shape.size();
+        debug!(
+            "Emulator got {}",
+            String::from_utf8_lossy(frame).escape_debug()
+        );
+
+        match &frame[..frame.len() - 2] {
+            b"AT" | b"B" => self.reply(OK),
+            b"C" => {
+                self.board.iter_mut().for_each(|v| *v = 0);
+                self.cages.clear();
+                self.reply(OK);
+            }
+            b"P" => {
+                self.reply(OK);
+                self.report_progress();
+                self.solve();
+                self.report_progress();
+                self.reply(DONE);
+            }
+            b"S" => {
+                self.readback = (0..self.board.len())
+                    .map(|c| crate::cell_frame(b'N', c % size, c / size, self.board[c]).to_vec())
+                    .collect();
+                self.next_readback();
+            }
+            b"R" => {
+                self.readback = self
+                    .cages
+                    .iter()
+                    .map(|cage| crate::cage_frame(cage, size))
+                    .collect();
+                self.next_readback();
+            }
+            b"T" => self.next_readback(),
+            b"W?" => self.reply(OK),
+            b"I" => {
+                let info = format!(
+                    "I{};{};{}\r\n",
+                    FIRMWARE,
+                    firmware::PROTOCOL_VERSION,
+                    COMMANDS.join(",")
+                );
+                self.reply(info.as_bytes());
+            }
+            b"F1" => {
+                self.reply(OK);
+                self.link = Some(EmuLink::default());
+            }
+            b"Q" => {
+                let frame = bulk::frame(b'Q', &self.board);
+                self.reply(&frame);
+            }
+            [b'W', ..] => match bulk::parse_frame(b'W', frame, self.shape) {
+                Ok(values) => {
+                    self.board = values;
+                    self.reply(OK);
+                }
+                Err(e) => {
+                    debug!("Emulator rejected bulk frame: {}", e);
+                    self.reply(bulk::ERROR);
+                }
+            },
+            b"OK" => (),
+            [b'N', ..] => match crate::parse_cell_frame(frame, size) {
+                Ok((row, col, num)) => {
+                    self.board[row * size + col] = num;
+                    self.reply(OK);
+                }
+                Err(e) => debug!("Emulator ignored frame: {}", e),
+            },
+            [b'K', ..] => match crate::parse_cage_frame(frame, size) {
+                Ok(cage) => {
+                    self.cages.push(cage);
+                    self.reply(OK);
+                }
+                Err(e) => debug!("Emulator ignored frame: {}", e),
+            },
+            &[b'D', x, y] => match (grid::decode_symbol(x), grid::decode_symbol(y)) {
+                (Some(x), Some(y))
+                    if x >= 1 && y >= 1 && x as usize <= size && y as usize <= size =>
+                {
+                    let (col, row) = (x as usize - 1, y as usize - 1);
+                    let value = self.board[row * size + col];
+                    self.reply(&crate::cell_frame(b'N', col, row, value));
+                }
+                _ => debug!("Emulator ignored an invalid D frame"),
+            },
+            _ => debug!("Emulator ignored an unknown frame"),
+        }
+    }
+}
+
+impl Read for Emulator {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.state();
+        if state.output.is_empty() {
+            return Err(io::Error::new(ErrorKind::TimedOut, "Operation timed out"));
+        }
+        let n = buf.len().min(state.output.len());
+        for (byte, out) in buf.iter_mut().zip(state.output.drain(..n)) {
+            *byte = out;
+        }
+        Ok(n)
+    }
+}
+
+impl Write for Emulator {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.state();
+        state.input.extend_from_slice(buf);
+        while let Some(end) = state.input.windows(2).position(|w| w == b"\r\n") {
+            let frame: Vec<u8> = state.input.drain(..end + 2).collect();
+            state.handle_framed(&frame);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SerialPort for Emulator {
+    fn name(&self) -> Option<String> {
+        Some(self.name.clone())
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        Ok(self.state().baud_rate)
+    }
+
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        Ok(self.state().data_bits)
+    }
+
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        Ok(self.state().flow_control)
+    }
+
+    fn parity(&self) -> serialport::Result<Parity> {
+        Ok(self.state().parity)
+    }
+
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        Ok(self.state().stop_bits)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.state().timeout
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+        self.state().baud_rate = baud_rate;
+        Ok(())
+    }
+
+    fn set_data_bits(&mut self, data_bits: DataBits) -> serialport::Result<()> {
+        self.state().data_bits = data_bits;
+        Ok(())
+    }
+
+    fn set_flow_control(&mut self, flow_control: FlowControl) -> serialport::Result<()> {
+        self.state().flow_control = flow_control;
+        Ok(())
+    }
+
+    fn set_parity(&mut self, parity: Parity) -> serialport::Result<()> {
+        self.state().parity = parity;
+        Ok(())
+    }
+
+    fn set_stop_bits(&mut self, stop_bits: StopBits) -> serialport::Result<()> {
+        self.state().stop_bits = stop_bits;
+        Ok(())
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.state().timeout = timeout;
+        Ok(())
+    }
+
+    fn write_request_to_send(&mut self, _level: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn write_data_terminal_ready(&mut self, _level: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        Ok(self.state().output.len() as u32)
+    }
+
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        Ok(0)
+    }
+
+    fn clear(&self, buffer_to_clear: ClearBuffer) -> serialport::Result<()> {
+        let mut state = self.state();
+        match buffer_to_clear {
+            ClearBuffer::Input => state.output.clear(),
+            ClearBuffer::Output => state.input.clear(),
+            ClearBuffer::All => {
+                state.output.clear();
+                state.input.clear();
+            }
+        }
+        Ok(())
+    }
+
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Ok(Box::new(self.clone()))
+    }
+
+    fn set_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn clear_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+use std::io;
+use thiserror::Error;
+
+// Library wide Result alias
+pub type Result<T> = std::result::Result<T, Error>;
+
+// Every failure the library can report
+// The binary wraps these in anyhow for context
+#[derive(Debug, Error)]
+pub enum Error {
+    // Filesystem or UART I/O failure
+    #[error("I/O error: {0}")]
+    Io(io::Error),
+
+    // Failure reported by the serialport crate (open, clear, configure)
+    #[error("Serial port error: {0}")]
+    Serial(#[from] serialport::Error),
+
+    // A board or record could not be serialized to JSON
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    // Malformed board file, board string or user supplied value
+    #[error("Parse error: {0}")]
+    Parse(String),
+
+    // The AVR replied with something other than what the protocol expects
+    #[error("Protocol error: expected \"{expected}\", got \"{got}\"")]
+    Protocol { expected: String, got: String },
+
+    // The device disappeared and was reopened, the command in flight was interrupted
+    #[error("The device was disconnected and reconnected, the command was interrupted")]
+    Reconnected,
+
+    // No reply arrived before the port timeout elapsed
+    #[error("Timed out waiting for the AVR")]
+    Timeout,
+
+    // The board has no solution or more than one
+    #[error("Board is not uniquely solvable")]
+    Unsolvable,
+
+    // The board received from the AVR does not match the solution
+    #[error("Invalid solution")]
+    InvalidSolution,
+
+    // The Killer cages read back from the AVR differ from the ones sent
+    #[error("Cages read back do not match the board")]
+    CageMismatch,
+
+    // Invalid shell command or a failed expectation in a script
+    #[error("Script error: {0}")]
+    Script(String),
+
+    // --dev auto found no port to use
+    #[error("No serial device found: {0}")]
+    NoDevice(String),
+
+    // Unreadable config file or unknown profile
+    #[error("Config error: {0}")]
+    Config(String),
+
+    // The simavr bridge could not be started or did not load the firmware
+    #[error("Simulator error: {0}")]
+    Simulator(String),
+}
+
+// A reconnected Port reports the interrupted operation as a ConnectionReset wrapping
+// Error::Reconnected (see port::Port), any other ConnectionReset stays an I/O error
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        if is_reconnected(&e) {
+            return Error::Reconnected;
+        }
+        Error::Io(e)
+    }
+}
+
+// Whether e is the marker a reconnected Port returns for the interrupted operation
+pub(crate) fn is_reconnected(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::ConnectionReset
+        && matches!(
+            e.get_ref().and_then(|e| e.downcast_ref::<Error>()),
+            Some(Error::Reconnected)
+        )
+}
+
+impl Error {
+    // Builds a Protocol error from raw bytes, escaping CR/LF for display
+    pub fn protocol(expected: &[u8], got: &[u8]) -> Self {
+        Error::Protocol {
+            expected: String::from_utf8_lossy(expected).escape_debug().to_string(),
+            got: String::from_utf8_lossy(got).escape_debug().to_string(),
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use log::{debug, warn};
+use serde::Serialize;
+use serialport::ClearBuffer;
+use std::fmt;
+use std::time::Duration;
+
+use crate::{read_line, send_uart, Error, Port, Result};
+
+// Asks the firmware who it is, see Firmware::parse()
+pub const INFO: &[u8] = b"I\r\n";
+// Protocol version of this host, newer firmware may use commands it does not know
+pub const PROTOCOL_VERSION: u32 = 1;
+// How long firmware without I gets to answer, it usually stays silent
+const INFO_TIMEOUT: Duration = Duration::from_millis(300);
+// Longest I reply read
+const MAX_INFO: usize = 128;
+
+// Identity of the firmware and the commands it supports, as it reports them
+#[derive(Debug, Clone, Serialize)]
+pub struct Firmware {
+    pub name: String,
+    pub version: u32,
+    /* Commands as in the protocol table (AT, C, P, D, K, W...) */
+    pub commands: Vec<String>,
+}
+
+impl Firmware {
+    // Parses an [I<NAME>;<VERSION>;<CMD>,<CMD>,...<CR><LF>] reply
+    pub fn parse(frame: &[u8]) -> Result<Self> {
+        let bad = || {
+            Error::Parse(format!(
+                "Invalid I reply {}",
+                String::from_utf8_lossy(frame).escape_debug()
+            ))
+        };
+        let line = std::str::from_utf8(frame)
+            .ok()
+            .and_then(|line| line.strip_prefix('I'))
+            .and_then(|line| line.strip_suffix("\r\n"))
+            .ok_or_else(bad)?;
+        let mut fields = line.split(';');
+        let (name, version, commands) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(name), Some(version), Some(commands)) if fields.next().is_none() => {
+                (name, version, commands)
+            }
+            _ => return Err(bad()),
+        };
+        Ok(Firmware {
+            name: name.trim().to_string(),
+            version: version.trim().parse().map_err(|_| bad())?,
+            commands: commands
+                .split(',')
+                .map(|command| command.trim().to_ascii_uppercase())
+                .filter(|command| !command.is_empty())
+                .collect(),
+        })
+    }
+
+    pub fn supports(&self, command: &str) -> bool {
+        self.commands.iter().any(|c| c == command)
+    }
+}
+
+impl fmt::Display for Firmware {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} protocol v{} ({})",
+            self.name,
+            self.version,
+            self.commands.join(" ")
+        )
+    }
+}
+
+// Asks the firmware for its identity with I
+// returns None for firmware without I, which ignores it (or answers something else)
+pub fn identify(port: &mut Port) -> Result<Option<Firmware>> {
+    let timeout = port.timeout();
+    port.set_timeout(INFO_TIMEOUT)?;
+    port.clear(ClearBuffer::All)?;
+    send_uart(port, INFO)?;
+    let answer = read_line(port, MAX_INFO);
+    port.set_timeout(timeout)?;
+    let firmware = match answer {
+        Ok(frame) => match Firmware::parse(&frame) {
+            Ok(firmware) => Some(firmware),
+            Err(e) => {
+                debug!("{}", e);
+                None
+            }
+        },
+        Err(Error::Timeout) | Err(Error::Protocol { .. }) => None,
+        Err(e) => return Err(e),
+    };
+    // Whatever firmware without I made of it must not be taken as the reply to the next command
+    port.clear(ClearBuffer::All)?;
+    if let Some(firmware) = &firmware {
+        if firmware.version > PROTOCOL_VERSION {
+            warn!(
+                "Firmware speaks protocol v{}, this version knows v{}",
+                firmware.version, PROTOCOL_VERSION
+            );
+        }
+    }
+    Ok(firmware)
+}
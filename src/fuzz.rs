@@ -0,0 +1,631 @@
+use colored::*;
+use log::{debug, info, warn};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{thread_rng, Rng, SeedableRng};
+use serialport::ClearBuffer;
+use std::fs;
+use std::io::{stdin, stdout, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::grid::{self, Shape};
+use crate::session::{self, Event};
+use crate::{bulk, firmware, link, Cage};
+use crate::{ensure_dir, read_uart, send_uart, Error, Port, Result};
+use crate::{AT, BREAK, CAGES, CLEAR, DONE, OK, PLAY, SAVE, T};
+
+// How long the replies to a step are collected for
+const REPLY_TIMEOUT: Duration = Duration::from_millis(200);
+// Most bytes collected as the reply to a step, firmware may keep talking forever
+const MAX_REPLY: usize = 4096;
+// AT sent by alive() before giving up, each one waiting the port timeout for OK
+const ALIVE_ATTEMPTS: usize = 3;
+// How long a reset AVR gets to answer AT again (bootloaders take a while)
+const RESET_TIMEOUT: Duration = Duration::from_secs(10);
+// How long DTR is held low to reset the AVR
+const DTR_PULSE: Duration = Duration::from_millis(100);
+// Replays minimise() may spend on a single hang
+const MINIMISE_TRIALS: usize = 64;
+// Most frames of a random sequence
+const MAX_RANDOM_STEPS: usize = 4;
+// Length of the overlong lines
+const LONG_LINE: usize = 300;
+
+// Settings of a fuzzing run
+#[derive(Debug, Clone)]
+pub struct FuzzConfig {
+    /* Board size the frames are built for */
+    pub shape: Shape,
+    /* Random sequences sent after the generated cases */
+    pub random: usize,
+    /* Seed of the random sequences, picked at random (and logged) if None */
+    pub seed: Option<u64>,
+    /* Reset a hung AVR by pulsing DTR instead of asking for the reset button */
+    pub dtr_reset: bool,
+    /* Directory the transcripts of hanging sequences are saved to */
+    pub dir: String,
+}
+
+// Input sequence sent to the firmware, one write per step
+#[derive(Debug, Clone)]
+pub struct Case {
+    pub name: String,
+    pub steps: Vec<Vec<u8>>,
+}
+
+impl Case {
+    fn new(name: impl Into<String>, steps: Vec<Vec<u8>>) -> Self {
+        Case {
+            name: name.into(),
+            steps,
+        }
+    }
+
+    fn bytes(&self) -> usize {
+        self.steps.iter().map(|step| step.len()).sum()
+    }
+
+    // Whether the case would switch the firmware to framed mode, which is not a hang
+    fn enables_link(&self) -> bool {
+        self.steps
+            .concat()
+            .windows(link::ENABLE.len())
+            .any(|w| w == link::ENABLE)
+    }
+}
+
+// Outcome of a fuzzing run
+#[derive(Debug, Default)]
+pub struct Report {
+    /* Cases sent */
+    pub cases: usize,
+    /* Transcripts of the cases that hung the firmware */
+    pub hangs: Vec<PathBuf>,
+}
+
+// Well formed frame of every command the PC sends, the starting point of the mutations
+// F1 is left out: framed mode is a different protocol, not a hang
+fn valid_frames(shape: Shape) -> Vec<(&'static str, Vec<u8>)> {
+    let size = shape.size();
+    let cage = Cage {
+        cells: vec![0, 1],
+        sum: 3,
+    };
+    vec![
+        ("at", AT.to_vec()),
+        ("clear", CLEAR.to_vec()),
+        ("play", PLAY.to_vec()),
+        ("save", SAVE.to_vec()),
+        ("next", T.to_vec()),
+        ("break", BREAK.to_vec()),
+        ("cages", CAGES.to_vec()),
+        ("info", firmware::INFO.to_vec()),
+        ("bulk-query", bulk::QUERY.to_vec()),
+        ("bulk-read", bulk::READ.to_vec()),
+        ("cell", crate::cell_frame(b'N', 0, 0, 1).to_vec()),
+        ("debug", crate::debug_frame(0, 0).to_vec()),
+        ("cage", crate::cage_frame(&cage, size)),
+        ("bulk-write", bulk::frame(b'W', &vec![0; shape.cells()])),
+    ]
+}
+
+// Symbols no coordinate or value should accept, plus the borders of the valid ones
+fn edge_symbols(shape: Shape) -> Vec<(String, u8)> {
+    let size = shape.size();
+    let beyond = match size < 16 {
+        true => grid::encode_symbol(size as u8 + 1),
+        false => b'H',
+    };
+    vec![
+        (String::from("0"), b'0'),
+        (format!("{}", size + 1), beyond),
+        (String::from("colon"), b':'),
+        (String::from("at-sign"), b'@'),
+        (String::from("space"), b' '),
+        (String::from("nul"), 0x00),
+        (String::from("ff"), 0xff),
+    ]
+}
+
+// Line endings and truncations of a single valid frame
+fn mutations(name: &str, frame: &[u8]) -> Vec<Case> {
+    let line = &frame[..frame.len() - 2];
+    let with = |end: &[u8]| vec![[line, end].concat()];
+    let mut cases = vec![
+        Case::new(format!("{}-no-eol", name), with(b"")),
+        Case::new(format!("{}-cr", name), with(b"\r")),
+        Case::new(format!("{}-lf", name), with(b"\n")),
+        Case::new(format!("{}-lf-cr", name), with(b"\n\r")),
+        Case::new(format!("{}-cr-cr-lf", name), with(b"\r\r\n")),
+        Case::new(format!("{}-trailing", name), with(b"x\r\n")),
+    ];
+    if line[0].is_ascii_uppercase() {
+        let lower: Vec<u8> = [&[line[0].to_ascii_lowercase()], &line[1..], b"\r\n"].concat();
+        cases.push(Case::new(format!("{}-lowercase", name), vec![lower]));
+    }
+    let mut cuts = vec![1, line.len() / 2, line.len() - 1];
+    cuts.dedup();
+    for cut in cuts.into_iter().filter(|&cut| cut > 0 && cut < line.len()) {
+        cases.push(Case::new(
+            format!("{}-truncated-{}", name, cut),
+            vec![[&line[..cut], b"\r\n"].concat()],
+        ));
+    }
+    cases
+}
+
+// Frames with a field out of range: coordinates, values, sums, lengths and checksums
+fn field_cases(shape: Shape) -> Vec<Case> {
+    let mut cases = Vec::new();
+    for (name, s) in edge_symbols(shape) {
+        cases.push(Case::new(
+            format!("cell-x-{}", name),
+            vec![vec![b'N', s, b'1', b'1', b'\r', b'\n']],
+        ));
+        cases.push(Case::new(
+            format!("cell-y-{}", name),
+            vec![vec![b'N', b'1', s, b'1', b'\r', b'\n']],
+        ));
+        if s != b'0' {
+            cases.push(Case::new(
+                format!("cell-value-{}", name),
+                vec![vec![b'N', b'1', b'1', s, b'\r', b'\n']],
+            ));
+        }
+        cases.push(Case::new(
+            format!("debug-x-{}", name),
+            vec![vec![b'D', s, b'1', b'\r', b'\n']],
+        ));
+        cases.push(Case::new(
+            format!("debug-y-{}", name),
+            vec![vec![b'D', b'1', s, b'\r', b'\n']],
+        ));
+        cases.push(Case::new(
+            format!("cage-cell-{}", name),
+            vec![vec![b'K', b'0', b'3', s, b'1', b'\r', b'\n']],
+        ));
+    }
+
+    let long_cage: Vec<u8> = [&b"K45"[..], &b"11".repeat(40), b"\r\n"].concat();
+    cases.extend([
+        Case::new("cage-sum-00", vec![b"K0011\r\n".to_vec()]),
+        Case::new("cage-sum-99", vec![b"K9911\r\n".to_vec()]),
+        Case::new("cage-sum-letters", vec![b"Kxx11\r\n".to_vec()]),
+        Case::new("cage-no-cells", vec![b"K03\r\n".to_vec()]),
+        Case::new("cage-odd-length", vec![b"K03112\r\n".to_vec()]),
+        Case::new("cage-40-cells", vec![long_cage]),
+    ]);
+
+    let empty = vec![0; shape.cells()];
+    let valid = bulk::frame(b'W', &empty);
+    let symbols = &valid[1..=shape.cells()];
+    let bulk_with = |symbols: &[u8], checksum: u8| {
+        [
+            &b"W"[..],
+            symbols,
+            format!("{:02X}\r\n", checksum).as_bytes(),
+        ]
+        .concat()
+    };
+    let short = &symbols[..symbols.len() - 1];
+    let long: Vec<u8> = [symbols, b"0"].concat();
+    let mut out_of_range = symbols.to_vec();
+    out_of_range[0] = b'Z';
+    cases.extend([
+        Case::new(
+            "bulk-write-short",
+            vec![bulk_with(short, bulk::checksum(short))],
+        ),
+        Case::new(
+            "bulk-write-long",
+            vec![bulk_with(&long, bulk::checksum(&long))],
+        ),
+        Case::new(
+            "bulk-write-bad-checksum",
+            vec![bulk_with(symbols, bulk::checksum(symbols).wrapping_add(1))],
+        ),
+        Case::new(
+            "bulk-write-bad-symbol",
+            vec![bulk_with(&out_of_range, bulk::checksum(&out_of_range))],
+        ),
+        Case::new(
+            "bulk-write-lowercase-checksum",
+            vec![[&valid[..valid.len() - 4], b"0a\r\n"].concat()],
+        ),
+    ]);
+    cases
+}
+
+// Lines no command starts with: empty, overlong, binary, replies sent the wrong way
+fn line_cases() -> Vec<Case> {
+    let long_line: Vec<u8> = [&vec![b'A'; LONG_LINE][..], b"\r\n"].concat();
+    vec![
+        Case::new("empty-line", vec![b"\r\n".to_vec()]),
+        Case::new("empty-lines", vec![b"\r\n".repeat(10)]),
+        Case::new("long-line", vec![long_line]),
+        Case::new("long-no-eol", vec![vec![b'A'; LONG_LINE]]),
+        Case::new("all-bytes", vec![(0..=255).collect()]),
+        Case::new("nul", vec![vec![0x00]]),
+        Case::new("ff", vec![vec![0xff]]),
+        Case::new("pc-ok", vec![OK.to_vec()]),
+        Case::new("pc-done", vec![DONE.to_vec()]),
+        Case::new("pc-error", vec![bulk::ERROR.to_vec()]),
+        Case::new("pc-progress", vec![b"U1;0\r\n".to_vec()]),
+        Case::new("pc-ack", vec![link::ack(0).to_vec()]),
+        Case::new("unasked-frame", vec![link::wrap(0, b"AT")]),
+        Case::new("framed-off", vec![b"F0\r\n".to_vec()]),
+        Case::new("framed-2", vec![b"F2\r\n".to_vec()]),
+        Case::new("framed-no-mode", vec![b"F\r\n".to_vec()]),
+    ]
+}
+
+// Valid frames at the wrong time, e.g. a stray byte while solving
+fn sequence_cases() -> Vec<Case> {
+    let cell = crate::cell_frame(b'N', 0, 0, 1).to_vec();
+    let steps = |frames: &[&[u8]]| frames.iter().map(|f| f.to_vec()).collect();
+    vec![
+        Case::new("stray-byte-during-play", steps(&[CLEAR, PLAY, b"x"])),
+        Case::new("at-during-play", steps(&[CLEAR, PLAY, AT])),
+        Case::new("play-twice", steps(&[CLEAR, PLAY, PLAY])),
+        Case::new("cell-during-play", steps(&[CLEAR, PLAY, &cell])),
+        Case::new("clear-during-play", steps(&[CLEAR, PLAY, CLEAR])),
+        Case::new("next-without-save", steps(&[CLEAR, T, T])),
+        Case::new("abandoned-readback", steps(&[SAVE, CLEAR])),
+        Case::new("cell-during-readback", steps(&[SAVE, T, &cell])),
+        Case::new("readback-past-done", steps(&[CLEAR, CAGES, T, T, T])),
+        Case::new("same-cell-twice", steps(&[&cell, &cell])),
+        Case::new("split-frame", steps(&[b"N1", b"11\r\n"])),
+        Case::new("partial-then-at", steps(&[b"N1", AT])),
+    ]
+}
+
+// Every generated case: mutations of each valid frame, out of range fields, odd lines and sequences
+pub fn generate(shape: Shape) -> Vec<Case> {
+    let mut cases: Vec<Case> = valid_frames(shape)
+        .iter()
+        .flat_map(|(name, frame)| mutations(name, frame))
+        .collect();
+    cases.extend(field_cases(shape));
+    cases.extend(line_cases());
+    cases.extend(sequence_cases());
+    cases.retain(|case| !case.enables_link());
+    cases
+}
+
+// Random sequences of up to MAX_RANDOM_STEPS frames from the generated ones and the valid ones,
+// every other one with a random byte changed
+pub fn random(shape: Shape, n: usize, seed: u64) -> Vec<Case> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let pool: Vec<Vec<u8>> = valid_frames(shape)
+        .into_iter()
+        .map(|(_, frame)| frame)
+        .chain(generate(shape).into_iter().flat_map(|case| case.steps))
+        .collect();
+    let mut cases = Vec::new();
+    while cases.len() < n {
+        let len = rng.gen_range(1..=MAX_RANDOM_STEPS);
+        let mut steps: Vec<Vec<u8>> = pool.choose_multiple(&mut rng, len).cloned().collect();
+        if rng.gen_bool(0.5) {
+            let step = rng.gen_range(0..steps.len());
+            let byte = rng.gen_range(0..steps[step].len());
+            steps[step][byte] = rng.gen();
+        }
+        let case = Case::new(format!("random-{}-{}", seed, cases.len()), steps);
+        if !case.enables_link() {
+            cases.push(case);
+        }
+    }
+    cases
+}
+
+// Reads whatever the firmware sends until it stays quiet for REPLY_TIMEOUT
+fn collect(port: &mut Port) -> Result<Vec<u8>> {
+    let timeout = port.timeout();
+    port.set_timeout(REPLY_TIMEOUT)?;
+    let mut reply = Vec::new();
+    let result = loop {
+        if reply.len() >= MAX_REPLY {
+            break Ok(());
+        }
+        match read_uart(port, 256) {
+            Ok(data) => reply.extend(data),
+            Err(Error::Timeout) => break Ok(()),
+            Err(e) => break Err(e),
+        }
+    };
+    port.set_timeout(timeout)?;
+    result.map(|_| reply)
+}
+
+// Sends every step of case, returning each step with what the firmware answered to it
+fn send_case(port: &mut Port, case: &Case) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let mut exchange = Vec::new();
+    for step in &case.steps {
+        send_uart(port, step)?;
+        exchange.push((step.clone(), collect(port)?));
+    }
+    Ok(exchange)
+}
+
+// Whether the firmware still answers AT with OK
+// Ends whatever line the case left unfinished first, replies still on their way (e.g. D) are skipped
+pub fn alive(port: &mut Port) -> Result<bool> {
+    send_uart(port, b"\r\n")?;
+    collect(port)?;
+    for attempt in 1..=ALIVE_ATTEMPTS {
+        send_uart(port, AT)?;
+        let mut reply = Vec::new();
+        loop {
+            match read_uart(port, 1) {
+                Ok(byte) => reply.extend(byte),
+                Err(Error::Timeout) => break,
+                Err(e) => return Err(e),
+            }
+            if reply.ends_with(OK) {
+                return Ok(true);
+            }
+            if reply.len() >= MAX_REPLY {
+                break;
+            }
+        }
+        debug!(
+            "No OK to AT ({}/{}), got {}",
+            attempt,
+            ALIVE_ATTEMPTS,
+            escape(&reply)
+        );
+    }
+    Ok(false)
+}
+
+// Gets a hung AVR back: pulses DTR (boards resetting on it, like Arduinos) or asks for the reset button,
+// then waits for it to answer AT again
+fn reset(port: &mut Port, dtr: bool) -> Result<()> {
+    if dtr {
+        port.write_data_terminal_ready(false)?;
+        thread::sleep(DTR_PULSE);
+        port.write_data_terminal_ready(true)?;
+    } else {
+        info!("Reset the AVR and press Enter");
+        stdin().read_line(&mut String::new())?;
+    }
+    let started = Instant::now();
+    while started.elapsed() < RESET_TIMEOUT {
+        if alive(port)? {
+            port.clear(ClearBuffer::All)?;
+            return Ok(());
+        }
+    }
+    Err(Error::Protocol {
+        expected: String::from("OK to AT after a reset"),
+        got: String::from("no answer"),
+    })
+}
+
+// Replays candidates of a hanging case, resetting the AVR after each one that hangs it
+struct Minimiser<'a> {
+    port: &'a mut Port,
+    dtr: bool,
+    trials: usize,
+}
+
+impl Minimiser<'_> {
+    // Whether steps hang the firmware, false once MINIMISE_TRIALS are spent
+    fn hangs(&mut self, steps: &[Vec<u8>]) -> Result<bool> {
+        if self.trials >= MINIMISE_TRIALS {
+            return Ok(false);
+        }
+        self.trials += 1;
+        hangs(self.port, steps, self.dtr)
+    }
+}
+
+// Whether steps hang the firmware, resetting it if they do
+fn hangs(port: &mut Port, steps: &[Vec<u8>], dtr: bool) -> Result<bool> {
+    send_case(port, &Case::new("candidate", steps.to_vec()))?;
+    let hung = !alive(port)?;
+    if hung {
+        reset(port, dtr)?;
+    }
+    Ok(hung)
+}
+
+// Delta debugging: drops chunks of items, halving their size, while what is left still hangs
+fn ddmin<I: Clone>(
+    mut items: Vec<I>,
+    mut hangs: impl FnMut(&[I]) -> Result<bool>,
+) -> Result<Vec<I>> {
+    let mut chunk = items.len() / 2;
+    while chunk > 0 {
+        let mut start = 0;
+        let mut dropped = false;
+        while start < items.len() {
+            let end = (start + chunk).min(items.len());
+            let candidate: Vec<I> = [&items[..start], &items[end..]].concat();
+            if !candidate.is_empty() && hangs(&candidate)? {
+                items = candidate;
+                dropped = true;
+            } else {
+                start = end;
+            }
+        }
+        if !dropped {
+            chunk /= 2;
+        }
+    }
+    Ok(items)
+}
+
+// Shrinks a hanging case: drops steps first, then bytes of the steps left
+// The firmware must be answering AT again (see reset()) when it is called
+fn minimise(port: &mut Port, case: &Case, dtr: bool) -> Result<Case> {
+    let mut minimiser = Minimiser {
+        port,
+        dtr,
+        trials: 0,
+    };
+    let mut steps = ddmin(case.steps.clone(), |steps| minimiser.hangs(steps))?;
+    for i in 0..steps.len() {
+        let bytes = ddmin(steps[i].clone(), |bytes| {
+            let mut candidate = steps.clone();
+            candidate[i] = bytes.to_vec();
+            minimiser.hangs(&candidate)
+        })?;
+        steps[i] = bytes;
+    }
+    debug!("Minimised {} in {} replays", case.name, minimiser.trials);
+    Ok(Case::new(case.name.clone(), steps))
+}
+
+// Bytes as text: printable ASCII as is, \r, \n, \\ and \xHH for the rest
+pub fn escape(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| match b {
+            b'\r' => String::from("\\r"),
+            b'\n' => String::from("\\n"),
+            b'\\' => String::from("\\\\"),
+            b' '..=b'~' => (b as char).to_string(),
+            _ => format!("\\x{:02X}", b),
+        })
+        .collect()
+}
+
+// Inverse of escape()
+pub fn unescape(text: &str) -> Result<Vec<u8>> {
+    let bad = || Error::Parse(format!("Invalid escape in '{}'", text));
+    let mut bytes = Vec::new();
+    let mut chars = text.bytes();
+    while let Some(b) = chars.next() {
+        if b != b'\\' {
+            bytes.push(b);
+            continue;
+        }
+        bytes.push(match chars.next().ok_or_else(bad)? {
+            b'r' => b'\r',
+            b'n' => b'\n',
+            b'\\' => b'\\',
+            b'x' => {
+                let hex = [chars.next().ok_or_else(bad)?, chars.next().ok_or_else(bad)?];
+                std::str::from_utf8(&hex)
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(bad)?
+            }
+            _ => return Err(bad()),
+        });
+    }
+    Ok(bytes)
+}
+
+// Transcript of a hanging case: a "> " line per step sent, a "< " line with the reply to it
+// Lines starting with # are comments, only the "> " lines are replayed
+pub fn transcript(case: &Case, exchange: &[(Vec<u8>, Vec<u8>)]) -> String {
+    let mut text = format!(
+        "# {} hangs the firmware: no OK to AT after it (\\r\\n to end an unfinished line, then AT)\n# Replay with: ace411_sudoku fuzz --replay <this file> --dev <dev>\n",
+        case.name
+    );
+    for (sent, reply) in exchange {
+        text.push_str(&format!("> {}\n< {}\n", escape(sent), escape(reply)));
+    }
+    text
+}
+
+// Reads a transcript written by transcript() (or by hand) back into a case
+pub fn load(path: &Path) -> Result<Case> {
+    let text = fs::read_to_string(path)?;
+    let steps = text
+        .lines()
+        .filter_map(|line| line.strip_prefix("> "))
+        .map(unescape)
+        .collect::<Result<Vec<Vec<u8>>>>()?;
+    if steps.is_empty() {
+        return Err(Error::Parse(format!("{} has no steps", path.display())));
+    }
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    Ok(Case::new(name, steps))
+}
+
+// Sends a case to firmware that answers AT, returns whether it hangs it
+pub fn replay(port: &mut Port, case: &Case) -> Result<bool> {
+    if !alive(port)? {
+        return Err(Error::Timeout);
+    }
+    send_case(port, case)?;
+    Ok(!alive(port)?)
+}
+
+// Sends every generated case and config.random random ones, checking AT after each
+// Hanging cases are minimised and saved as transcripts to config.dir, the AVR is reset to carry on
+pub fn run(port: &mut Port, config: &FuzzConfig) -> Result<Report> {
+    let seed = config.seed.unwrap_or_else(|| thread_rng().gen());
+    info!("Random sequences use seed {}", seed);
+    let mut cases = generate(config.shape);
+    cases.extend(random(config.shape, config.random, seed));
+
+    if !alive(port)? {
+        return Err(Error::Protocol {
+            expected: String::from("OK to AT before fuzzing"),
+            got: String::from("no answer"),
+        });
+    }
+    ensure_dir(&config.dir)?;
+
+    let mut report = Report::default();
+    for (i, case) in cases.iter().enumerate() {
+        print!("\r{}", format!("Fuzzing: {}/{}", i + 1, cases.len()).cyan());
+        let _ = stdout().flush();
+        debug!("Case {}: {}", case.name, escape(&case.steps.concat()));
+        report.cases += 1;
+        send_case(port, case)?;
+        if alive(port)? {
+            continue;
+        }
+
+        println!();
+        warn!(
+            "{}",
+            format!("{} hangs the firmware", case.name).red().bold()
+        );
+        reset(port, config.dtr_reset)?;
+        let minimal = match hangs(port, &case.steps, config.dtr_reset)? {
+            true => minimise(port, case, config.dtr_reset)?,
+            false => {
+                warn!("It did not hang again after a reset, it may depend on the cases before it");
+                case.clone()
+            }
+        };
+
+        // Once more to record what the firmware answers to the minimal case
+        let exchange = send_case(port, &minimal)?;
+        if !alive(port)? {
+            reset(port, config.dtr_reset)?;
+        }
+        let path = Path::new(&config.dir).join(format!(
+            "hang_{}_{}.txt",
+            report.hangs.len() + 1,
+            case.name
+        ));
+        fs::write(&path, transcript(&minimal, &exchange))?;
+        info!(
+            "Minimised to {} steps ({} bytes), saved to {}",
+            minimal.steps.len(),
+            minimal.bytes(),
+            path.display()
+        );
+        session::record(Event::Hang {
+            case: case.name.clone(),
+            steps: minimal.steps.len(),
+            bytes: minimal.bytes(),
+            transcript: path.display().to_string(),
+        });
+        report.hangs.push(path);
+    }
+    println!();
+    Ok(report)
+}
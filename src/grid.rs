@@ -0,0 +1,428 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::killer::{self, Cage};
+use crate::variant::Variant;
+use crate::{Error, Result};
+
+// Symbols used on the wire and in board files
+// Index is the value: 0 is an empty Cell, 1-9 are digits, 10-16 are A-G
+// For 9x9 boards this is exactly the classic '0'..'9' encoding
+const SYMBOLS: &[u8; 17] = b"0123456789ABCDEFG";
+
+// Node budget for a single uniqueness check while minimizing large boards
+// Checks that run out of budget are treated as "not unique"
+const MINIMIZE_BUDGET: u64 = 5_000;
+
+// Dimensions of a board: box_rows x box_cols boxes, (box_rows * box_cols)^2 Cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Shape {
+    pub box_rows: usize,
+    pub box_cols: usize,
+}
+
+impl Default for Shape {
+    fn default() -> Self {
+        Shape::CLASSIC
+    }
+}
+
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}", self.size(), self.size())
+    }
+}
+
+impl Shape {
+    // The classic 9x9 board with 3x3 boxes
+    pub const CLASSIC: Shape = Shape {
+        box_rows: 3,
+        box_cols: 3,
+    };
+
+    // Returns the shape for a side length
+    // Supported: 4 (2x2 boxes), 6 (2x3 boxes), 9 (3x3 boxes), 16 (4x4 boxes)
+    pub fn from_size(size: usize) -> Result<Self> {
+        let (box_rows, box_cols) = match size {
+            4 => (2, 2),
+            6 => (2, 3),
+            9 => (3, 3),
+            16 => (4, 4),
+            _ => return Err(Error::Parse(format!("Unsupported board size {}", size))),
+        };
+        Ok(Shape { box_rows, box_cols })
+    }
+
+    // Returns the shape for a board with the given number of Cells
+    pub fn from_cells(cells: usize) -> Result<Self> {
+        match [4, 6, 9, 16].iter().find(|&&size| size * size == cells) {
+            Some(&size) => Shape::from_size(size),
+            None => Err(Error::Parse(format!("A board cannot have {} Cells", cells))),
+        }
+    }
+
+    // Side length of the board (also the largest value)
+    pub fn size(&self) -> usize {
+        self.box_rows * self.box_cols
+    }
+
+    // Total number of Cells
+    pub fn cells(&self) -> usize {
+        self.size() * self.size()
+    }
+
+    // Index of the box containing row, col
+    pub fn box_of(&self, row: usize, col: usize) -> usize {
+        (row / self.box_rows) * self.box_rows + col / self.box_cols
+    }
+
+    // Every row and column as a list of Cell indices
+    pub fn lines(&self) -> Vec<Vec<usize>> {
+        let n = self.size();
+        let mut lines: Vec<Vec<usize>> = Vec::with_capacity(2 * n);
+
+        for row in 0..n {
+            lines.push((0..n).map(|col| row * n + col).collect());
+        }
+        for col in 0..n {
+            lines.push((0..n).map(|row| row * n + col).collect());
+        }
+        lines
+    }
+
+    // Every box as a list of Cell indices
+    pub fn boxes(&self) -> Vec<Vec<usize>> {
+        let n = self.size();
+        let mut boxes: Vec<Vec<usize>> = vec![Vec::with_capacity(n); n];
+        for row in 0..n {
+            for col in 0..n {
+                boxes[self.box_of(row, col)].push(row * n + col);
+            }
+        }
+        boxes
+    }
+}
+
+// Encodes a value (0 for empty) as its ASCII symbol
+pub fn encode_symbol(value: u8) -> u8 {
+    SYMBOLS[value as usize]
+}
+
+// Decodes an ASCII symbol into a value
+// '.' and '_' are accepted as empty Cells, letters are case insensitive
+pub fn decode_symbol(byte: u8) -> Option<u8> {
+    match byte {
+        b'.' | b'_' => Some(0),
+        _ => SYMBOLS
+            .iter()
+            .position(|&s| s == byte.to_ascii_uppercase())
+            .map(|v| v as u8),
+    }
+}
+
+// Parses a board string (one symbol per Cell) into its shape and values
+pub fn parse_line(line: &str) -> Result<(Shape, Vec<u8>)> {
+    let line = line.trim();
+    let shape = Shape::from_cells(line.len())?;
+    let values = line
+        .bytes()
+        .map(|b| match decode_symbol(b) {
+            Some(v) if (v as usize) <= shape.size() => Ok(v),
+            _ => Err(Error::Parse(format!(
+                "Invalid symbol '{}' for a {} board",
+                b as char, shape
+            ))),
+        })
+        .collect::<Result<Vec<u8>>>()?;
+    Ok((shape, values))
+}
+
+// Result of a solution count
+pub struct Count {
+    // Number of solutions found, stops at the requested limit
+    pub solutions: usize,
+    // The first solution found
+    pub first: Option<Vec<u8>>,
+    // The last solution found, differs from first if there is more than one
+    pub last: Option<Vec<u8>>,
+    // False if the search ran out of its node budget
+    pub complete: bool,
+}
+
+// Backtracking search over an arbitrary set of houses and Killer cages
+// Picks the Cell with the fewest candidates first
+struct Search<'a, R: Rng> {
+    size: usize,
+    houses_of: &'a [Vec<usize>],
+    cages: &'a [Cage],
+    cage_of: Vec<Option<usize>>,
+    used: Vec<u32>,
+    grid: Vec<u8>,
+    limit: usize,
+    budget: u64,
+    nodes: u64,
+    count: Count,
+    rng: Option<&'a mut R>,
+}
+
+impl<'a, R: Rng> Search<'a, R> {
+    fn new(
+        shape: Shape,
+        houses: &[Vec<usize>],
+        houses_of: &'a [Vec<usize>],
+        cages: &'a [Cage],
+        grid: &[u8],
+    ) -> Result<Self> {
+        let mut used = vec![0u32; houses.len()];
+        for (h, house) in houses.iter().enumerate() {
+            for &cell in house {
+                let value = grid[cell];
+                if value == 0 {
+                    continue;
+                }
+                let bit = 1 << (value - 1);
+                if used[h] & bit != 0 {
+                    return Err(Error::Unsolvable);
+                }
+                used[h] |= bit;
+            }
+        }
+
+        let mut cage_of = vec![None; grid.len()];
+        for (i, cage) in cages.iter().enumerate() {
+            for &cell in &cage.cells {
+                cage_of[cell] = Some(i);
+            }
+        }
+
+        Ok(Search {
+            size: shape.size(),
+            houses_of,
+            cages,
+            cage_of,
+            used,
+            grid: grid.to_vec(),
+            limit: 1,
+            budget: u64::MAX,
+            nodes: 0,
+            count: Count {
+                solutions: 0,
+                first: None,
+                last: None,
+                complete: true,
+            },
+            rng: None,
+        })
+    }
+
+    fn candidates(&self, cell: usize) -> u32 {
+        let taken = self.houses_of[cell]
+            .iter()
+            .fold(0, |acc, &h| acc | self.used[h]);
+        let free = ((1u32 << self.size) - 1) & !taken;
+
+        match self.cage_of[cell] {
+            Some(i) => {
+                let (mut used, mut empty, mut left) = (0u32, 0, self.cages[i].sum);
+                for &c in &self.cages[i].cells {
+                    match self.grid[c] {
+                        0 => empty += 1,
+                        v => {
+                            used |= 1 << (v - 1);
+                            left = left.saturating_sub(v as u32);
+                        }
+                    }
+                }
+                let avail = ((1u32 << self.size) - 1) & !used;
+                free & killer::sum_mask(avail, empty, left)
+            }
+            None => free,
+        }
+    }
+
+    fn set(&mut self, cell: usize, value: u8) {
+        let bit = 1 << (value - 1);
+        for &h in &self.houses_of[cell] {
+            self.used[h] ^= bit;
+        }
+        self.grid[cell] = value;
+    }
+
+    fn unset(&mut self, cell: usize, value: u8) {
+        let bit = 1 << (value - 1);
+        for &h in &self.houses_of[cell] {
+            self.used[h] ^= bit;
+        }
+        self.grid[cell] = 0;
+    }
+
+    // returns true once the search should stop
+    fn run(&mut self) -> bool {
+        self.nodes += 1;
+        if self.nodes > self.budget {
+            self.count.complete = false;
+            return true;
+        }
+
+        // Most constrained Cell
+        let mut best: Option<(usize, u32)> = None;
+        for cell in 0..self.grid.len() {
+            if self.grid[cell] != 0 {
+                continue;
+            }
+            let cand = self.candidates(cell);
+            if best.is_none_or(|(_, b)| cand.count_ones() < b.count_ones()) {
+                best = Some((cell, cand));
+                if cand.count_ones() <= 1 {
+                    break;
+                }
+            }
+        }
+
+        let (cell, cand) = match best {
+            Some(best) => best,
+            None => {
+                self.count.solutions += 1;
+                if self.count.first.is_none() {
+                    self.count.first = Some(self.grid.clone());
+                }
+                self.count.last = Some(self.grid.clone());
+                return self.count.solutions >= self.limit;
+            }
+        };
+
+        let mut values: Vec<u8> = (1..=self.size as u8)
+            .filter(|v| cand & (1 << (v - 1)) != 0)
+            .collect();
+        if let Some(rng) = self.rng.as_mut() {
+            values.shuffle(*rng);
+        }
+
+        for value in values {
+            self.set(cell, value);
+            let stop = self.run();
+            self.unset(cell, value);
+            if stop {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+// Lists, for every Cell, the houses it belongs to
+fn houses_of(cells: usize, houses: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let mut of = vec![Vec::new(); cells];
+    for (h, house) in houses.iter().enumerate() {
+        for &cell in house {
+            of[cell].push(h);
+        }
+    }
+    of
+}
+
+// Returns true if grid is completely filled and breaks none of the variant's houses
+// Killer cages must also add up to their sums
+pub fn satisfies(shape: Shape, variant: &Variant, grid: &[u8]) -> bool {
+    grid.len() == shape.cells()
+        && variant
+            .cages()
+            .iter()
+            .all(|cage| cage.cells.iter().map(|&c| grid[c] as u32).sum::<u32>() == cage.sum)
+        && variant.houses(shape).iter().all(|house| {
+            let mut seen = 0u32;
+            house.iter().all(|&cell| {
+                let value = grid[cell];
+                if value == 0 || value as usize > shape.size() || seen & (1 << (value - 1)) != 0 {
+                    return false;
+                }
+                seen |= 1 << (value - 1);
+                true
+            })
+        })
+}
+
+// Counts the solutions of grid (row major, 0 for empty) up to limit
+pub fn count_solutions(
+    shape: Shape,
+    variant: &Variant,
+    grid: &[u8],
+    limit: usize,
+) -> Result<Count> {
+    count_with_budget(shape, variant, grid, limit, u64::MAX)
+}
+
+// Same as count_solutions() but gives up after budget search nodes (Count::complete is false)
+pub(crate) fn count_with_budget(
+    shape: Shape,
+    variant: &Variant,
+    grid: &[u8],
+    limit: usize,
+    budget: u64,
+) -> Result<Count> {
+    let houses = variant.houses(shape);
+    let of = houses_of(shape.cells(), &houses);
+    let mut search: Search<rand::rngs::ThreadRng> =
+        Search::new(shape, &houses, &of, variant.cages(), grid)?;
+    search.limit = limit;
+    search.budget = budget;
+    search.run();
+    Ok(search.count)
+}
+
+// Returns the unique solution of grid
+// Err(Unsolvable) if there is none or more than one
+pub fn solve_unique(shape: Shape, variant: &Variant, grid: &[u8]) -> Result<Vec<u8>> {
+    let count = count_solutions(shape, variant, grid, 2)?;
+    match (count.solutions, count.first) {
+        (1, Some(solution)) => Ok(solution),
+        _ => Err(Error::Unsolvable),
+    }
+}
+
+// Fills an empty board with a random valid solution
+// Gives up with Err(Unsolvable) if the variant has no solution within budget
+// (possible with some jigsaw layouts)
+pub fn random_solution<R: Rng>(
+    shape: Shape,
+    variant: &Variant,
+    budget: u64,
+    rng: &mut R,
+) -> Result<Vec<u8>> {
+    let houses = variant.houses(shape);
+    let of = houses_of(shape.cells(), &houses);
+    let empty = vec![0u8; shape.cells()];
+    let mut search = Search::new(shape, &houses, &of, variant.cages(), &empty)?;
+    search.rng = Some(rng);
+    search.budget = budget;
+    search.run();
+    search.count.first.ok_or(Error::Unsolvable)
+}
+
+// Removes Cells from a full solution in random order while it stays uniquely solvable
+// returns the (locally) minimal puzzle
+pub fn minimal_puzzle<R: Rng>(
+    shape: Shape,
+    variant: &Variant,
+    solution: &[u8],
+    rng: &mut R,
+) -> Vec<u8> {
+    let mut puzzle = solution.to_vec();
+    let mut order: Vec<usize> = (0..puzzle.len()).collect();
+    order.shuffle(rng);
+
+    for cell in order {
+        let value = puzzle[cell];
+        puzzle[cell] = 0;
+        let unique = match count_with_budget(shape, variant, &puzzle, 2, MINIMIZE_BUDGET) {
+            Ok(count) => count.complete && count.solutions == 1,
+            Err(_) => false,
+        };
+        if !unique {
+            puzzle[cell] = value;
+        }
+    }
+    puzzle
+}
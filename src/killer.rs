@@ -0,0 +1,283 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use crate::grid::{self, Shape};
+use crate::variant::{neighbours, Variant};
+use crate::{Error, Result};
+
+// Node budget for a single uniqueness check of a cage layout
+// Layouts that run out of budget get split further, which makes them easier
+const UNIQUE_BUDGET: u64 = 200_000;
+// Largest sum a K frame carries, it sends two decimal digits (see cage_frame())
+pub const MAX_SUM: u32 = 99;
+
+// A Killer cage: its Cells (row major indices) hold distinct values adding up to sum
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Cage {
+    pub cells: Vec<usize>,
+    pub sum: u32,
+}
+
+impl Cage {
+    fn new(cells: Vec<usize>, solution: &[u8]) -> Self {
+        let sum = cells.iter().map(|&c| solution[c] as u32).sum();
+        Cage { cells, sum }
+    }
+
+    // Top left Cell of the cage, where the sum is printed
+    pub fn anchor(&self) -> usize {
+        self.cells.iter().copied().min().unwrap_or(0)
+    }
+}
+
+// Bitmask of the values that can still go into a cage
+// avail: values not used in the cage yet, empty: Cells left to fill, left: sum left to reach
+// Every value returned takes part in at least one set of empty distinct values adding up to left
+pub fn sum_mask(avail: u32, empty: u32, left: u32) -> u32 {
+    sum_mask_counted(avail, empty, left).0
+}
+
+// sum_mask() along with the number of steps its search took, for cycle estimates
+pub fn sum_mask_counted(avail: u32, empty: u32, left: u32) -> (u32, u64) {
+    let mut steps = 0;
+    let mask = combinations(avail, empty, left, &mut steps).unwrap_or(0);
+    (mask, steps)
+}
+
+fn combinations(avail: u32, count: u32, sum: u32, steps: &mut u64) -> Option<u32> {
+    *steps += 1;
+    if count == 0 {
+        return (sum == 0).then_some(0);
+    }
+    let mut mask = None;
+    let mut rest = avail;
+    while rest != 0 {
+        let value = rest.trailing_zeros() + 1;
+        // Only larger values remain in rest, so every set is visited once
+        rest &= rest - 1;
+        if value > sum {
+            break;
+        }
+        if let Some(m) = combinations(rest, count - 1, sum - value, steps) {
+            mask = Some(mask.unwrap_or(0) | m | 1 << (value - 1));
+        }
+    }
+    mask
+}
+
+// Parses a line of cages as written by cages_line()
+// Cages are separated by whitespace, each one as <SUM>:<X><Y><X><Y>...
+pub fn parse_cages(shape: Shape, line: &str) -> Result<Vec<Cage>> {
+    let n = shape.size();
+    line.split_whitespace()
+        .map(|word| {
+            let invalid = || Error::Parse(format!("Invalid cage '{}'", word));
+            let (sum, cells) = word.split_once(':').ok_or_else(invalid)?;
+            let sum = sum.parse::<u32>().map_err(|_| invalid())?;
+            if cells.is_empty() || !cells.len().is_multiple_of(2) {
+                return Err(invalid());
+            }
+            let cells = cells
+                .as_bytes()
+                .chunks(2)
+                .map(
+                    |pair| match (grid::decode_symbol(pair[0]), grid::decode_symbol(pair[1])) {
+                        (Some(x), Some(y))
+                            if x >= 1 && y >= 1 && x as usize <= n && y as usize <= n =>
+                        {
+                            Ok((y as usize - 1) * n + x as usize - 1)
+                        }
+                        _ => Err(invalid()),
+                    },
+                )
+                .collect::<Result<Vec<usize>>>()?;
+            Ok(Cage { cells, sum })
+        })
+        .collect()
+}
+
+// Writes cages the way parse_cages() reads them
+// Cells use the same 1 based column / row symbols as the wire protocol
+pub fn cages_line(shape: Shape, cages: &[Cage]) -> String {
+    let n = shape.size();
+    cages
+        .iter()
+        .map(|cage| {
+            let mut word = format!("{}:", cage.sum);
+            for &cell in &cage.cells {
+                word.push(grid::encode_symbol((cell % n) as u8 + 1) as char);
+                word.push(grid::encode_symbol((cell / n) as u8 + 1) as char);
+            }
+            word
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// Assigns every cage a color index so that touching cages never share one
+// Greedy, so at most colors colors are used unless a cage has that many neighbours
+pub fn cage_colors(shape: Shape, cages: &[Cage], colors: usize) -> Vec<usize> {
+    let n = shape.size();
+    let mut cage_of = vec![0; shape.cells()];
+    for (i, cage) in cages.iter().enumerate() {
+        for &cell in &cage.cells {
+            cage_of[cell] = i;
+        }
+    }
+
+    let mut color: Vec<Option<usize>> = vec![None; cages.len()];
+    for (i, cage) in cages.iter().enumerate() {
+        let mut taken = vec![false; colors];
+        for &cell in &cage.cells {
+            for next in neighbours(n, cell) {
+                if let Some(c) = color[cage_of[next]] {
+                    taken[c] = true;
+                }
+            }
+        }
+        color[i] = Some(taken.iter().position(|&t| !t).unwrap_or(i % colors));
+    }
+    color.into_iter().map(|c| c.unwrap_or(0)).collect()
+}
+
+// Cuts the board into random connected cages of at most max_size Cells
+// A cage never holds the same value of solution twice
+fn random_cages<R: Rng>(shape: Shape, solution: &[u8], max_size: usize, rng: &mut R) -> Vec<Cage> {
+    let n = shape.size();
+    let mut taken = vec![false; shape.cells()];
+    let mut order: Vec<usize> = (0..shape.cells()).collect();
+    order.shuffle(rng);
+
+    let mut cages = Vec::new();
+    for start in order {
+        if taken[start] {
+            continue;
+        }
+        let target = rng.gen_range(2..=max_size.max(2));
+        let mut cells = vec![start];
+        let mut values = 1u32 << (solution[start] - 1);
+        taken[start] = true;
+
+        while cells.len() < target {
+            let frontier: Vec<usize> = cells
+                .iter()
+                .flat_map(|&c| neighbours(n, c))
+                .filter(|&c| !taken[c] && values & (1 << (solution[c] - 1)) == 0)
+                .collect();
+            let next = match frontier.choose(rng) {
+                Some(&next) => next,
+                None => break,
+            };
+            taken[next] = true;
+            values |= 1 << (solution[next] - 1);
+            cells.push(next);
+        }
+        cells.sort_unstable();
+        cages.push(Cage::new(cells, solution));
+    }
+    cages
+}
+
+// Splits cells into their orthogonally connected parts
+fn components(n: usize, cells: &[usize]) -> Vec<Vec<usize>> {
+    let mut left: Vec<usize> = cells.to_vec();
+    let mut parts = Vec::new();
+
+    while let Some(start) = left.pop() {
+        let mut part = vec![start];
+        let mut queue = VecDeque::from([start]);
+        while let Some(cell) = queue.pop_front() {
+            for next in neighbours(n, cell) {
+                if let Some(pos) = left.iter().position(|&c| c == next) {
+                    left.swap_remove(pos);
+                    part.push(next);
+                    queue.push_back(next);
+                }
+            }
+        }
+        part.sort_unstable();
+        parts.push(part);
+    }
+    parts
+}
+
+// Splits the cage holding cell in two: about half of it grown around cell, and the rest
+// The rest is split again if it falls apart
+fn split_cage(shape: Shape, cages: &mut Vec<Cage>, cell: usize, solution: &[u8]) {
+    let n = shape.size();
+    let index = match cages.iter().position(|cage| cage.cells.contains(&cell)) {
+        Some(index) => index,
+        None => return,
+    };
+    let cage = cages.swap_remove(index);
+    let half = (cage.cells.len() / 2).max(1);
+
+    let mut part = vec![cell];
+    let mut queue = VecDeque::from([cell]);
+    while let Some(c) = queue.pop_front() {
+        for next in neighbours(n, c) {
+            if part.len() < half && cage.cells.contains(&next) && !part.contains(&next) {
+                part.push(next);
+                queue.push_back(next);
+            }
+        }
+    }
+    let rest: Vec<usize> = cage
+        .cells
+        .iter()
+        .copied()
+        .filter(|c| !part.contains(c))
+        .collect();
+
+    part.sort_unstable();
+    cages.push(Cage::new(part, solution));
+    for cells in components(n, &rest) {
+        cages.push(Cage::new(cells, solution));
+    }
+}
+
+// Builds a cage layout of at most max_size Cells per cage for which solution
+// is the only solution without any given Cells
+// Starts from random cages and splits the ones a second solution gets through
+pub fn unique_cages<R: Rng>(
+    shape: Shape,
+    solution: &[u8],
+    max_size: usize,
+    rng: &mut R,
+) -> Vec<Cage> {
+    let empty = vec![0u8; shape.cells()];
+    let mut cages = random_cages(shape, solution, max_size, rng);
+
+    loop {
+        let variant = Variant::Killer {
+            cages: cages.clone(),
+        };
+        let count = match grid::count_with_budget(shape, &variant, &empty, 2, UNIQUE_BUDGET) {
+            Ok(count) => count,
+            Err(_) => return cages,
+        };
+        if count.complete && count.solutions == 1 {
+            cages.sort();
+            return cages;
+        }
+
+        // Split where another solution differs, else the largest cage
+        let other = [count.first, count.last]
+            .into_iter()
+            .flatten()
+            .find(|found| found != solution);
+        let cell = match other {
+            Some(other) => (0..solution.len()).find(|&c| other[c] != solution[c]),
+            None => cages
+                .iter()
+                .max_by_key(|cage| cage.cells.len())
+                .and_then(|cage| cage.cells.choose(rng).copied()),
+        };
+        match cell {
+            Some(cell) => split_cage(shape, &mut cages, cell, solution),
+            None => return cages,
+        }
+    }
+}
@@ -1,53 +1,98 @@
-use anyhow::{bail, Context, Result};
 use colored::*;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use serialport::ClearBuffer;
-use std::fs::{self, create_dir, File, OpenOptions};
-use std::io::{stdin, BufRead, BufReader, ErrorKind, Write};
+use std::fs::{self, create_dir_all, OpenOptions};
+use std::io::{stdin, ErrorKind, Read, Write};
 use std::path::PathBuf;
 use std::str;
 use std::thread;
-use std::time::{Duration, Instant};
 use strum::IntoEnumIterator;
 
-#[path = "sudoku_avr.rs"]
+pub mod bench;
+pub mod bulk;
+pub mod config;
+pub mod cycles;
+pub mod emulator;
+pub mod error;
+pub mod firmware;
+pub mod fuzz;
+pub mod grid;
+pub mod killer;
+pub mod link;
+pub mod port;
+pub mod session;
+pub mod shell;
+pub mod simavr;
+pub mod solver;
 pub mod sudoku_avr;
-
-pub use sudoku_avr::{Cell, Difficulty, SudokuAvr};
-// Declare Type for opened Port
-type Port = Box<dyn serialport::SerialPort>;
+pub mod timing;
+pub mod variant;
+
+pub use config::{Config, Profile};
+pub use error::{Error, Result};
+pub use firmware::Firmware;
+pub use grid::Shape;
+pub use killer::Cage;
+pub use port::{Pacing, Port, PortConfig};
+pub use session::Event;
+pub use shell::Shell;
+pub use sudoku_avr::{Board, Cell, Difficulty, SudokuAvr};
+pub use timing::Timing;
+pub use variant::{Variant, VariantKind};
 // Define constants replies
-const OK: &[u8] = b"OK\r\n";
-const AT: &[u8] = b"AT\r\n";
-const DONE: &[u8] = b"D\r\n";
-const CLEAR: &[u8] = b"C\r\n";
-const T: &[u8] = b"T\r\n";
-const PLAY: &[u8] = b"P\r\n";
-const SAVE: &[u8] = b"S\r\n";
+pub const OK: &[u8] = b"OK\r\n";
+pub const AT: &[u8] = b"AT\r\n";
+pub const DONE: &[u8] = b"D\r\n";
+pub const CLEAR: &[u8] = b"C\r\n";
+pub const T: &[u8] = b"T\r\n";
+pub const PLAY: &[u8] = b"P\r\n";
+pub const SAVE: &[u8] = b"S\r\n";
+pub const BREAK: &[u8] = b"B\r\n";
+pub const CAGES: &[u8] = b"R\r\n";
+// Longest frame read_frame() waits for (a cage of 16 Cells)
+const MAX_FRAME: usize = 3 + 2 * 16 + 2;
+// Times a tournament board is tried when reconnects interrupt it
+const BOARD_ATTEMPTS: u32 = 3;
 
 // Given a Directory dir as a string and a number ns
-// Generate n Boards of Each Difficulty inside dir
-pub fn generate_boards(dir: String, num: u32) -> Result<()> {
+// Generate n Boards of Each Difficulty, the given Shape and variant inside dir
+// Boards are written as JSON (board, solution and metadata) if json is set
+pub fn generate_boards(
+    dir: String,
+    num: u32,
+    shape: Shape,
+    kind: VariantKind,
+    json: bool,
+) -> Result<()> {
     for diff in Difficulty::iter() {
         for i in 1..=num {
-            // let filename = format!("{}_{}.txt", diff, i);
-            let filename = format!("{}_{}.txt", diff, i);
+            let ext = if json { "json" } else { "txt" };
+            let filename = format!("{}_{}.{}", diff, i, ext);
             let path = PathBuf::from(format!("./{}/", dir)).join(filename);
-            let sudoku = SudokuAvr::new(&diff);
+            let sudoku = SudokuAvr::new_variant(shape, kind, &diff)?;
 
             let mut f = OpenOptions::new()
                 .create(true)
                 .write(true)
-                .open(&path)
-                .with_context(|| format!("Failed to create {}", path.display()))?;
+                .truncate(true)
+                .open(&path)?;
 
-            write!(f, "{}\n{}", sudoku.dif, sudoku.to_string())?;
+            if json {
+                write!(f, "{}", sudoku.to_json()?)?;
+            } else {
+                write!(f, "{}", sudoku.file_contents())?;
+            }
             info!("Created '{}'", path.display());
         }
     }
     Ok(())
 }
 
+// Returns the name of the port or a placeholder for logging
+pub fn port_name(port: &Port) -> String {
+    port.name().unwrap_or_else(|| String::from("<unnamed>"))
+}
+
 // Waits for specified response
 // returns Err if not valid Response
 pub fn wait_response(port: &mut Port, response: &[u8]) -> Result<()> {
@@ -55,202 +100,328 @@ pub fn wait_response(port: &mut Port, response: &[u8]) -> Result<()> {
     if response == &*data {
         debug!(
             "Read: {}",
-            str::from_utf8(&data)?.replace("\r", "").replace("\n", "")
+            String::from_utf8_lossy(&data).replace(['\r', '\n'], "")
         );
-        return Ok(());
+        Ok(())
     } else {
-        bail!("Invalid Response {:?}", data);
+        Err(Error::protocol(response, &data))
     }
 }
 
 // Writes data argument to UART port
-// Flushes buffer and waits for 50ms before returning
+// Flushes buffer and sleeps for the delay of the port's pacing (if any) before returning
 pub fn write_uart(port: &mut Port, data: &[u8]) -> Result<()> {
-    debug!(
-        "Writing {} bytes to {}",
-        data.len(),
-        port.name().expect("Failed to get Uart Name")
-    );
-    match port.write(data) {
-        Ok(_) => {
-            debug!("Wrote {} bytes!", str::from_utf8(data)?);
-        }
-        Err(_) => {
-            bail!("Unable to Write to Uart");
+    send_uart(port, data)?;
+    if let Some(delay) = port.pacing().delay {
+        thread::sleep(delay);
+    }
+    Ok(())
+}
+
+// Writes frames that are each answered with OK
+// Up to the pacing's window of frames are sent before waiting for their OKs
+pub fn send_frames(port: &mut Port, frames: &[Vec<u8>]) -> Result<()> {
+    let window = port.pacing().window.max(1);
+    let mut acked = 0;
+    for (sent, frame) in frames.iter().enumerate() {
+        if sent - acked >= window {
+            wait_response(port, OK)?;
+            acked += 1;
         }
+        write_uart(port, frame)?;
+    }
+    while acked < frames.len() {
+        wait_response(port, OK)?;
+        acked += 1;
     }
+    Ok(())
+}
+
+// Writes data argument to UART port
+// returns as soon as the flush says the last byte left, for timing (see timing::play())
+pub fn send_uart(port: &mut Port, data: &[u8]) -> Result<()> {
+    debug!("Writing {} bytes to {}", data.len(), port_name(port));
+    port.write_all(data)?;
+    debug!("Wrote {} bytes!", String::from_utf8_lossy(data));
     port.flush()?;
-    thread::sleep(Duration::from_millis(50));
     Ok(())
 }
 
 // Read size bytes from UART
 // if size is < 0 then reads entire buffer
+// Keeps reading until size bytes arrived, the port timing out stops it early
+// The returned Vector holds only the bytes actually read
 pub fn read_uart(port: &mut Port, size: i32) -> Result<Vec<u8>> {
-    let readable_bytes: usize;
-
-    if size <= 0 {
-        readable_bytes = port.bytes_to_read()? as usize;
+    let readable_bytes: usize = if size <= 0 {
+        port.bytes_to_read()? as usize
     } else {
-        readable_bytes = size as usize;
+        size as usize
+    };
+
+    let mut data: Vec<u8> = vec![0; readable_bytes];
+    let mut n = 0;
+    while n < data.len() {
+        match port.read(&mut data[n..]) {
+            Ok(0) => break,
+            Ok(read) => n += read,
+            Err(e) if e.kind() == ErrorKind::TimedOut && n == 0 => return Err(Error::Timeout),
+            Err(e) if e.kind() == ErrorKind::TimedOut => break,
+            Err(e) => return Err(Error::from(e)),
+        }
     }
+    data.truncate(n);
+    debug!("Bytes Read: {:?}", data);
+    Ok(data)
+}
 
-    // info!("Reading {} bytes from {}", readable_bytes, port.name().expect("Failed to get Uart Name"));
+// Builds a [<CMD><X><Y><NUM><CR><LF>] frame from a zero based column and row
+// Coordinates are sent 1 based, everything encoded with grid::encode_symbol
+// (plain ASCII digits on a 9x9 board)
+pub fn cell_frame(cmd: u8, col: usize, row: usize, value: u8) -> [u8; 6] {
+    [
+        cmd,
+        grid::encode_symbol(col as u8 + 1),
+        grid::encode_symbol(row as u8 + 1),
+        grid::encode_symbol(value),
+        b'\r',
+        b'\n',
+    ]
+}
 
-    let mut data: Vec<u8> = vec![0 as u8; readable_bytes as usize];
-    match port.read(data.as_mut_slice()) {
-        Ok(_) => (),
-        Err(ref e) => {
-            if e.kind() == ErrorKind::TimedOut {
-                bail!("Timed out!")
-            }
+// Builds a [D<X><Y><CR><LF>] frame asking the AVR for a single Cell
+pub fn debug_frame(col: usize, row: usize) -> [u8; 5] {
+    [
+        b'D',
+        grid::encode_symbol(col as u8 + 1),
+        grid::encode_symbol(row as u8 + 1),
+        b'\r',
+        b'\n',
+    ]
+}
+
+// Builds a [K<SUM><X1><Y1>...<Xn><Yn><CR><LF>] frame defining a Killer cage
+// The sum is sent as two decimal digits (Variant::validate() keeps it within killer::MAX_SUM),
+// the Cells like the coordinates of cell_frame()
+pub fn cage_frame(cage: &Cage, size: usize) -> Vec<u8> {
+    let mut frame = format!("K{:02}", cage.sum).into_bytes();
+    for &cell in &cage.cells {
+        frame.push(grid::encode_symbol((cell % size) as u8 + 1));
+        frame.push(grid::encode_symbol((cell / size) as u8 + 1));
+    }
+    frame.extend_from_slice(b"\r\n");
+    frame
+}
+
+// Parses a frame built by cage_frame() for a board with side size
+pub fn parse_cage_frame(frame: &[u8], size: usize) -> Result<Cage> {
+    let expected: &[u8] = b"K<SUM><X><Y>...\r\n";
+    if frame.len() < 7
+        || frame[0] != b'K'
+        || !frame.ends_with(b"\r\n")
+        || frame.len().is_multiple_of(2)
+    {
+        return Err(Error::protocol(expected, frame));
+    }
+    let sum = str::from_utf8(&frame[1..3])
+        .ok()
+        .and_then(|digits| digits.parse::<u32>().ok())
+        .ok_or_else(|| Error::protocol(expected, frame))?;
+    let cells = frame[3..frame.len() - 2]
+        .chunks(2)
+        .map(|pair| {
+            let x = decode_symbol(pair[0], 1, size, frame)?;
+            let y = decode_symbol(pair[1], 1, size, frame)?;
+            Ok((y - 1) as usize * size + (x - 1) as usize)
+        })
+        .collect::<Result<Vec<usize>>>()?;
+    Ok(Cage { cells, sum })
+}
+
+// Reads a single variable length frame, up to and including <CR><LF>
+pub fn read_frame(port: &mut Port) -> Result<Vec<u8>> {
+    read_line(port, MAX_FRAME)
+}
+
+// Reads up to and including <CR><LF>, failing once more than max bytes arrived
+pub fn read_line(port: &mut Port, max: usize) -> Result<Vec<u8>> {
+    let mut frame = Vec::new();
+    while !frame.ends_with(b"\r\n") {
+        if frame.len() >= max {
+            return Err(Error::protocol(b"<FRAME>\r\n", &frame));
+        }
+        let byte = read_uart(port, 1)?;
+        if byte.is_empty() {
+            return Err(Error::Timeout);
+        }
+        frame.extend_from_slice(&byte);
+    }
+    debug!(
+        "Frame Read: {}",
+        String::from_utf8_lossy(&frame).escape_debug()
+    );
+    Ok(frame)
+}
+
+// Decodes a single symbol sent by the AVR, between min and size
+fn decode_symbol(byte: u8, min: u8, size: usize, frame: &[u8]) -> Result<u8> {
+    match grid::decode_symbol(byte) {
+        Some(v) if v >= min && v as usize <= size => Ok(v),
+        _ => Err(Error::protocol(b"N<X><Y><NUM>\r\n", frame)),
+    }
+}
+
+// Parses a [N<X><Y><NUM><CR><LF>] frame of a board with side size
+// returns the zero based (row, column) and the value of the Cell
+pub fn parse_cell_frame(frame: &[u8], size: usize) -> Result<(usize, usize, u8)> {
+    if frame.len() != 6 || frame[0] != b'N' || &frame[4..] != b"\r\n" {
+        return Err(Error::protocol(b"N<X><Y><NUM>\r\n", frame));
+    }
+    let x = decode_symbol(frame[1], 1, size, frame)?;
+    let y = decode_symbol(frame[2], 1, size, frame)?;
+    let num = decode_symbol(frame[3], 0, size, frame)?;
+    Ok(((y - 1) as usize, (x - 1) as usize, num))
+}
+
+// Requests the Killer cages stored on the AVR using R / T
+// Every cage comes back as a K frame, the PC answers T for the next one until D
+// returns Err(CageMismatch) if they differ from the cages of sudoku
+pub fn recv_cages(port: &mut Port, sudoku: &SudokuAvr) -> Result<()> {
+    let mut cages: Vec<Cage> = Vec::new();
+
+    write_uart(port, CAGES)?;
+
+    loop {
+        let frame = read_frame(port)?;
+        if frame == DONE {
+            write_uart(port, OK)?;
+            break;
+        }
+        cages.push(parse_cage_frame(&frame, sudoku.size())?);
+        write_uart(port, T)?;
+    }
+    info!("Received {} cages", cages.len());
+
+    // Order of cages and of their Cells does not matter
+    let normalize = |cages: &[Cage]| {
+        let mut cages = cages.to_vec();
+        for cage in cages.iter_mut() {
+            cage.cells.sort_unstable();
+        }
+        cages.sort();
+        cages
+    };
+    if normalize(&cages) == normalize(sudoku.variant().cages()) {
+        Ok(())
+    } else {
+        Err(Error::CageMismatch)
+    }
+}
+
+// Creates directory dir if it does not already exist
+pub fn ensure_dir(dir: &str) -> Result<()> {
+    match create_dir_all(dir) {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            error!("Unable to Create directory!");
+            Err(Error::Io(e))
         }
     }
-    debug!("Bytes Read: {:?}", data);
-    Ok(data)
 }
 
 // Traverse Directory and find board files
-// Construct a Vector with the board files and sort it based on Difficulty
-fn prep_boards(dir: &String) -> Result<Vec<SudokuAvr>> {
-    let paths = fs::read_dir(dir).with_context(|| format!("{}{}", "Unable to read", dir))?;
-    let mut boards: Vec<SudokuAvr> = Vec::new();
+// Construct a Vector with the board files and their paths, sorted based on Difficulty
+pub fn load_boards(dir: &str) -> Result<Vec<(PathBuf, SudokuAvr)>> {
+    let paths = fs::read_dir(dir)?;
+    let mut boards: Vec<(PathBuf, SudokuAvr)> = Vec::new();
 
     for path in paths {
         let path = path?;
         if path.path().is_dir() {
             continue;
         }
-        let file = File::open(path.path())?;
-        let mut line = String::new();
-        let mut reader = BufReader::new(file);
-        reader.read_line(&mut line)?;
-
-        let diff = match line.replace("\n", "").as_str() {
-            "Easy" => Difficulty::Easy,
-            "Medium" => Difficulty::Medium,
-            "Hard" => Difficulty::Hard,
-            "Ultra" => Difficulty::Ultra,
-            _ => bail!("Invalid Dificulty!"),
-        };
-        line.clear();
-        reader.read_line(&mut line)?;
-        let board = SudokuAvr::new_from_str(&line, diff);
-        boards.push(board);
+        boards.push((path.path(), SudokuAvr::load(&path.path())?));
     }
-    boards.sort();
+    boards.sort_by(|a, b| a.1.cmp(&b.1));
     Ok(boards)
 }
 
+// Boards of dir without their paths, see load_boards()
+fn prep_boards(dir: &str) -> Result<Vec<SudokuAvr>> {
+    Ok(load_boards(dir)?
+        .into_iter()
+        .map(|(_, board)| board)
+        .collect())
+}
+
 // For a specific team, iterate over all provided boards
 // Play each board and log time and solution to a file
-pub fn play_tournament(dir: &String, team: &String, port: &mut Port) -> Result<()> {
+// The results go to out_dir/team_<team>.txt
+pub fn play_tournament(dir: &str, team: &str, out_dir: &str, port: &mut Port) -> Result<()> {
     info!("{}", "Prepairing Boards!".white().bold());
     let boards = prep_boards(dir)?;
 
-    let dir = "tournament";
     let mut total_time: f64 = 0.0;
-    match create_dir(dir) {
-        Ok(_) => (),
-        Err(e) => match e.kind() {
-            ErrorKind::AlreadyExists => (),
-            _ => {
-                error!("Unable to Create directory!");
-                bail!("{}", format!("{:#}", e));
-            }
-        },
-    }
+    ensure_dir(out_dir)?;
 
     let filename = format!("team_{}.txt", team);
-    let path = PathBuf::from(format!("./{}", dir)).join(filename.clone());
+    let path = PathBuf::from(out_dir).join(filename);
 
     let mut f = OpenOptions::new()
         .create(true)
         .write(true)
         .truncate(true)
-        .open(&path)
-        .with_context(|| format!("Failed to create {}", path.display()))?;
+        .open(&path)?;
 
-    write!(f, "Team: {}\n", team)?;
-    write!(f, "-------------------\n")?;
+    writeln!(f, "Team: {}", team)?;
+    match port.firmware() {
+        Some(firmware) => writeln!(f, "Firmware: {}", firmware)?,
+        None => writeln!(f, "Firmware: unknown (no reply to I)")?,
+    }
+    if let Some(simulation) = port.simulation() {
+        writeln!(
+            f,
+            "Simulated: {} at {} MHz under simavr",
+            simulation.mcu(),
+            simulation.clock_hz() as f64 / 1e6
+        )?;
+    }
+    writeln!(f, "-------------------")?;
 
     for (i, board) in boards.iter().enumerate() {
-        // Check if Board is Live
-        // send at
-        write_uart(port, AT)?;
-        // wait for ok
-        wait_response(port, OK)?;
-        // send clear
-        write_uart(port, CLEAR)?;
-        // wait for ok
-        wait_response(port, OK)?;
-        // send board
-        info!("{}", "Sending Board".white().bold());
-        board.send_board(port)?;
-        // clear buffers
-        port.clear(ClearBuffer::All)
-            .with_context(|| format!("Unable to Clear Buffers"))?;
-
-        info!(
-            "{}",
-            format!("Playing Board: {} Difficulty: {}", i, board.dif)
-                .white()
-                .bold()
-        );
-        write_uart(port, PLAY)?;
-
-        let time_now = Instant::now();
-
-        wait_response(port, OK)?;
-
-        // Wait until solution
-        loop {
-            match wait_response(port, DONE) {
-                Ok(()) => break,
-                Err(_) => continue,
-            }
-        }
-
-        let time_elapsed = time_now.elapsed();
+        let (timing, sol) = play_again_on_reconnect(port, board, i)?;
+        let time_elapsed = timing.solve();
         total_time += time_elapsed.as_secs_f64();
-
-        let mut sol = false;
-
-        // log time and solution
-        match recv_and_check(port, board) {
-            Ok(()) => {
-                info!("{}", format!("Valid Solution!!").green().bold());
-                sol = true;
-            }
-            Err(_) => info!("{}", format!("Invalid Solution! :( ").red().bold()),
+        if !timing.progress.is_empty() {
+            save_progress(out_dir, team, i, &timing.progress)?;
         }
 
-        // Clear Buffers
-        port.clear(ClearBuffer::All)
-            .with_context(|| format!("Unable to Clear Buffers"))?;
-
         // Log solution
-        write!(
+        writeln!(
             f,
-            "Board: {}\nDifficulty: {}\nTime to solve: {:?}\nValid Solution: {}\n",
-            i, board.dif, time_elapsed, sol
+            "Board: {}\nDifficulty: {}\nTime to solve: {}",
+            i,
+            board.dif,
+            timing::format_ms(time_elapsed)
         )?;
+        if let Some(simulated) = timing.simulated {
+            writeln!(f, "Simulated Cycles: {}", simulated.cycles)?;
+        }
+        writeln!(f, "Valid Solution: {}", sol)?;
 
-        write!(f, "-------------------\n")?;
+        writeln!(f, "-------------------")?;
         info!(
-            "Board: {} Difficulty: {} Solved in: {:?}",
-            i, board.dif, time_elapsed
+            "Board: {} Difficulty: {} Solved in: {}",
+            i,
+            board.dif,
+            timing::format_ms(time_elapsed)
         );
 
         info!("Press Enter to Send Next Board!");
         let mut junk = String::new();
-        stdin()
-            .read_line(&mut junk)
-            .with_context(|| format!("Unable to Read Line!"))?;
-        junk.clear();
+        stdin().read_line(&mut junk)?;
     }
-    write!(f, "Total Time: {:.4} seconds\n", total_time)?;
-    write!(f, "Finished Playing!\n")?;
+    writeln!(f, "Total Time: {:.3} seconds", total_time)?;
+    writeln!(f, "Finished Playing!")?;
     info!(
         "{}",
         format!("Team {} done! in {:.4} seconds", team, total_time)
@@ -260,31 +431,132 @@ pub fn play_tournament(dir: &String, team: &String, port: &mut Port) -> Result<(
     Ok(())
 }
 
-pub fn recv_and_check(port: &mut Port, sudoku: &SudokuAvr) -> Result<()> {
-    let mut p_board: [[Cell; 9]; 9] = Default::default();
+// Plays board i like play_board(), a board interrupted by a reconnect is downloaded
+// and played again from the start
+pub fn play_again_on_reconnect(
+    port: &mut Port,
+    board: &SudokuAvr,
+    i: usize,
+) -> Result<(Timing, bool)> {
+    let mut attempt = 1;
+    loop {
+        match play_board(port, board, i) {
+            Err(Error::Reconnected) if attempt < BOARD_ATTEMPTS => {
+                warn!(
+                    "{}",
+                    format!("Board {} interrupted, playing it again", i)
+                        .yellow()
+                        .bold()
+                );
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
 
-    write_uart(port, &SAVE)?;
-    let mut data: Vec<u8>;
+// Downloads and plays a single tournament board
+// returns the time the AVR took and whether its solution was valid
+fn play_board(port: &mut Port, board: &SudokuAvr, i: usize) -> Result<(Timing, bool)> {
+    // Check if Board is Live
+    // send at
+    write_uart(port, AT)?;
+    // wait for ok
+    wait_response(port, OK)?;
+    // send clear
+    write_uart(port, CLEAR)?;
+    // wait for ok
+    wait_response(port, OK)?;
+    // send board
+    info!("{}", "Sending Board".white().bold());
+    board.send_board(port)?;
+    // clear buffers
+    port.clear(ClearBuffer::All)?;
 
-    loop {
-        data = read_uart(port, 6)?;
-        // println!("{:?}", data);
-        debug!("{:?}", data);
-        if &data[..3] == DONE {
-            write_uart(port, &OK)?;
-            break;
+    info!(
+        "{}",
+        format!("Playing Board: {} Difficulty: {}", i, board.dif)
+            .white()
+            .bold()
+    );
+    // Wait until solution, giving up only if the device is lost
+    let timing = timing::play(port)?;
+    info!("Solved in: {}", timing);
+
+    let mut sol = false;
+
+    // log time and solution
+    match recv_and_check(port, board) {
+        Ok(()) => {
+            info!("{}", "Valid Solution!!".green().bold());
+            sol = true;
         }
-        p_board[(data[2] - 0x31) as usize][(data[1] - 0x31) as usize].value = data[3] - 0x30;
-        write_uart(port, &T)?;
+        Err(Error::Reconnected) => return Err(Error::Reconnected),
+        Err(e) => info!("{} ({})", "Invalid Solution! :( ".red().bold(), e),
+    }
+    session::record(Event::play(board, &timing, sol));
+
+    // Clear Buffers
+    port.clear(ClearBuffer::All)?;
+    Ok((timing, sol))
+}
+
+// Writes the progress reports of a tournament board as CSV: team_<team>_board_<i>_progress.csv
+fn save_progress(out_dir: &str, team: &str, i: usize, progress: &[timing::Progress]) -> Result<()> {
+    let path = PathBuf::from(out_dir).join(format!("team_{}_board_{}_progress.csv", team, i));
+    let mut f = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(&path)?;
+    writeln!(f, "ms,filled,backtracks")?;
+    for report in progress {
+        writeln!(f, "{},{},{}", report.ms, report.filled, report.backtracks)?;
+    }
+    debug!("Progress written to {}", path.display());
+    Ok(())
+}
+
+// Requests the solved board from the AVR using S / T (or Q if the firmware supports bulk frames)
+// Prints it and compares it against the solution
+// returns Err(InvalidSolution) if it doesn't match
+pub fn recv_and_check(port: &mut Port, sudoku: &SudokuAvr) -> Result<()> {
+    let mut p_board = sudoku.empty_board();
+
+    if bulk::supported(port)? {
+        let values = bulk::receive(port, sudoku.shape())?;
+        for (cell, value) in p_board.iter_mut().flatten().zip(values) {
+            cell.value = value;
+        }
+    } else {
+        recv_cells(port, sudoku, &mut p_board)?;
     }
 
     info!("{}", "Player Board: ".white().bold());
-    SudokuAvr::print_board(&p_board);
-    port.clear(ClearBuffer::All)
-        .with_context(|| format!("Unable to Clear Buffers"))?;
+    sudoku.print_board(&p_board);
+    port.clear(ClearBuffer::All)?;
 
-    match sudoku.check(&p_board) {
-        true => Ok(()),
-        false => bail!(""),
+    if sudoku.check(&p_board) {
+        Ok(())
+    } else {
+        Err(Error::InvalidSolution)
     }
 }
+
+// Reads the board one Cell at a time: S, then T for every N frame until D
+fn recv_cells(port: &mut Port, sudoku: &SudokuAvr, p_board: &mut Board) -> Result<()> {
+    write_uart(port, SAVE)?;
+
+    loop {
+        // D is shorter than an N frame, reading by line does not wait out the timeout for it
+        let data = read_frame(port)?;
+        if data == DONE {
+            write_uart(port, OK)?;
+            break;
+        }
+        let (row, col, num) = parse_cell_frame(&data, sudoku.size())?;
+        p_board[row][col].value = num;
+        write_uart(port, T)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,306 @@
+use log::debug;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+
+use crate::{wait_response, write_uart, Error, Port, Result, OK};
+
+// Switches the firmware to framed mode, sent (and answered with OK) unframed
+pub const ENABLE: &[u8] = b"F1\r\n";
+// Times a frame is sent before giving up on getting it acknowledged
+const ATTEMPTS: usize = 5;
+
+// Counters of a framed link, reported when the port is closed
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct LinkStats {
+    /* Frames sent and acknowledged */
+    pub sent: u64,
+    /* Frames received intact, repeats not counted */
+    pub received: u64,
+    /* Frames sent again after a NAK or a missing ACK */
+    pub retransmits: u64,
+    /* Received frames with a bad CRC, each one answered with a NAK */
+    pub crc_errors: u64,
+    /* NAKs the AVR answered our frames with */
+    pub naks: u64,
+    /* Frames the AVR did not acknowledge in time */
+    pub timeouts: u64,
+    /* Frames the AVR sent again although we had them, e.g. after a lost ACK */
+    pub duplicates: u64,
+}
+
+impl fmt::Display for LinkStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} frames sent, {} received, {} retransmits ({} NAKs, {} timeouts), {} CRC errors, {} duplicates",
+            self.sent,
+            self.received,
+            self.retransmits,
+            self.naks,
+            self.timeouts,
+            self.crc_errors,
+            self.duplicates
+        )
+    }
+}
+
+// CRC-16/XMODEM (polynomial 0x1021, initial value 0), _crc_xmodem_update() of avr-libc
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, &byte| {
+        (0..8).fold(crc ^ (byte as u16) << 8, |crc, _| match crc & 0x8000 {
+            0 => crc << 1,
+            _ => (crc << 1) ^ 0x1021,
+        })
+    })
+}
+
+fn hex_digit(value: u8) -> u8 {
+    b"0123456789ABCDEF"[(value & 0x0f) as usize]
+}
+
+// Builds a [#<SEQ><PAYLOAD><CRC><CR><LF>] frame around a line without its <CR><LF>
+// SEQ is a single hex digit, the CRC (of SEQ and PAYLOAD) four hex digits
+pub fn wrap(seq: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![b'#', hex_digit(seq)];
+    frame.extend_from_slice(payload);
+    frame.extend(format!("{:04X}\r\n", crc16(&frame[1..])).into_bytes());
+    frame
+}
+
+// Sequence number and payload of an intact frame (including its <CR><LF>)
+// None if it is not a frame or its CRC does not match
+pub fn unwrap(frame: &[u8]) -> Option<(u8, &[u8])> {
+    if frame.len() < 8 || frame[0] != b'#' || !frame.ends_with(b"\r\n") {
+        return None;
+    }
+    let body = &frame[1..frame.len() - 6];
+    let crc = std::str::from_utf8(&frame[frame.len() - 6..frame.len() - 2])
+        .ok()
+        .and_then(|hex| u16::from_str_radix(hex, 16).ok())?;
+    let seq = (body[0] as char).to_digit(16)? as u8;
+    (crc16(body) == crc).then(|| (seq, &body[1..]))
+}
+
+// [+<SEQ><CR><LF>] acknowledges a frame, [-<SEQ><CR><LF>] asks for it again
+pub fn ack(seq: u8) -> [u8; 4] {
+    [b'+', hex_digit(seq), b'\r', b'\n']
+}
+
+pub fn nak(seq: u8) -> [u8; 4] {
+    [b'-', hex_digit(seq), b'\r', b'\n']
+}
+
+// State of a framed link on top of a port
+// Every line written is sent as a frame and resent until the AVR acknowledges it,
+// every frame read is acknowledged (or NAKed on a bad CRC) and its payload handed on as a line
+#[derive(Default)]
+pub struct Link {
+    tx_seq: u8,
+    /* Sequence number of the last frame handed on, repeats of it are dropped */
+    rx_seq: Option<u8>,
+    /* Bytes written since the end of the last line */
+    tx_line: Vec<u8>,
+    /* Bytes read from the wire since the end of the last line */
+    rx_line: Vec<u8>,
+    /* Payload of received frames not read yet */
+    rx_buf: VecDeque<u8>,
+    pub stats: LinkStats,
+}
+
+impl Link {
+    // Starts over after the firmware was reset, keeping the statistics
+    pub fn reset(&mut self) {
+        self.tx_seq = 0;
+        self.rx_seq = None;
+        self.tx_line.clear();
+        self.clear_input();
+    }
+
+    pub fn clear_input(&mut self) {
+        self.rx_line.clear();
+        self.rx_buf.clear();
+    }
+
+    // Payload bytes received but not read yet
+    pub fn buffered(&self) -> usize {
+        self.rx_buf.len()
+    }
+
+    // Sends every complete line of buf as a frame, the rest once its line is complete
+    pub fn write<W: Read + Write>(&mut self, wire: &mut W, buf: &[u8]) -> io::Result<usize> {
+        self.tx_line.extend_from_slice(buf);
+        while let Some(end) = self.tx_line.windows(2).position(|w| w == b"\r\n") {
+            let line: Vec<u8> = self.tx_line.drain(..end + 2).collect();
+            self.send_frame(wire, &line[..end])?;
+        }
+        Ok(buf.len())
+    }
+
+    // Hands on the payload of received frames, reading the wire until one arrives
+    pub fn read<W: Read + Write>(&mut self, wire: &mut W, buf: &mut [u8]) -> io::Result<usize> {
+        while self.rx_buf.is_empty() {
+            let line = self.next_line(wire)?;
+            if line.first() == Some(&b'#') {
+                self.receive(wire, &line)?;
+            } else {
+                debug!(
+                    "Link ignored {}",
+                    String::from_utf8_lossy(&line).escape_debug()
+                );
+            }
+        }
+        let n = buf.len().min(self.rx_buf.len());
+        for (byte, out) in buf.iter_mut().zip(self.rx_buf.drain(..n)) {
+            *byte = out;
+        }
+        Ok(n)
+    }
+
+    fn send_frame<W: Read + Write>(&mut self, wire: &mut W, payload: &[u8]) -> io::Result<()> {
+        let seq = self.tx_seq;
+        let frame = wrap(seq, payload);
+        for attempt in 0..ATTEMPTS {
+            if attempt > 0 {
+                self.stats.retransmits += 1;
+            }
+            wire.write_all(&frame)?;
+            wire.flush()?;
+            if self.wait_ack(wire, seq)? {
+                self.tx_seq = (seq + 1) % 16;
+                self.stats.sent += 1;
+                return Ok(());
+            }
+        }
+        Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "{} was not acknowledged after {} attempts",
+                String::from_utf8_lossy(payload),
+                ATTEMPTS
+            ),
+        ))
+    }
+
+    // true once the frame seq is acknowledged, false if it has to be sent again
+    // Frames of the AVR arriving meanwhile are received as usual
+    fn wait_ack<W: Read + Write>(&mut self, wire: &mut W, seq: u8) -> io::Result<bool> {
+        loop {
+            let line = match self.next_line(wire) {
+                Ok(line) => line,
+                Err(e) if e.kind() == ErrorKind::TimedOut => {
+                    self.stats.timeouts += 1;
+                    return Ok(false);
+                }
+                Err(e) => return Err(e),
+            };
+            match line.as_slice() {
+                [b'+', s, b'\r', b'\n'] if *s == hex_digit(seq) => return Ok(true),
+                [b'-', s, b'\r', b'\n'] if *s == hex_digit(seq) => {
+                    self.stats.naks += 1;
+                    return Ok(false);
+                }
+                [b'#', ..] => self.receive(wire, &line)?,
+                _ => debug!(
+                    "Link ignored {}",
+                    String::from_utf8_lossy(&line).escape_debug()
+                ),
+            }
+        }
+    }
+
+    // Acknowledges an intact frame and keeps its payload (unless it is a repeat), NAKs a broken one
+    fn receive<W: Write>(&mut self, wire: &mut W, line: &[u8]) -> io::Result<()> {
+        match unwrap(line) {
+            Some((seq, payload)) => {
+                wire.write_all(&ack(seq))?;
+                if self.rx_seq == Some(seq) {
+                    self.stats.duplicates += 1;
+                } else {
+                    self.rx_seq = Some(seq);
+                    self.rx_buf.extend(payload.iter().chain(b"\r\n"));
+                    self.stats.received += 1;
+                }
+            }
+            None => {
+                self.stats.crc_errors += 1;
+                let seq = line
+                    .get(1)
+                    .and_then(|&s| (s as char).to_digit(16))
+                    .unwrap_or(0) as u8;
+                wire.write_all(&nak(seq))?;
+            }
+        }
+        wire.flush()
+    }
+
+    // Next line from the wire, including its <CR><LF>
+    fn next_line<R: Read>(&mut self, wire: &mut R) -> io::Result<Vec<u8>> {
+        loop {
+            if let Some(end) = self.rx_line.windows(2).position(|w| w == b"\r\n") {
+                return Ok(self.rx_line.drain(..end + 2).collect());
+            }
+            let mut chunk = [0u8; 64];
+            match wire.read(&mut chunk)? {
+                0 => return Err(io::Error::new(ErrorKind::TimedOut, "Operation timed out")),
+                n => self.rx_line.extend_from_slice(&chunk[..n]),
+            }
+        }
+    }
+}
+
+// Switches the port and the firmware to framed mode
+pub fn enable(port: &mut Port) -> Result<()> {
+    write_uart(port, ENABLE)?;
+    match wait_response(port, OK) {
+        Ok(()) => (),
+        Err(Error::Timeout) | Err(Error::Protocol { .. }) => {
+            return Err(Error::Protocol {
+                expected: String::from("OK to F1 (framed mode)"),
+                got: String::from("no framed mode support"),
+            })
+        }
+        Err(e) => return Err(e),
+    }
+    port.set_link(Some(Link::default()));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc16_known_answer() {
+        assert_eq!(crc16(b"123456789"), 0x31C3);
+        assert_eq!(crc16(b""), 0);
+    }
+
+    #[test]
+    fn wrap_unwrap_round_trip() {
+        for (seq, payload) in [(0, &b"AT"[..]), (7, b"N123"), (15, b""), (10, b"U40;12")] {
+            let frame = wrap(seq, payload);
+            assert!(frame.starts_with(b"#") && frame.ends_with(b"\r\n"));
+            assert_eq!(unwrap(&frame), Some((seq, payload)));
+        }
+    }
+
+    #[test]
+    fn unwrap_rejects_a_flipped_byte() {
+        let frame = wrap(3, b"N957");
+        // Every byte between the # and the <CR><LF>: sequence number, payload and CRC
+        for i in 1..frame.len() - 2 {
+            let mut corrupted = frame.clone();
+            corrupted[i] ^= 0x01;
+            assert_eq!(unwrap(&corrupted), None, "byte {} flipped", i);
+        }
+    }
+
+    #[test]
+    fn unwrap_rejects_non_frames() {
+        assert_eq!(unwrap(b"OK\r\n"), None);
+        assert_eq!(unwrap(b"#0AT"), None);
+        assert_eq!(unwrap(&ack(2)), None);
+    }
+}
@@ -1,33 +1,21 @@
+use ace411_sudoku as lib;
 use anyhow::{bail, Context, Result};
 use colored::*;
-use log::{error, info};
-use pad::PadStr;
-use serialport::{available_ports, ClearBuffer, DataBits, Parity, StopBits};
-use simplelog::{ColorChoice, ConfigBuilder, TermLogger, TerminalMode};
-use std::fs::File;
-use std::io::{stdin, BufRead, BufReader, Write};
-use std::thread;
+use lib::session::{self, SessionLogger};
+use lib::*;
+use log::{debug, error, info};
+use serialport::{available_ports, ClearBuffer, DataBits, Parity, SerialPortType, StopBits};
+use simplelog::{
+    ColorChoice, CombinedLogger, ConfigBuilder, SharedLogger, TermLogger, TerminalMode,
+};
+use std::path::Path;
+use std::str::FromStr;
 use std::time::Duration;
-use std::time::Instant;
 use structopt::StructOpt;
+use strum::IntoEnumIterator;
 use strum_macros::EnumString;
 
-mod lib;
-
-use crate::lib::*;
-
-// Define a new Type for Open Port
-type Port = Box<dyn serialport::SerialPort>;
-
-// Define constants replies
-const OK: &[u8] = b"OK\r\n";
-const AT: &[u8] = b"AT\r\n";
-const DONE: &[u8] = b"D\r\n";
-const CLEAR: &[u8] = b"C\r\n";
-const BREAK: &[u8] = b"B\r\n";
-const PLAY: &[u8] = b"P\r\n";
-
-#[derive(Debug, EnumString)]
+#[derive(Debug, Clone, Copy, EnumString)]
 enum MyParity {
     #[strum(ascii_case_insensitive)]
     None,
@@ -51,6 +39,22 @@ struct Opts {
     /// Verbosity level
     #[structopt(name = "verbosity", long = "verbose", short = "v")]
     verbosity: bool,
+
+    /// Config file [default: ./ace411_sudoku.toml, then ~/.config/ace411_sudoku/config.toml]
+    #[structopt(long = "config")]
+    config: Option<String>,
+
+    /// Profile of the config file to use
+    #[structopt(long = "profile", short = "P")]
+    profile: Option<String>,
+
+    /// Directory for the session log (JSON lines, one file per run) [default: sessions]
+    #[structopt(long = "log-dir")]
+    log_dir: Option<String>,
+
+    /// Do not write a session log
+    #[structopt(long = "no-log")]
+    no_log: bool,
 }
 
 #[derive(StructOpt, Debug)]
@@ -74,33 +78,160 @@ enum Command {
     /// Download Board to MCU
     #[structopt(name = "tour")]
     Tour(Tournament),
+
+    /// Find the baud rate, parity and stop bits the AVR answers AT to
+    #[structopt(name = "probe")]
+    Probe(Probe),
+
+    /// Send malformed and edge-case frames and check the AVR still answers AT
+    #[structopt(name = "fuzz")]
+    Fuzz(Fuzz),
+
+    /// Solve boards on the host and print search statistics per board and difficulty
+    #[structopt(name = "solve")]
+    Solve(Solve),
+
+    /// Estimate ATmega16 cycles and time of the host side solvers, optionally against tournament times
+    #[structopt(name = "estimate")]
+    Estimate(Estimate),
+
+    /// Play boards of every difficulty and report time statistics per difficulty
+    #[structopt(name = "bench")]
+    Bench(Bench),
 }
 
 #[derive(StructOpt, Debug)]
-struct Tournament {
-    /// Directory to place the Boards
+struct Solve {
+    /// Directory of board files to solve
     #[structopt(long = "directory", short = "d")]
+    directory: Option<String>,
+
+    /// Board file to solve, can be given more than once
+    #[structopt(long = "board-file", short = "b")]
+    boards: Vec<String>,
+
+    /// Algorithm to solve with, can be given more than once [default: all]
+    #[structopt(long = "algorithm", short = "a", possible_values(&["Backtracking", "Propagation", "DancingLinks", "DLX"]), case_insensitive = true)]
+    algorithms: Vec<lib::solver::Algorithm>,
+
+    /// Search nodes an algorithm may visit per board before giving up
+    #[structopt(long = "budget", default_value = "20000000")]
+    budget: u64,
+}
+
+#[derive(StructOpt, Debug)]
+struct Estimate {
+    /// Directory of board files to estimate, in the order the tournament plays them
+    #[structopt(long = "directory", short = "d")]
+    directory: Option<String>,
+
+    /// Board file to estimate, can be given more than once
+    #[structopt(long = "board-file", short = "b")]
+    boards: Vec<String>,
+
+    /// Algorithm to estimate, can be given more than once [default: all]
+    #[structopt(long = "algorithm", short = "a", possible_values(&["Backtracking", "Propagation", "DancingLinks", "DLX"]), case_insensitive = true)]
+    algorithms: Vec<lib::solver::Algorithm>,
+
+    /// Clock of the ATmega16 in MHz
+    #[structopt(long = "clock", default_value = "8")]
+    clock: f64,
+
+    /// Search nodes an algorithm may visit per board before giving up
+    #[structopt(long = "budget", default_value = "20000000")]
+    budget: u64,
+
+    /// Tournament results (team_<team>.txt) of the boards of --directory to compare against
+    #[structopt(long = "results", requires = "directory", conflicts_with = "boards")]
+    results: Option<String>,
+}
+
+#[derive(StructOpt, Debug)]
+struct Bench {
+    #[structopt(flatten)]
+    port: PortArgs,
+
+    /// Boards played per difficulty
+    #[structopt(long = "number", short = "n", default_value = "10")]
+    number: u64,
+
+    /// Board size
+    #[structopt(long="size", short="s", default_value="9", possible_values(&["4", "6", "9", "16"]))]
+    size: usize,
+
+    /// Variant Rules (Classic, Diagonal, Windoku, Jigsaw, Killer)
+    #[structopt(long = "variant", default_value = "Classic")]
+    variant: lib::VariantKind,
+
+    /// Seed of the generated boards, the same seed plays the same boards
+    #[structopt(long = "seed")]
+    seed: Option<u64>,
+
+    /// Solve on the host with this algorithm instead of playing on the AVR
+    #[structopt(long = "solver", possible_values(&["Backtracking", "Propagation", "DancingLinks", "DLX"]), case_insensitive = true)]
+    solver: Option<lib::solver::Algorithm>,
+
+    /// Search nodes the --solver may visit per board before giving up
+    #[structopt(long = "budget", default_value = "20000000")]
+    budget: u64,
+
+    /// Bars of the histogram of every difficulty
+    #[structopt(long = "bins", default_value = "10")]
+    bins: usize,
+
+    /// CSV file the time of every board is written to
+    #[structopt(long = "csv", default_value = "bench.csv")]
+    csv: String,
+}
+
+#[derive(StructOpt, Debug)]
+struct Fuzz {
+    #[structopt(flatten)]
+    port: PortArgs,
+
+    /// Directory to save the transcripts of sequences hanging the firmware
+    #[structopt(long = "directory", short = "d", default_value = "fuzz")]
     directory: String,
 
-    /// Device Port
-    #[structopt(long = "dev", short = "u")]
-    dev: String,
+    /// Random sequences to send after the generated ones
+    #[structopt(long = "random", default_value = "100")]
+    random: usize,
 
-    /// Stop Bits
-    #[structopt(long="stop-bits", default_value="1", possible_values(&["1", "2"]))]
-    sb: u8,
+    /// Seed of the random sequences, one is picked and printed without it
+    #[structopt(long = "seed")]
+    seed: Option<u64>,
 
-    /// Data Bits
-    #[structopt(long="data-bits", default_value="8", possible_values(&["5", "6", "7", "8"]))]
-    db: u8,
+    /// Board size the frames are built for
+    #[structopt(long="size", short="s", default_value="9", possible_values(&["4", "6", "9", "16"]))]
+    size: usize,
 
-    /// Parity
-    #[structopt(long = "parity", short = "p", default_value = "None")]
-    p: MyParity,
+    /// Reset a hung AVR by pulsing DTR (Arduino style boards) instead of asking for the reset button
+    #[structopt(long = "dtr-reset")]
+    dtr_reset: bool,
 
-    /// Baudrate
-    #[structopt(long = "baud-rate", short = "r")]
-    br: u32,
+    /// Replay a saved transcript and check whether it still hangs the firmware
+    #[structopt(long = "replay")]
+    replay: Option<String>,
+}
+
+#[derive(StructOpt, Debug)]
+struct Probe {
+    #[structopt(flatten)]
+    port: PortArgs,
+
+    /// Keep going after the first clean OK and report every setting that works
+    #[structopt(long = "all", short = "a")]
+    all: bool,
+}
+
+#[derive(StructOpt, Debug)]
+struct Tournament {
+    /// Directory to place the Boards
+    #[structopt(long = "directory", short = "d")]
+    directory: String,
+
+    #[structopt(flatten)]
+    port: PortArgs,
 
     /// Team
     #[structopt(long = "team", short = "t")]
@@ -116,29 +247,25 @@ struct Gen {
     /// Generate <number> boards for EACH difficulty level
     #[structopt(long = "number", short = "n")]
     number: u32,
-}
-
-#[derive(StructOpt, Debug)]
-struct Prog {
-    /// Device Port
-    #[structopt(long = "dev", short = "u")]
-    dev: String,
 
-    /// Stop Bits
-    #[structopt(long="stop-bits", default_value="1", possible_values(&["1", "2"]))]
-    sb: u8,
+    /// Write boards as JSON (board, solution and metadata)
+    #[structopt(long = "json", short = "j")]
+    json: bool,
 
-    /// Data Bits
-    #[structopt(long="data-bits", default_value="8", possible_values(&["5", "6", "7", "8"]))]
-    db: u8,
+    /// Board size (4x4, 6x6, 9x9 or 16x16)
+    #[structopt(long="size", short="s", default_value="9", possible_values(&["4", "6", "9", "16"]))]
+    size: usize,
 
-    /// Parity
-    #[structopt(long = "parity", short = "p", default_value = "None")]
-    p: MyParity,
+    /// Sudoku variant
+    /// [possible values: Classic, Diagonal (X), Windoku, Jigsaw, Killer]
+    #[structopt(long = "variant", default_value = "Classic")]
+    variant: lib::VariantKind,
+}
 
-    /// Baudrate
-    #[structopt(long = "baud-rate", short = "r")]
-    br: u32,
+#[derive(StructOpt, Debug)]
+struct Prog {
+    #[structopt(flatten)]
+    port: PortArgs,
 
     /// Board file to download
     #[structopt(long = "board-file", short = "b")]
@@ -147,65 +274,162 @@ struct Prog {
     /// Enter Interactive shell
     #[structopt(long = "interactive", short = "i")]
     inter: bool,
+
+    /// Run shell commands from a script file
+    #[structopt(long = "script")]
+    script: Option<String>,
+
+    /// Run shell commands separated by ';'
+    #[structopt(long = "command", short = "c")]
+    commands: Option<String>,
 }
 
 #[derive(StructOpt, Debug)]
 struct Run {
-    /// Device Port
-    #[structopt(long = "dev", short = "u")]
-    dev: String,
+    #[structopt(flatten)]
+    port: PortArgs,
 
-    /// Difficulty of Game
+    /// Difficulty of Game, taken from the profile if not given
     /// [possible values: Easy, Medium, Hard, Ultra]
     #[structopt(long = "difficulty", short = "d")]
-    difficulty: lib::Difficulty,
+    difficulty: Option<lib::Difficulty>,
+
+    /// Board size (4x4, 6x6, 9x9 or 16x16)
+    #[structopt(long="size", short="s", default_value="9", possible_values(&["4", "6", "9", "16"]))]
+    size: usize,
 
-    /// Stop Bits
-    #[structopt(long="stop-bits", default_value="1", possible_values(&["1", "2"]))]
-    sb: u8,
+    /// Sudoku variant
+    /// [possible values: Classic, Diagonal (X), Windoku, Jigsaw, Killer]
+    #[structopt(long = "variant", default_value = "Classic")]
+    variant: lib::VariantKind,
 
-    /// Data Bits
-    #[structopt(long="data-bits", default_value="8", possible_values(&["5", "6", "7", "8"]))]
-    db: u8,
+    /// Run shell commands from a script file instead of the interactive shell
+    #[structopt(long = "script")]
+    script: Option<String>,
+
+    /// Run shell commands separated by ';' instead of the interactive shell
+    #[structopt(long = "command", short = "c")]
+    commands: Option<String>,
+}
+
+// Port settings shared by every subcommand talking to the AVR
+// Each one overrides the selected profile of the config file
+#[derive(StructOpt, Debug, Clone)]
+struct PortArgs {
+    /// Device Port, "auto" picks a connected STK-500 / USB-UART adapter
+    #[structopt(long = "dev", short = "u")]
+    dev: Option<String>,
 
-    /// Parity
-    #[structopt(long = "parity", short = "p", default_value = "None")]
-    p: MyParity,
+    /// Stop Bits [default: 1]
+    #[structopt(long="stop-bits", possible_values(&["1", "2"]))]
+    sb: Option<u8>,
+
+    /// Data Bits [default: 8]
+    #[structopt(long="data-bits", possible_values(&["5", "6", "7", "8"]))]
+    db: Option<u8>,
+
+    /// Parity [default: None]
+    #[structopt(long = "parity", short = "p")]
+    p: Option<MyParity>,
 
     /// Baudrate
     #[structopt(long = "baud-rate", short = "r")]
-    br: u32,
-    // #[structopt(long = "timeout", short = "t", default_value = "200")]
-    // timeout: u64,
-}
+    br: Option<u32>,
+
+    /// Read timeout in milliseconds [default: 1000]
+    #[structopt(long = "timeout")]
+    timeout: Option<u64>,
+
+    /// Sleep after every frame, for slow firmware (older versions always slept 50 ms)
+    #[structopt(long = "delay")]
+    delay: Option<u64>,
+
+    /// Board frames sent before waiting for their OKs [default: 1]
+    #[structopt(long = "window")]
+    window: Option<usize>,
+
+    /// Always use the per-cell protocol, even if the firmware supports bulk frames
+    #[structopt(long = "no-bulk")]
+    no_bulk: bool,
+
+    /// Frame every line with a sequence number and a CRC, resending corrupted ones (needs firmware support)
+    #[structopt(long = "crc")]
+    crc: bool,
 
-struct PortConfig {
-    baud_rate: u32,
-    stop_bits: StopBits,
-    data_bits: DataBits,
-    parity: Parity,
-    dev: String,
+    /// Do not ask the firmware with I or W?, assume the legacy command set (for firmware hanging on unknown commands)
+    #[structopt(long = "legacy")]
+    legacy: bool,
+
+    /// With --dev auto, send AT to every candidate and use the first one answering
+    #[structopt(long = "probe")]
+    probe: bool,
+
+    /// Try common baud rates, parity and stop bits until the AVR answers AT
+    #[structopt(long = "detect")]
+    detect: bool,
+
+    /// Seconds to wait for a disconnected device to come back, 0 to give up right away [default: 30]
+    #[structopt(long = "reconnect")]
+    reconnect: Option<u64>,
 }
 
-fn get_ports() {
-    let ports = available_ports().expect("No ports found!");
+// Prints every serial port, with the USB details and the adapter --dev auto would see
+fn get_ports(profile: &Profile) -> Result<()> {
+    let ports = available_ports().context("No ports found!")?;
+    let adapters = profile.adapters.clone().unwrap_or_default();
     for p in ports {
         println!(
-            "{}",
-            format!(
-                "{}{}{} {} {}",
-                "[".white().bold(),
-                "*".green().bold(),
-                "]".white().bold(),
-                "Found Port: ".white().bold(),
-                p.port_name.white().bold()
-            )
+            "{}{}{} {} {}",
+            "[".white().bold(),
+            "*".green().bold(),
+            "]".white().bold(),
+            "Found Port: ".white().bold(),
+            p.port_name.white().bold()
         );
+        let usb = match p.port_type {
+            SerialPortType::UsbPort(usb) => usb,
+            SerialPortType::PciPort => {
+                println!("    Type: PCI");
+                continue;
+            }
+            SerialPortType::BluetoothPort => {
+                println!("    Type: Bluetooth");
+                continue;
+            }
+            SerialPortType::Unknown => {
+                println!("    Type: Unknown");
+                continue;
+            }
+        };
+        let unknown = || String::from("-");
+        println!("    Type: USB {:04x}:{:04x}", usb.vid, usb.pid);
+        println!(
+            "    Manufacturer: {}  Product: {}  Serial: {}",
+            usb.manufacturer.clone().unwrap_or_else(unknown),
+            usb.product.clone().unwrap_or_else(unknown),
+            usb.serial_number.clone().unwrap_or_else(unknown)
+        );
+        if let Some(name) = lib::port::adapter_name(&usb, &adapters) {
+            println!(
+                "    {}",
+                format!("Adapter: {} (--dev auto)", name).green().bold()
+            );
+        }
     }
+    Ok(())
 }
 
-fn run(dif: lib::Difficulty, port: &mut Port) -> Result<()> {
-    let mut sudoku = lib::SudokuAvr::new(&dif);
+fn run(args: &Run, profile: &Profile, port: &mut Port) -> Result<()> {
+    let shape = Shape::from_size(args.size)?;
+    let difficulty = match args
+        .difficulty
+        .clone()
+        .or_else(|| profile.difficulty.clone())
+    {
+        Some(difficulty) => difficulty,
+        None => bail!("No difficulty given, use --difficulty or set one in the config"),
+    };
+    let sudoku = lib::SudokuAvr::new_variant(shape, args.variant, &difficulty)?;
 
     println!("\n{}", "Generated Board!".white().bold());
     sudoku.print_unsolved();
@@ -213,26 +437,337 @@ fn run(dif: lib::Difficulty, port: &mut Port) -> Result<()> {
     println!("{}", "Generated Solution!".white().bold());
     sudoku.print_solved();
 
-    println!("{}", "Going Interactive".white().bold());
-    go_interactive(port, &mut sudoku, false)?;
+    let name = format!("{} {} {}", shape, args.variant, difficulty);
+    let mut shell = Shell::new(port, &name, sudoku, false);
+    shell.set_export_dir(profile.export_dir());
+    let scripted = args.script.is_some() || args.commands.is_some();
+    run_scripts(&mut shell, &args.script, &args.commands)?;
+    if !scripted {
+        println!("{}", "Going Interactive".white().bold());
+        shell.run_interactive()?;
+    }
 
     Ok(())
 }
 
-fn open_port(port_config: &PortConfig) -> Result<Port> {
-    let builder = serialport::new(port_config.dev.as_str(), port_config.baud_rate)
-        .stop_bits(port_config.stop_bits)
-        .data_bits(port_config.data_bits)
-        // .timeout(Duration::from_millis(timeout))
-        .parity(port_config.parity);
+// Runs the script file, then the ';' separated commands
+// Any failure is returned so that the process exits with a non-zero code
+fn run_scripts(
+    shell: &mut Shell,
+    script: &Option<String>,
+    commands: &Option<String>,
+) -> Result<()> {
+    if let Some(script) = script {
+        shell
+            .run_file(Path::new(script))
+            .with_context(|| format!("Script {} failed", script))?;
+    }
+    if let Some(commands) = commands {
+        shell.run_script(commands).context("Commands failed")?;
+    }
+    Ok(())
+}
+
+// Port settings from the command line, falling back to the profile and then the defaults
+fn port_config(args: &PortArgs, profile: &Profile) -> Result<PortConfig> {
+    let detect = args.detect || profile.detect.unwrap_or(false);
+    let parity = match (args.p, &profile.parity) {
+        (Some(parity), _) => parity,
+        (None, Some(parity)) => MyParity::from_str(parity)
+            .with_context(|| format!("Invalid parity '{}' in the config", parity))?,
+        (None, None) => MyParity::None,
+    };
+    Ok(PortConfig {
+        dev: match args.dev.clone().or_else(|| profile.dev.clone()) {
+            Some(dev) => dev,
+            None => bail!("No device given, use --dev or set one in the config"),
+        },
+        baud_rate: match (args.br.or(profile.baud_rate), detect) {
+            (Some(baud_rate), _) => baud_rate,
+            (None, true) => lib::port::BAUD_RATES[0],
+            (None, false) => {
+                bail!("No baud rate given, use --baud-rate, --detect or set one in the config")
+            }
+        },
+        stop_bits: check_stop_bits(args.sb.or(profile.stop_bits).unwrap_or(1))?,
+        data_bits: check_data_bits(args.db.or(profile.data_bits).unwrap_or(8))?,
+        parity: check_parity(parity)?,
+        timeout: args
+            .timeout
+            .or(profile.timeout_ms)
+            .map(Duration::from_millis),
+        adapters: profile.adapters.clone().unwrap_or_default(),
+        probe: args.probe || profile.probe.unwrap_or(false),
+        detect,
+        reconnect: match args.reconnect.or(profile.reconnect_s) {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => Some(lib::port::RECONNECT_TIMEOUT),
+        },
+        pacing: Pacing {
+            delay: args
+                .delay
+                .or(profile.delay_ms)
+                .filter(|&ms| ms > 0)
+                .map(Duration::from_millis),
+            window: match args.window.or(profile.window).unwrap_or(1) {
+                0 => bail!("The window must hold at least 1 frame"),
+                window => window,
+            },
+        },
+        bulk: !args.no_bulk && profile.bulk.unwrap_or(true),
+        crc: args.crc || profile.crc.unwrap_or(false),
+        legacy: args.legacy || profile.legacy.unwrap_or(false),
+        simavr: lib::simavr::Settings {
+            bridge: profile
+                .simavr_bridge
+                .clone()
+                .unwrap_or_else(|| String::from(lib::simavr::BRIDGE)),
+            mcu: profile
+                .simavr_mcu
+                .clone()
+                .unwrap_or_else(|| String::from(lib::simavr::DEFAULT_MCU)),
+            clock_hz: match profile
+                .simavr_clock_hz
+                .unwrap_or(lib::simavr::DEFAULT_CLOCK_HZ)
+            {
+                0 => bail!("The simulated clock must be above 0 Hz"),
+                hz => hz,
+            },
+        },
+    })
+}
+
+// Sends AT with every common framing and reports the ones answering OK
+fn probe(args: &Probe, profile: &Profile) -> Result<()> {
+    let port_args = PortArgs {
+        detect: true,
+        ..args.port.clone()
+    };
+    let config = port_config(&port_args, profile)?;
+    let dev = lib::port::resolve_dev(&config)?;
+    if lib::emulator::open(&dev, config.baud_rate).is_some() {
+        bail!("The emulator answers with every setting, there is nothing to probe");
+    }
+    if lib::simavr::firmware(&dev).is_some() {
+        bail!("The simulator's terminal takes every setting, there is nothing to probe");
+    }
+
+    info!("Probing {}", dev);
+    let tried = lib::port::detect(&config, &dev, args.all)
+        .with_context(|| format!("Unable to probe {}", dev))?;
+    let working: Vec<&lib::port::Framing> = tried
+        .iter()
+        .filter(|(_, answer)| *answer == lib::port::Answer::Ok)
+        .map(|(framing, _)| framing)
+        .collect();
+
+    match working.first() {
+        Some(framing) => {
+            info!(
+                "{}",
+                format!("{} answers OK with {}", dev, framing).green().bold()
+            );
+            if working.len() > 1 {
+                let others: Vec<String> = working[1..].iter().map(|f| f.to_string()).collect();
+                info!("Also OK: {}", others.join(", "));
+            }
+            info!(
+                "Use: --dev {} --baud-rate {} --parity {:?} --stop-bits {}, or --detect",
+                dev,
+                framing.baud_rate,
+                framing.parity,
+                match framing.stop_bits {
+                    StopBits::One => 1,
+                    StopBits::Two => 2,
+                }
+            );
+            Ok(())
+        }
+        None => bail!(
+            "No setting got a clean OK from {} ({} tried), check the wiring and the AVR's UART code",
+            dev,
+            tried.len()
+        ),
+    }
+}
+
+// Fuzzes the firmware, or replays a saved transcript with --replay
+fn fuzz(args: &Fuzz, profile: &Profile) -> Result<()> {
+    let config = port_config(&args.port, profile)?;
+    if config.crc {
+        bail!("fuzz sends raw bytes, it does not work with --crc");
+    }
+    let mut port = open_port(&config)?;
+
+    if let Some(replay) = &args.replay {
+        let case = lib::fuzz::load(Path::new(replay))
+            .with_context(|| format!("Unable to load transcript {}", replay))?;
+        let hangs = lib::fuzz::replay(&mut port, &case)
+            .with_context(|| format!("Unable to replay {}", replay))?;
+        if hangs {
+            bail!("{} still hangs the firmware", replay);
+        }
+        info!(
+            "{}",
+            format!("The firmware survives {}", replay).green().bold()
+        );
+        return Ok(());
+    }
+
+    let fuzz_config = lib::fuzz::FuzzConfig {
+        shape: Shape::from_size(args.size)?,
+        random: args.random,
+        seed: args.seed,
+        dtr_reset: args.dtr_reset,
+        dir: args.directory.clone(),
+    };
+    let report = lib::fuzz::run(&mut port, &fuzz_config)?;
+    if !report.hangs.is_empty() {
+        bail!(
+            "{} of {} sequences hung the firmware, transcripts in {}",
+            report.hangs.len(),
+            report.cases,
+            args.directory
+        );
+    }
+    info!(
+        "{}",
+        format!("The firmware survived all {} sequences", report.cases)
+            .green()
+            .bold()
+    );
+    Ok(())
+}
 
-    let port = builder
-        .open()
-        .with_context(|| format!("Unable to open port {}!", port_config.dev))?;
+// Loads the boards of a directory, then the board files, named after their paths
+fn load_board_list(
+    directory: &Option<String>,
+    files: &[String],
+) -> Result<Vec<(String, SudokuAvr)>> {
+    let mut boards = Vec::new();
+    if let Some(dir) = directory {
+        for (path, sudoku) in
+            lib::load_boards(dir).with_context(|| format!("Unable to load boards from {}", dir))?
+        {
+            boards.push((path.display().to_string(), sudoku));
+        }
+    }
+    for board in files {
+        let sudoku = SudokuAvr::load(Path::new(board))
+            .with_context(|| format!("Unable to load board {}", board))?;
+        boards.push((board.clone(), sudoku));
+    }
+    if boards.is_empty() {
+        bail!("No boards given, use --directory or --board-file");
+    }
+    Ok(boards)
+}
 
-    info!("{}", "Opened Port Successfully!!".green());
+// Every algorithm if none was given
+fn algorithms(given: &[lib::solver::Algorithm]) -> Vec<lib::solver::Algorithm> {
+    match given.is_empty() {
+        true => lib::solver::Algorithm::iter().collect(),
+        false => given.to_vec(),
+    }
+}
 
-    Ok(port)
+// Solves the boards given with every algorithm asked for
+fn solve(args: &Solve) -> Result<()> {
+    let boards = load_board_list(&args.directory, &args.boards)?;
+    lib::solver::print_stats(&boards, &algorithms(&args.algorithms), args.budget)?;
+    Ok(())
+}
+
+fn estimate(args: &Estimate) -> Result<()> {
+    if args.clock <= 0.0 {
+        bail!("The clock must be above 0 MHz");
+    }
+    let boards = load_board_list(&args.directory, &args.boards)?;
+    let results = match &args.results {
+        Some(path) => Some(
+            lib::cycles::load_results(Path::new(path))
+                .with_context(|| format!("Unable to load results {}", path))?,
+        ),
+        None => None,
+    };
+    lib::cycles::print_estimates(
+        &boards,
+        &algorithms(&args.algorithms),
+        args.budget,
+        args.clock,
+        results.as_ref(),
+    )?;
+    Ok(())
+}
+
+// Plays --number boards of every difficulty, then writes the samples and prints their statistics
+// The samples played so far are kept if a board fails
+fn bench(args: &Bench, profile: &Profile) -> Result<()> {
+    if args.number == 0 {
+        bail!("Nothing to play, --number must be at least 1");
+    }
+    let shape = Shape::from_size(args.size)?;
+    let mut port;
+    let mut target = match args.solver {
+        Some(algorithm) => lib::bench::Target::Solver {
+            algorithm,
+            budget: args.budget,
+        },
+        None => {
+            port = open_port(&port_config(&args.port, profile)?)?;
+            lib::bench::Target::Avr(&mut port)
+        }
+    };
+    let name = target.name();
+
+    let mut samples = Vec::new();
+    let mut played = || -> Result<()> {
+        for (d, difficulty) in Difficulty::iter().enumerate() {
+            for i in 0..args.number {
+                let sudoku = match args.seed {
+                    Some(seed) => {
+                        let board = d as u64 * args.number + i;
+                        SudokuAvr::new_seeded(
+                            shape,
+                            args.variant,
+                            &difficulty,
+                            seed.wrapping_add(board),
+                        )?
+                    }
+                    None => SudokuAvr::new_variant(shape, args.variant, &difficulty)?,
+                };
+                let sample = lib::bench::play(&mut target, &sudoku, i as usize)?;
+                info!(
+                    "{} board {} of {}: {}{}",
+                    difficulty,
+                    i + 1,
+                    args.number,
+                    lib::timing::format_ms(sample.time),
+                    if sample.valid {
+                        ""
+                    } else {
+                        ", no valid solution"
+                    }
+                );
+                samples.push(sample);
+            }
+        }
+        Ok(())
+    };
+    let result = played();
+
+    if !samples.is_empty() {
+        lib::bench::write_csv(Path::new(&args.csv), &name, &samples)
+            .with_context(|| format!("Unable to write {}", args.csv))?;
+        lib::bench::print_summaries(&name, &samples, args.bins);
+    }
+    result.with_context(|| format!("Benchmark stopped after {} boards", samples.len()))
+}
+
+fn open_port(port_config: &PortConfig) -> Result<Port> {
+    lib::port::open(port_config)
+        .with_context(|| format!("Unable to open port {}!", port_config.dev))
 }
 
 fn main() -> Result<()> {
@@ -243,73 +778,81 @@ fn main() -> Result<()> {
         true => log::LevelFilter::Debug,
     };
 
-    TermLogger::init(
+    let config = Config::load(opts.config.as_deref().map(Path::new))?;
+    let profile = config.profile(opts.profile.as_deref())?;
+    let log_dir = opts
+        .log_dir
+        .clone()
+        .unwrap_or_else(|| String::from(profile.log_dir()));
+
+    let session = match opts.no_log {
+        true => None,
+        false => Some(session::start(Path::new(&log_dir))),
+    };
+    let mut loggers: Vec<Box<dyn SharedLogger>> = vec![TermLogger::new(
         log_level,
         ConfigBuilder::new().set_time_to_local(true).build(),
         TerminalMode::Mixed,
         ColorChoice::Auto,
-    )
-    .expect("Failed to init logger");
+    )];
+    if let Some(Ok(_)) = session {
+        loggers.push(SessionLogger::new(log_level));
+    }
+    CombinedLogger::init(loggers).expect("Failed to init logger");
+
+    match session {
+        Some(Ok(path)) => debug!("Session log: {}", path.display()),
+        Some(Err(e)) => error!("Unable to write a session log to {}: {}", log_dir, e),
+        None => (),
+    }
+    session::record(Event::Start {
+        version: String::from(env!("CARGO_PKG_VERSION")),
+        args: std::env::args().collect(),
+    });
+
+    if let Some(name) = opts.profile.as_deref().or(config.profile.as_deref()) {
+        info!("Using profile {}", name);
+    }
+
+    let result = execute(opts.cmd, &profile);
+    session::record(Event::End {
+        ok: result.is_ok(),
+        error: result.as_ref().err().map(|e| format!("{:#}", e)),
+    });
+    result
+}
 
-    match opts.cmd {
+// Runs the chosen subcommand with the settings of profile
+fn execute(cmd: Command, profile: &Profile) -> Result<()> {
+    match cmd {
         Command::List => {
-            get_ports();
-            return Ok(());
+            get_ports(profile)?;
         }
         Command::Tour(args) => {
-            let port_config = PortConfig {
-                baud_rate: args.br,
-                stop_bits: check_stop_bits(args.sb)?,
-                data_bits: check_data_bits(args.db)?,
-                parity: check_parity(args.p)?,
-                dev: args.dev,
-            };
-            let mut port = open_port(&port_config)?;
-            lib::play_tournament(&args.directory, &args.team, &mut port)?;
+            let mut port = open_port(&port_config(&args.port, profile)?)?;
+            lib::play_tournament(
+                &args.directory,
+                &args.team,
+                profile.tournament_dir(),
+                &mut port,
+            )?;
         }
         Command::Run(args) => {
-            let port_config = PortConfig {
-                baud_rate: args.br,
-                stop_bits: check_stop_bits(args.sb)?,
-                data_bits: check_data_bits(args.db)?,
-                parity: check_parity(args.p)?,
-                dev: args.dev,
-            };
-
-            let mut port = open_port(&port_config)?;
+            let mut port = open_port(&port_config(&args.port, profile)?)?;
 
-            if let Err(e) = run(args.difficulty, &mut port) {
+            if let Err(e) = run(&args, profile, &mut port) {
                 error!("{:?}", e);
+                session::record(Event::End {
+                    ok: false,
+                    error: Some(format!("{:#}", e)),
+                });
                 std::process::exit(-1);
             }
         }
         Command::Prog(args) => {
-            let port_config = PortConfig {
-                baud_rate: args.br,
-                stop_bits: check_stop_bits(args.sb)?,
-                data_bits: check_data_bits(args.db)?,
-                parity: check_parity(args.p)?,
-                dev: args.dev,
-            };
-
-            let mut port = open_port(&port_config)?;
-            let file = File::open(&args.board)?;
-            let mut reader = BufReader::new(file);
-            let mut line = String::new();
-            let diff: lib::Difficulty;
-
-            reader.read_line(&mut line)?;
-
-            match line.replace("\n", "").as_str() {
-                "Easy" => diff = lib::Difficulty::Easy,
-                "Medium" => diff = lib::Difficulty::Medium,
-                "Hard" => diff = lib::Difficulty::Hard,
-                "Ultra" => diff = lib::Difficulty::Ultra,
-                _ => bail!("Invalid File Name"),
-            }
-            line.clear();
-            reader.read_line(&mut line)?;
-            let mut sudoku = SudokuAvr::new_from_str(&line, diff);
+            let mut port = open_port(&port_config(&args.port, profile)?)?;
+            let sudoku = SudokuAvr::load(Path::new(&args.board))
+                .with_context(|| format!("Unable to load board {}", args.board))?;
             sudoku.print_solved();
 
             write_uart(&mut port, CLEAR)?;
@@ -317,14 +860,28 @@ fn main() -> Result<()> {
             info!("{}", "Sending Board!".white().bold());
             sudoku.send_board(&mut port)?;
             port.clear(ClearBuffer::All)
-                .with_context(|| format!("Unable to Clear Buffers"))?;
+                .context("Unable to Clear Buffers")?;
+            let mut shell = Shell::new(&mut port, &args.board, sudoku, true);
+            shell.set_export_dir(profile.export_dir());
+            run_scripts(&mut shell, &args.script, &args.commands)?;
             if args.inter {
                 println!("{}", "Going Interactive".white().bold());
-                go_interactive(&mut port, &mut sudoku, true)?;
+                shell.run_interactive()?;
             }
         }
+        Command::Probe(args) => probe(&args, profile)?,
+        Command::Fuzz(args) => fuzz(&args, profile)?,
+        Command::Solve(args) => solve(&args)?,
+        Command::Estimate(args) => estimate(&args)?,
+        Command::Bench(args) => bench(&args, profile)?,
         Command::Gen(gen) => {
-            generate_boards(gen.directory, gen.number)?;
+            generate_boards(
+                gen.directory,
+                gen.number,
+                Shape::from_size(gen.size)?,
+                gen.variant,
+                gen.json,
+            )?;
         }
     }
 
@@ -356,260 +913,3 @@ fn check_parity(parity: MyParity) -> Result<Parity> {
         MyParity::None => Ok(Parity::None),
     }
 }
-
-fn ct_msg(msg: &str) -> Result<()> {
-    info!("Hit Enter when Ready!");
-
-    let mut input = String::new();
-    stdin()
-        .read_line(&mut input)
-        .with_context(|| format!("Unable to Read Line!"))?;
-
-    print!("{}", msg);
-    for i in (0..=5).rev() {
-        print!("{}...", i);
-        std::io::stdout().flush()?;
-        thread::sleep(Duration::from_millis(450));
-    }
-    println!();
-
-    Ok(())
-}
-
-fn go_interactive(port: &mut Port, sudoku: &mut lib::SudokuAvr, flag: bool) -> Result<()> {
-    let mut flag_send = flag;
-    let mut user_input = String::new();
-
-    loop {
-        print!("{}", "🤘> ".green().bold());
-        std::io::stdout().flush().expect("Couldn't Flush STDOUT");
-
-        user_input.clear();
-        stdin()
-            .read_line(&mut user_input)
-            .with_context(|| format!("Unable to Read Line!"))?;
-        let user_input_vec: Vec<&str> = user_input.split_ascii_whitespace().collect();
-
-        if user_input_vec.len() == 0 {
-            continue;
-        }
-
-        match user_input_vec[0] {
-            "at" => {
-                write_uart(port, AT)?;
-                wait_response(port, OK)?;
-            }
-            "clear" => {
-                write_uart(port, CLEAR)?;
-                wait_response(port, OK)?;
-                flag_send = false;
-            }
-            "break" => {
-                write_uart(port, BREAK)?;
-                wait_response(port, OK)?;
-            }
-            "play" => {
-                if !flag_send {
-                    error!("No board Downloaded!");
-                    continue;
-                }
-                write_uart(port, PLAY)?;
-
-                let time_now = Instant::now();
-
-                wait_response(port, OK)?;
-
-                loop {
-                    match lib::wait_response(port, DONE) {
-                        Ok(_) => break,
-                        Err(_) => continue,
-                    }
-                }
-                let time_elapsed = time_now.elapsed();
-                info!(
-                    "{}",
-                    format!("Solved in: {:?}", time_elapsed).green().bold()
-                );
-                info!("Ready to Receive the Solved Board from the AVR?");
-                ct_msg("Receiving in ")?;
-                match lib::recv_and_check(port, &sudoku) {
-                    Ok(()) => {
-                        info!("{}", format!("Valid Solution!!").green().bold());
-                        sudoku.tts = time_elapsed.as_secs();
-                    }
-                    Err(_) => info!("{}", format!("Invalid Solution! :( ").red().bold()),
-                }
-            }
-            "exit" => break,
-            "download" => {
-                if flag_send {
-                    error!("Game Already Running!");
-                    continue;
-                }
-
-                info!("Ready to Send the Unsolved Board to the AVR?");
-                ct_msg("Sending in ")?;
-                info!(
-                    "Sending Unsolved board to {:?}",
-                    port.name().expect("Couldn't Get Port Name!")
-                );
-                sudoku.send_board(port)?;
-                flag_send = true;
-            }
-            "fill" => {
-                if user_input_vec.len() > 4 {
-                    error!("Invalid Command!");
-                    continue;
-                }
-
-                let x: u8;
-                let y: u8;
-                let z: u8;
-
-                match user_input_vec[1].parse::<u8>() {
-                    Ok(num) => x = num,
-                    Err(_) => {
-                        error!("Arguments must be within 1-9");
-                        continue;
-                    }
-                }
-                match user_input_vec[2].parse::<u8>() {
-                    Ok(num) => y = num,
-                    Err(_) => {
-                        error!("Arguments must be within 1-9");
-                        continue;
-                    }
-                }
-                match user_input_vec[3].parse::<u8>() {
-                    Ok(num) => z = num,
-                    Err(_) => {
-                        error!("Arguments must be within 1-9");
-                        continue;
-                    }
-                }
-
-                if x > 9 || y > 9 || z > 9 {
-                    error!("Arguments must be within 1-9");
-                    continue;
-                }
-                write_uart(
-                    port,
-                    [b'N', x + 0x30, y + 0x30, z + 0x30, b'\x0D', b'\x0A'].as_ref(),
-                )?;
-                wait_response(port, OK)?;
-            }
-            "debug" => {
-                if user_input_vec.len() > 3 {
-                    error!("Invalid Command!");
-                    continue;
-                }
-                let x: u8;
-                let y: u8;
-
-                match user_input_vec[1].parse::<u8>() {
-                    Ok(num) => x = num,
-                    Err(_) => {
-                        error!("Arguments must be within 1-9");
-                        continue;
-                    }
-                }
-                match user_input_vec[2].parse::<u8>() {
-                    Ok(num) => y = num,
-                    Err(_) => {
-                        error!("Arguments must be within 1-9");
-                        continue;
-                    }
-                }
-
-                if x > 9 || y > 9 {
-                    error!("Arguments must be within 1-9");
-                    continue;
-                }
-                write_uart(port, [b'D', x + 0x30, y + 0x30, b'\x0D', b'\x0A'].as_ref())?;
-                let data = read_uart(port, 6)?;
-
-                info!(
-                    "{}",
-                    format!(
-                        "[{},{}]: {}",
-                        data[1] - 0x30,
-                        data[2] - 0x30,
-                        data[3] - 0x30
-                    )
-                    .yellow()
-                    .bold()
-                );
-            }
-            "solution" => sudoku.print_solved(),
-            "unsolved" => sudoku.print_unsolved(),
-            "help" | "?" => print_help(),
-            "export" => sudoku.export_board()?,
-            _ => error!("Invalid Command!"),
-        }
-    }
-    Ok(())
-}
-
-fn print_help() {
-    println!("{}", "Available Commands: ".yellow().bold());
-    println!(
-        "{}{}",
-        "at".pad_to_width(20).white().bold(),
-        "Attention".white().bold()
-    );
-    println!(
-        "{}{}",
-        "clear".pad_to_width(20).white().bold(),
-        "Clear Board".white().bold()
-    );
-    println!(
-        "{}{}",
-        "play".pad_to_width(20).white().bold(),
-        "Play Game".white().bold()
-    );
-    println!(
-        "{}{}",
-        "fill".pad_to_width(20).white().bold(),
-        "Fill Cell [x y num]".white().bold()
-    );
-    println!(
-        "{}{}",
-        "solution".pad_to_width(20).white().bold(),
-        "Print Solution".white().bold()
-    );
-    println!(
-        "{}{}",
-        "unsolved".pad_to_width(20).white().bold(),
-        "Print Board".white().bold()
-    );
-    println!(
-        "{}{}",
-        "download".pad_to_width(20).white().bold(),
-        "Download Board to AVR".white().bold()
-    );
-    println!(
-        "{}{}",
-        "break".pad_to_width(20).white().bold(),
-        "Break".white().bold()
-    );
-    println!(
-        "{}{}",
-        "debug".pad_to_width(20).white().bold(),
-        "Return the contents of a Cell [x y num]".white().bold()
-    );
-    println!(
-        "{}{}",
-        "export".pad_to_width(20).white().bold(),
-        "Export Board".white().bold()
-    );
-    println!(
-        "{}{}",
-        "exit".pad_to_width(20).white().bold(),
-        "Exit".white().bold()
-    );
-    println!(
-        "{}{}",
-        "help or ?".pad_to_width(20).white().bold(),
-        "Print this Help message".white().bold()
-    );
-}
@@ -0,0 +1,733 @@
+use colored::*;
+use log::{debug, info, warn};
+use serde::Deserialize;
+use serialport::{
+    available_ports, ClearBuffer, DataBits, Parity, SerialPort, SerialPortInfo, SerialPortType,
+    StopBits, UsbPortInfo,
+};
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+use std::ops::{Deref, DerefMut};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::emulator::{self, Emulator};
+use crate::firmware::{self, Firmware};
+use crate::link::{self, Link, LinkStats};
+use crate::session::{self, Event};
+use crate::simavr::{self, Simulation};
+use crate::{read_uart, wait_response, write_uart, Error, Result, AT, OK};
+
+// Device name picking a connected USB adapter instead of a fixed port, see auto_select()
+pub const AUTO: &str = "auto";
+// Read timeout unless the config sets one, how long a reply may take
+// (serialport's own default of 0 only worked with the fixed sleeps after every write)
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+// Read timeout while probing a candidate with AT, unless the config sets one
+const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+// How often reconnect() looks for the lost device
+const RECONNECT_POLL: Duration = Duration::from_millis(500);
+// How long a lost device may take to come back unless the config says otherwise
+pub const RECONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+// Read timeout of every attempt of detect(), a wrong framing usually stays silent
+const DETECT_TIMEOUT: Duration = Duration::from_millis(200);
+// Baud rates detect() tries, most common first
+pub const BAUD_RATES: &[u32] = &[9600, 19200, 38400, 57600, 115200, 4800, 2400, 1200];
+
+// USB adapters --dev auto looks for: vendor id, product id (None for any) and name
+const KNOWN_ADAPTERS: &[(u16, Option<u16>, &str)] = &[
+    (0x03eb, None, "Atmel (STK-500 / AVR board)"),
+    (0x0403, Some(0x6001), "FTDI FT232R USB-UART"),
+    (0x0403, Some(0x6015), "FTDI FT230X USB-UART"),
+    (0x067b, Some(0x2303), "Prolific PL2303 USB-UART"),
+    (0x10c4, Some(0xea60), "Silicon Labs CP210x USB-UART"),
+    (0x1a86, Some(0x7523), "WCH CH340 USB-UART"),
+    (0x2341, None, "Arduino"),
+];
+
+// USB adapter from the config file, e.g. { vid = 0x0403, pid = 0x6001, name = "Lab 3 FTDI" }
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Adapter {
+    pub vid: u16,
+    /* Any product of the vendor if None */
+    pub pid: Option<u16>,
+    pub name: String,
+}
+
+// Line settings of a port, shown like "9600 8N1"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Framing {
+    pub baud_rate: u32,
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+impl fmt::Display for Framing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let data_bits = match self.data_bits {
+            DataBits::Five => 5,
+            DataBits::Six => 6,
+            DataBits::Seven => 7,
+            DataBits::Eight => 8,
+        };
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Even => 'E',
+            Parity::Odd => 'O',
+        };
+        let stop_bits = match self.stop_bits {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        };
+        write!(f, "{} {}{}{}", self.baud_rate, data_bits, parity, stop_bits)
+    }
+}
+
+// What a device sent back to AT
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Answer {
+    Ok,
+    /* Anything but OK, usually the sign of a framing mismatch */
+    Garbage(Vec<u8>),
+    Silent,
+    /* The port could not be opened with the framing, some adapters refuse certain ones */
+    Error(String),
+}
+
+impl fmt::Display for Answer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Answer::Ok => write!(f, "OK"),
+            Answer::Garbage(data) => write!(f, "garbage {:02x?}", data),
+            Answer::Silent => write!(f, "no reply"),
+            Answer::Error(e) => write!(f, "unable to open: {}", e),
+        }
+    }
+}
+
+// Everything needed to open a port
+pub struct PortConfig {
+    /* Device path, the emulator or AUTO */
+    pub dev: String,
+    pub baud_rate: u32,
+    pub stop_bits: StopBits,
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    /* Read timeout, DEFAULT_TIMEOUT if None */
+    pub timeout: Option<Duration>,
+    /* Adapters AUTO looks for before the known ones */
+    pub adapters: Vec<Adapter>,
+    /* AUTO sends AT to every candidate and takes the first one answering OK */
+    pub probe: bool,
+    /* Look for the framing the device answers to, see detect() */
+    pub detect: bool,
+    /* How long a lost device may take to come back, None to fail right away */
+    pub reconnect: Option<Duration>,
+    pub pacing: Pacing,
+    /* Use bulk frames if the firmware supports them, see bulk::supported() */
+    pub bulk: bool,
+    /* Frame every line with a sequence number and a CRC, see link::Link */
+    pub crc: bool,
+    /* Never send I or W?: the firmware is taken to support the legacy per-cell commands */
+    pub legacy: bool,
+    /* How to launch simavr for "simavr:<firmware.elf>" devices */
+    pub simavr: simavr::Settings,
+}
+
+// How frames are paced when writing to the AVR
+// By default the next frame goes out as soon as the previous one is answered
+#[derive(Debug, Clone, Copy)]
+pub struct Pacing {
+    /* Sleep after every frame, for firmware that can not keep up (older versions slept 50 ms) */
+    pub delay: Option<Duration>,
+    /* Frames of a board download sent before waiting for their OKs, 1 for none in flight */
+    pub window: usize,
+}
+
+impl Default for Pacing {
+    fn default() -> Self {
+        Pacing {
+            delay: None,
+            window: 1,
+        }
+    }
+}
+
+impl PortConfig {
+    // Line settings of the config
+    pub fn framing(&self) -> Framing {
+        Framing {
+            baud_rate: self.baud_rate,
+            data_bits: self.data_bits,
+            parity: self.parity,
+            stop_bits: self.stop_bits,
+        }
+    }
+}
+
+// Open serial port (or emulator)
+// Ports of real devices remember how they were opened: when the device disappears
+// they wait for it to come back and reopen it, see reconnect()
+pub struct Port {
+    inner: Box<dyn SerialPort>,
+    /* None for ports that can not be reopened */
+    origin: Option<Origin>,
+    pacing: Pacing,
+    /* Whether the firmware takes bulk frames, None until asked (see bulk::supported()) */
+    bulk: Option<bool>,
+    /* Framed mode, see link::enable() */
+    link: Option<Link>,
+    /* What the firmware reported to I, None if it did not */
+    firmware: Option<Firmware>,
+    /* The simavr run behind a simulated port, see simavr::open() */
+    simulation: Option<Simulation>,
+    /* The emulator behind an emulated port, told the variant of every board sent */
+    emulator: Option<Emulator>,
+}
+
+// Where a port came from, enough to find and reopen its device
+struct Origin {
+    dev: String,
+    /* USB serial number, finds the adapter again under a new name */
+    serial_number: Option<String>,
+    framing: Framing,
+    timeout: Option<Duration>,
+    /* How long to wait for the device to come back, None to give up right away */
+    reconnect: Option<Duration>,
+    /* Switch the reopened device to framed mode */
+    crc: bool,
+}
+
+impl Port {
+    // Wraps a port that is never reopened (the emulator, the simulator, probing)
+    pub fn new(inner: Box<dyn SerialPort>) -> Self {
+        Port {
+            inner,
+            origin: None,
+            pacing: Pacing::default(),
+            bulk: None,
+            link: None,
+            firmware: None,
+            simulation: None,
+            emulator: None,
+        }
+    }
+
+    pub fn firmware(&self) -> Option<&Firmware> {
+        self.firmware.as_ref()
+    }
+
+    // Whether the firmware supports a command (as in the protocol table)
+    // Firmware that did not identify itself is assumed to support all of them
+    pub fn supports(&self, command: &str) -> bool {
+        self.firmware
+            .as_ref()
+            .is_none_or(|firmware| firmware.supports(command))
+    }
+
+    pub fn set_link(&mut self, link: Option<Link>) {
+        self.link = link;
+    }
+
+    pub fn simulation(&self) -> Option<&Simulation> {
+        self.simulation.as_ref()
+    }
+
+    pub fn set_simulation(&mut self, simulation: Option<Simulation>) {
+        self.simulation = simulation;
+    }
+
+    pub fn emulator(&self) -> Option<&Emulator> {
+        self.emulator.as_ref()
+    }
+
+    pub fn set_emulator(&mut self, emulator: Option<Emulator>) {
+        self.emulator = emulator;
+    }
+
+    pub fn link_stats(&self) -> Option<LinkStats> {
+        self.link.as_ref().map(|link| link.stats)
+    }
+
+    // Clears the port's buffers, with the payload a framed link received but was not read yet
+    pub fn clear(&mut self, buffer: ClearBuffer) -> serialport::Result<()> {
+        if let (Some(link), ClearBuffer::Input | ClearBuffer::All) = (self.link.as_mut(), buffer) {
+            link.clear_input();
+        }
+        self.inner.clear(buffer)
+    }
+
+    // Bytes ready to be read, on a framed link only the payload already received
+    pub fn bytes_to_read(&self) -> serialport::Result<u32> {
+        match &self.link {
+            Some(link) => Ok(link.buffered() as u32),
+            None => self.inner.bytes_to_read(),
+        }
+    }
+
+    // Runs f with the link taken out of the port, so that it can use the port as its wire
+    // A reconnect resets the link, the firmware starts over as well
+    fn with_link<T>(
+        &mut self,
+        mut link: Link,
+        f: impl FnOnce(&mut Link, &mut Wire) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let result = f(&mut link, &mut Wire(self));
+        if let Err(e) = &result {
+            if crate::error::is_reconnected(e) {
+                link.reset();
+            }
+        }
+        self.link = Some(link);
+        result
+    }
+
+    pub fn bulk(&self) -> Option<bool> {
+        self.bulk
+    }
+
+    pub fn set_bulk(&mut self, bulk: Option<bool>) {
+        self.bulk = bulk;
+    }
+
+    pub fn pacing(&self) -> Pacing {
+        self.pacing
+    }
+
+    pub fn set_pacing(&mut self, pacing: Pacing) {
+        self.pacing = pacing;
+    }
+
+    // true if e means the device is gone rather than slow
+    fn is_lost(&self, e: &io::Error) -> bool {
+        let reconnects = self
+            .origin
+            .as_ref()
+            .is_some_and(|origin| origin.reconnect.is_some());
+        reconnects
+            && !matches!(
+                e.kind(),
+                ErrorKind::TimedOut | ErrorKind::Interrupted | ErrorKind::WouldBlock
+            )
+    }
+
+    // Waits for the lost device to reappear (by USB serial number, else by name),
+    // reopens it with the same settings and checks that the AVR answers AT
+    pub fn reconnect(&mut self) -> Result<()> {
+        let origin = match &self.origin {
+            Some(origin) => origin,
+            None => {
+                return Err(Error::NoDevice(String::from(
+                    "the port can not be reopened",
+                )))
+            }
+        };
+        let wait = origin.reconnect.unwrap_or_default();
+        warn!(
+            "{}",
+            format!(
+                "Lost {}, waiting up to {:?} for it to come back",
+                origin.dev, wait
+            )
+            .yellow()
+            .bold()
+        );
+
+        let started = Instant::now();
+        while started.elapsed() < wait {
+            thread::sleep(RECONNECT_POLL);
+            let dev = match find_again(origin) {
+                Some(dev) => dev,
+                None => continue,
+            };
+            let mut port = match open_device(&dev, &origin.framing, Some(PROBE_TIMEOUT)) {
+                Ok(port) => Port::new(port),
+                Err(e) => {
+                    debug!("Unable to reopen {}: {}", dev, e);
+                    continue;
+                }
+            };
+            let answer = port
+                .clear(ClearBuffer::All)
+                .map_err(Error::from)
+                .and_then(|_| write_uart(&mut port, AT))
+                .and_then(|_| wait_response(&mut port, OK));
+            let answer = match (answer, origin.crc) {
+                (Ok(()), true) => {
+                    write_uart(&mut port, link::ENABLE).and_then(|_| wait_response(&mut port, OK))
+                }
+                (answer, _) => answer,
+            };
+            if let Err(e) = answer {
+                debug!("{} is back but did not answer AT: {}", dev, e);
+                continue;
+            }
+            port.set_timeout(origin.timeout.unwrap_or(DEFAULT_TIMEOUT))?;
+
+            info!("{}", format!("Reconnected to {}", dev).green().bold());
+            session::record(Event::Reconnect {
+                dev: dev.clone(),
+                ms: started.elapsed().as_millis(),
+            });
+            std::mem::swap(&mut self.inner, &mut port.inner);
+            if let Some(origin) = self.origin.as_mut() {
+                origin.dev = dev;
+            }
+            return Ok(());
+        }
+        Err(Error::NoDevice(format!(
+            "{} did not come back within {:?}",
+            origin.dev, wait
+        )))
+    }
+
+    // Reconnects after a lost device, the operation that failed is reported as Error::Reconnected
+    // (a ConnectionReset wrapping it, see Error's From<io::Error>) since its data may be lost
+    fn recover(&mut self, e: io::Error) -> io::Error {
+        if !self.is_lost(&e) {
+            return e;
+        }
+        debug!("Device error: {}", e);
+        match self.reconnect() {
+            Ok(()) => io::Error::new(ErrorKind::ConnectionReset, Error::Reconnected),
+            Err(e) => io::Error::new(ErrorKind::NotConnected, e),
+        }
+    }
+}
+
+impl Deref for Port {
+    type Target = dyn SerialPort;
+
+    fn deref(&self) -> &Self::Target {
+        self.inner.as_ref()
+    }
+}
+
+impl DerefMut for Port {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.inner.as_mut()
+    }
+}
+
+impl Read for Port {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.link.take() {
+            Some(link) => self.with_link(link, |link, wire| link.read(wire, buf)),
+            None => Wire(self).read(buf),
+        }
+    }
+}
+
+impl Write for Port {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.link.take() {
+            Some(link) => self.with_link(link, |link, wire| link.write(wire, buf)),
+            None => Wire(self).write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Wire(self).flush()
+    }
+}
+
+// Reports the statistics of a framed link once the port is closed
+impl Drop for Port {
+    fn drop(&mut self) {
+        if let Some(stats) = self.link_stats() {
+            info!("Link: {}", stats);
+            session::record(Event::Link(stats));
+        }
+    }
+}
+
+// The device itself under a port, reconnecting when it is lost
+struct Wire<'a>(&'a mut Port);
+
+impl Read for Wire<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.inner.read(buf).map_err(|e| self.0.recover(e))
+    }
+}
+
+impl Write for Wire<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.inner.write(buf).map_err(|e| self.0.recover(e))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.inner.flush().map_err(|e| self.0.recover(e))
+    }
+}
+
+// Name the lost device has now: the port with its USB serial number, else its old name once it is listed again
+// Ports are looked up by name rather than as paths, COM ports are not files on Windows
+fn find_again(origin: &Origin) -> Option<String> {
+    let ports = available_ports().ok()?;
+    ports
+        .into_iter()
+        .find_map(|info| match (&origin.serial_number, info.port_type) {
+            (Some(serial), SerialPortType::UsbPort(usb))
+                if usb.serial_number.as_ref() == Some(serial) =>
+            {
+                Some(info.port_name)
+            }
+            (None, _) if info.port_name == origin.dev => Some(info.port_name),
+            _ => None,
+        })
+}
+
+// USB serial number of the adapter behind dev, if it has one
+fn serial_number(dev: &str) -> Option<String> {
+    available_ports()
+        .ok()?
+        .into_iter()
+        .find(|info| info.port_name == dev)
+        .and_then(|info| match info.port_type {
+            SerialPortType::UsbPort(usb) => usb.serial_number,
+            _ => None,
+        })
+}
+
+// Opens the device (or the emulator / simulator, see emulator::open() and simavr::open())
+// and records it in the session log
+// AUTO opens the adapter picked by auto_select(), with detect set the framing comes from detect()
+pub fn open(config: &PortConfig) -> Result<Port> {
+    let dev = resolve_dev(config)?;
+    let mut framing = config.framing();
+    let software = emulator::open(&dev, config.baud_rate).or_else(|| simavr::open(&dev, config));
+    let mut port = match software {
+        Some(port) => port?,
+        None => {
+            if config.detect {
+                framing = match detect(config, &dev, false)?.pop() {
+                    Some((framing, Answer::Ok)) => framing,
+                    _ => return Err(Error::NoDevice(format!("{} did not answer AT", dev))),
+                };
+            }
+            let inner = open_device(&dev, &framing, config.timeout)?;
+            info!("{}", "Opened Port Successfully!!".green());
+            Port {
+                inner,
+                origin: Some(Origin {
+                    dev: dev.clone(),
+                    serial_number: serial_number(&dev),
+                    framing,
+                    timeout: config.timeout,
+                    reconnect: config.reconnect,
+                    crc: config.crc,
+                }),
+                pacing: Pacing::default(),
+                bulk: None,
+                link: None,
+                firmware: None,
+                simulation: None,
+                emulator: None,
+            }
+        }
+    };
+    port.set_pacing(config.pacing);
+    if config.legacy {
+        info!("Assuming the legacy command set without asking the firmware");
+    } else {
+        port.firmware = firmware::identify(&mut port)?;
+        match &port.firmware {
+            Some(firmware) => {
+                info!("Firmware: {}", firmware);
+                session::record(Event::Firmware(firmware.clone()));
+            }
+            None => info!("Firmware did not identify itself, assuming the legacy command set"),
+        }
+    }
+    if !config.bulk || config.legacy {
+        port.set_bulk(Some(false));
+    } else if let Some(firmware) = &port.firmware {
+        port.set_bulk(Some(firmware.supports("W") && firmware.supports("Q")));
+    }
+    if config.crc {
+        if config.legacy || !port.supports("F") {
+            return Err(Error::Config(String::from(
+                "--crc needs firmware supporting framed mode (F)",
+            )));
+        }
+        link::enable(&mut port)?;
+        info!("Framed mode with CRC-16 enabled");
+    }
+    session::record(Event::Port {
+        dev,
+        baud_rate: framing.baud_rate,
+        data_bits: format!("{:?}", framing.data_bits),
+        stop_bits: format!("{:?}", framing.stop_bits),
+        parity: format!("{:?}", framing.parity),
+    });
+
+    Ok(port)
+}
+
+// Device the config names, the one auto_select() picks for AUTO
+pub fn resolve_dev(config: &PortConfig) -> Result<String> {
+    match config.dev.as_str() {
+        AUTO => auto_select(config),
+        dev => Ok(String::from(dev)),
+    }
+}
+
+fn open_device(
+    dev: &str,
+    framing: &Framing,
+    timeout: Option<Duration>,
+) -> Result<Box<dyn SerialPort>> {
+    let builder = serialport::new(dev, framing.baud_rate)
+        .stop_bits(framing.stop_bits)
+        .data_bits(framing.data_bits)
+        .parity(framing.parity);
+    Ok(builder.timeout(timeout.unwrap_or(DEFAULT_TIMEOUT)).open()?)
+}
+
+// Name of the adapter behind a USB port, checking the config's adapters first
+pub fn adapter_name(usb: &UsbPortInfo, adapters: &[Adapter]) -> Option<String> {
+    let configured = adapters
+        .iter()
+        .find(|a| a.vid == usb.vid && a.pid.is_none_or(|pid| pid == usb.pid))
+        .map(|a| a.name.clone());
+    configured.or_else(|| {
+        KNOWN_ADAPTERS
+            .iter()
+            .find(|(vid, pid, _)| *vid == usb.vid && pid.is_none_or(|pid| pid == usb.pid))
+            .map(|(_, _, name)| String::from(*name))
+    })
+}
+
+// Ports of recognised USB adapters with the adapter's name, in the order serialport lists them
+pub fn candidates(adapters: &[Adapter]) -> Result<Vec<(SerialPortInfo, String)>> {
+    Ok(available_ports()?
+        .into_iter()
+        .filter_map(|info| match &info.port_type {
+            SerialPortType::UsbPort(usb) => {
+                adapter_name(usb, adapters).map(|name| (info.clone(), name))
+            }
+            _ => None,
+        })
+        .collect())
+}
+
+// Picks the port for AUTO among the recognised adapters
+// Without probing the first one wins, with probing the first one answering AT
+fn auto_select(config: &PortConfig) -> Result<String> {
+    let found = candidates(&config.adapters)?;
+    if found.is_empty() {
+        return Err(Error::NoDevice(String::from(
+            "no known USB-UART adapter or STK-500 is connected",
+        )));
+    }
+
+    if !config.probe {
+        let (info, name) = &found[0];
+        if found.len() > 1 {
+            let others: Vec<&str> = found[1..]
+                .iter()
+                .map(|(info, _)| info.port_name.as_str())
+                .collect();
+            warn!(
+                "Several adapters found, using {} (also: {}); --probe picks the one answering AT",
+                info.port_name,
+                others.join(", ")
+            );
+        }
+        info!("Using {} ({})", info.port_name, name);
+        return Ok(info.port_name.clone());
+    }
+
+    for (info, name) in &found {
+        info!("Probing {} ({})", info.port_name, name);
+        match probe(config, &info.port_name) {
+            Ok(()) => {
+                info!("Using {} ({})", info.port_name, name);
+                return Ok(info.port_name.clone());
+            }
+            Err(e) => debug!("No answer from {}: {}", info.port_name, e),
+        }
+    }
+    Err(Error::NoDevice(String::from(
+        "no adapter answered AT with OK",
+    )))
+}
+
+// Opens dev with the settings of config and checks that it answers AT with OK
+fn probe(config: &PortConfig, dev: &str) -> Result<()> {
+    let timeout = config.timeout.unwrap_or(PROBE_TIMEOUT);
+    match ask(dev, &config.framing(), timeout)? {
+        Answer::Ok => Ok(()),
+        Answer::Garbage(data) => Err(Error::protocol(OK, &data)),
+        Answer::Silent => Err(Error::Timeout),
+        Answer::Error(e) => Err(Error::NoDevice(format!("{}: {}", dev, e))),
+    }
+}
+
+// Opens dev with framing, sends AT and classifies the reply
+// A port that does not open with framing is an answer too, see Answer::Error
+fn ask(dev: &str, framing: &Framing, timeout: Duration) -> Result<Answer> {
+    let mut port = match open_device(dev, framing, Some(timeout)) {
+        Ok(inner) => Port::new(inner),
+        Err(e) => return Ok(Answer::Error(e.to_string())),
+    };
+    port.clear(ClearBuffer::All)?;
+    write_uart(&mut port, AT)?;
+    match read_uart(&mut port, OK.len() as i32) {
+        Ok(data) if data == OK => Ok(Answer::Ok),
+        Ok(data) if data.is_empty() => Ok(Answer::Silent),
+        Ok(data) => Ok(Answer::Garbage(data)),
+        Err(Error::Timeout) => Ok(Answer::Silent),
+        Err(e) => Err(e),
+    }
+}
+
+// Sends AT to dev with every common baud rate, parity and stop bits (data bits as configured)
+// The config's own framing goes first; without all it stops at the first clean OK
+// returns every framing tried with its answer, in order
+pub fn detect(config: &PortConfig, dev: &str, all: bool) -> Result<Vec<(Framing, Answer)>> {
+    let first = config.framing();
+    let mut framings = vec![first];
+    for &baud_rate in BAUD_RATES {
+        for parity in [Parity::None, Parity::Even, Parity::Odd] {
+            for stop_bits in [StopBits::One, StopBits::Two] {
+                let framing = Framing {
+                    baud_rate,
+                    data_bits: config.data_bits,
+                    parity,
+                    stop_bits,
+                };
+                if framing != first {
+                    framings.push(framing);
+                }
+            }
+        }
+    }
+
+    let timeout = config.timeout.unwrap_or(DETECT_TIMEOUT);
+    let mut tried = Vec::new();
+    for framing in framings {
+        let answer = ask(dev, &framing, timeout)?;
+        match answer {
+            Answer::Ok => info!("{} {}", framing, "OK".green().bold()),
+            _ => info!("{} {}", framing, answer.to_string().red()),
+        }
+        let done = answer == Answer::Ok && !all;
+        tried.push((framing, answer));
+        if done {
+            break;
+        }
+    }
+    // Not a framing the adapter refuses but a port that does not open at all
+    if let Some((_, Answer::Error(e))) = tried.first() {
+        if tried
+            .iter()
+            .all(|(_, answer)| matches!(answer, Answer::Error(_)))
+        {
+            return Err(Error::NoDevice(format!("{}: {}", dev, e)));
+        }
+    }
+    Ok(tried)
+}
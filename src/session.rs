@@ -0,0 +1,265 @@
+use chrono::Local;
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::Serialize;
+use simplelog::{Config, SharedLogger};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::bench::Summary;
+use crate::cycles::Ops;
+use crate::firmware::Firmware;
+use crate::link::LinkStats;
+use crate::solver::{Algorithm, Stats};
+use crate::timing::Progress;
+use crate::{Result, SudokuAvr, Timing};
+
+// Directory session logs go to unless --log-dir says otherwise
+pub const DEFAULT_DIR: &str = "sessions";
+
+// The session log of this run, None until start() succeeds
+static SESSION: Mutex<Option<BufWriter<File>>> = Mutex::new(None);
+
+// One line of the session log
+// Every line carries the local time and the kind of event, e.g.
+// {"time":"2022-01-10T12:00:00.000+02:00","event":"play","ms":1520,"valid":true,...}
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    // Program version and command line of the run
+    Start {
+        version: String,
+        args: Vec<String>,
+    },
+    // Serial port settings once the port is open
+    Port {
+        dev: String,
+        baud_rate: u32,
+        data_bits: String,
+        stop_bits: String,
+        parity: String,
+    },
+    // Board downloaded to the AVR, cells as in board files
+    Board {
+        difficulty: String,
+        variant: String,
+        size: usize,
+        cells: String,
+        layout: Option<String>,
+    },
+    // Shell command with the AVR's reply (if it sent one) or the error it failed with
+    Command {
+        words: Vec<String>,
+        reply: Option<String>,
+        error: Option<String>,
+        ms: u128,
+    },
+    // Board download with its size and how long it took
+    Download {
+        frames: usize,
+        bytes: usize,
+        ms: u128,
+    },
+    // Identity the firmware reported to I
+    Firmware(Firmware),
+    // Statistics of a framed link, once its port is closed
+    Link(LinkStats),
+    // Lost device found again after ms and reopened
+    Reconnect {
+        dev: String,
+        ms: u128,
+    },
+    // Sequence hanging the firmware, minimised to steps writes of bytes and saved to transcript
+    Hang {
+        case: String,
+        steps: usize,
+        bytes: usize,
+        transcript: String,
+    },
+    // Progress report of the AVR while solving, see timing::Progress
+    Progress(Progress),
+    // Time the AVR took to solve a board (raw_ms without the latency subtracted)
+    // and whether its solution was valid, cycles if it ran under simavr (ms then comes from them)
+    Play {
+        difficulty: String,
+        variant: String,
+        ms: u128,
+        raw_ms: u128,
+        latency_us: u128,
+        cycles: Option<u64>,
+        valid: bool,
+    },
+    // Board solved on the host by solve, us it took and whether the solution was valid
+    Solve {
+        board: String,
+        algorithm: Algorithm,
+        #[serde(flatten)]
+        stats: Stats,
+        us: u128,
+        solved: bool,
+    },
+    // Times of the boards of one difficulty played by bench
+    Bench {
+        target: String,
+        difficulty: String,
+        #[serde(flatten)]
+        summary: Summary,
+    },
+    // ATmega16 cost of a host side solve estimated by estimate, with the tournament time if known
+    Estimate {
+        board: String,
+        algorithm: Algorithm,
+        #[serde(flatten)]
+        ops: Ops,
+        cycles: u64,
+        estimate_us: u128,
+        sram: u64,
+        avr_us: Option<u128>,
+    },
+    // Line of the terminal output
+    Log {
+        level: String,
+        target: String,
+        message: String,
+    },
+    // End of the run, error is set if it failed
+    End {
+        ok: bool,
+        error: Option<String>,
+    },
+}
+
+impl Event {
+    // Board event for a board about to be downloaded
+    pub fn board(sudoku: &SudokuAvr) -> Self {
+        Event::Board {
+            difficulty: sudoku.dif.to_string(),
+            variant: sudoku.variant().to_string(),
+            size: sudoku.size(),
+            cells: sudoku.to_string(),
+            layout: sudoku.variant().layout_line(sudoku.shape()),
+        }
+    }
+
+    // Play event for a board the AVR solved, see timing::play()
+    pub fn play(sudoku: &SudokuAvr, timing: &Timing, valid: bool) -> Self {
+        Event::Play {
+            difficulty: sudoku.dif.to_string(),
+            variant: sudoku.variant().to_string(),
+            ms: timing.solve().as_millis(),
+            raw_ms: timing.raw.as_millis(),
+            latency_us: timing.latency.as_micros(),
+            cycles: timing.simulated.map(|simulated| simulated.cycles),
+            valid,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    time: String,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+// Opens a new session log in dir (created if needed) and records every event from now on
+// The file is named after the local time and the process id: session_<date>_<time>_<pid>.jsonl
+pub fn start(dir: &Path) -> Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let name = format!(
+        "session_{}_{}.jsonl",
+        Local::now().format("%Y%m%d_%H%M%S"),
+        std::process::id()
+    );
+    let path = dir.join(name);
+    let file = OpenOptions::new().create(true).append(true).open(&path)?;
+    *lock() = Some(BufWriter::new(file));
+    Ok(path)
+}
+
+// Appends an event to the session log, does nothing if there is none
+// Write errors are dropped: they must not stop a run, and logging them would come back here
+pub fn record(event: Event) {
+    let mut session = lock();
+    if let Some(out) = session.as_mut() {
+        let line = Line {
+            time: Local::now().to_rfc3339(),
+            event: &event,
+        };
+        if let Ok(json) = serde_json::to_string(&line) {
+            let _ = writeln!(out, "{}", json).and_then(|_| out.flush());
+        }
+    }
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Option<BufWriter<File>>> {
+    SESSION.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Removes the terminal color codes from a message
+fn strip_colors(message: &str) -> String {
+    let mut out = String::with_capacity(message.len());
+    let mut chars = message.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip up to and including the final letter of the escape sequence
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+// Logger copying every log line into the session log as a Log event
+// Meant to be combined with the TermLogger, see simplelog::CombinedLogger
+pub struct SessionLogger {
+    level: LevelFilter,
+}
+
+impl SessionLogger {
+    pub fn new(level: LevelFilter) -> Box<Self> {
+        Box::new(SessionLogger { level })
+    }
+}
+
+impl Log for SessionLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, entry: &Record) {
+        if self.enabled(entry.metadata()) {
+            record(Event::Log {
+                level: entry.level().to_string(),
+                target: entry.target().to_string(),
+                message: strip_colors(&entry.args().to_string()),
+            });
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(out) = lock().as_mut() {
+            let _ = out.flush();
+        }
+    }
+}
+
+impl SharedLogger for SessionLogger {
+    fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    fn config(&self) -> Option<&Config> {
+        None
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        Box::new(*self)
+    }
+}
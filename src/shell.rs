@@ -0,0 +1,1066 @@
+use colored::*;
+use log::{debug, error, info, warn};
+use pad::PadStr;
+use rand::{thread_rng, Rng};
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{stdin, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+use strum::IntoEnumIterator;
+
+use crate::{
+    cell_frame, debug_frame, parse_cell_frame, read_frame, recv_and_check, recv_cages, session,
+    timing, wait_response, write_uart, Difficulty, Error, Event, Firmware, Port, Result, SudokuAvr,
+    Variant, BREAK, CLEAR, OK,
+};
+
+// Prompt of the interactive shell
+const PROMPT: &str = "🤘> ";
+// History file, kept in the home directory
+const HISTORY_FILE: &str = ".ace411_sudoku_history";
+
+// What an argument of a command is, used for tab completion
+#[derive(Clone, Copy)]
+enum Arg {
+    // 1 based column or row
+    Coord,
+    // Cell value, 0 clears the Cell
+    Value,
+    // Board file or script path
+    File,
+    // Name of another command
+    Command,
+    // Difficulty level of a generated board
+    Difficulty,
+    // 1 based index into the boards of the session
+    Board,
+}
+
+// Entry of the command table, drives both help and tab completion
+struct CommandInfo {
+    names: &'static [&'static str],
+    args: &'static [Arg],
+    usage: &'static str,
+    help: &'static str,
+    /* Protocol commands the firmware has to support, see Port::supports() */
+    needs: &'static [&'static str],
+}
+
+// Every shell command, in the order help lists them
+const COMMANDS: &[CommandInfo] = &[
+    CommandInfo {
+        names: &["at"],
+        args: &[],
+        usage: "",
+        help: "Attention",
+        needs: &["AT"],
+    },
+    CommandInfo {
+        names: &["clear"],
+        args: &[],
+        usage: "",
+        help: "Clear Board",
+        needs: &["C"],
+    },
+    CommandInfo {
+        names: &["play"],
+        args: &[],
+        usage: "",
+        help: "Play Game",
+        needs: &["P"],
+    },
+    CommandInfo {
+        names: &["fill"],
+        args: &[Arg::Coord, Arg::Coord, Arg::Value],
+        usage: "[x y num]",
+        help: "Fill Cell",
+        needs: &["N"],
+    },
+    CommandInfo {
+        names: &["solution"],
+        args: &[],
+        usage: "",
+        help: "Print Solution",
+        needs: &[],
+    },
+    CommandInfo {
+        names: &["unsolved"],
+        args: &[],
+        usage: "",
+        help: "Print Board",
+        needs: &[],
+    },
+    CommandInfo {
+        names: &["download"],
+        args: &[],
+        usage: "",
+        help: "Download Board to AVR (Killer boards also need K)",
+        needs: &["N"],
+    },
+    CommandInfo {
+        names: &["break"],
+        args: &[],
+        usage: "",
+        help: "Break",
+        needs: &["B"],
+    },
+    CommandInfo {
+        names: &["debug"],
+        args: &[Arg::Coord, Arg::Coord],
+        usage: "[x y]",
+        help: "Return the contents of a Cell",
+        needs: &["D"],
+    },
+    CommandInfo {
+        names: &["dump"],
+        args: &[],
+        usage: "",
+        help: "Read every Cell from the AVR and compare it to the board",
+        needs: &["D"],
+    },
+    CommandInfo {
+        names: &["cages"],
+        args: &[],
+        usage: "",
+        help: "Read back and check the Killer cages",
+        needs: &["R"],
+    },
+    CommandInfo {
+        names: &["load"],
+        args: &[Arg::File],
+        usage: "[file]",
+        help: "Load a board file and make it active",
+        needs: &[],
+    },
+    CommandInfo {
+        names: &["gen"],
+        args: &[Arg::Difficulty],
+        usage: "[difficulty seed]",
+        help: "Generate a board like the active one and make it active",
+        needs: &[],
+    },
+    CommandInfo {
+        names: &["list"],
+        args: &[Arg::File],
+        usage: "[dir]",
+        help: "List the boards of the session, or the board files in dir",
+        needs: &[],
+    },
+    CommandInfo {
+        names: &["switch"],
+        args: &[Arg::Board],
+        usage: "[n]",
+        help: "Make board n of the session active",
+        needs: &[],
+    },
+    CommandInfo {
+        names: &["send"],
+        args: &[],
+        usage: "[frame]",
+        help: "Send a raw frame and print the reply",
+        needs: &[],
+    },
+    CommandInfo {
+        names: &["set"],
+        args: &[],
+        usage: "[name value]",
+        help: "Set a variable, used as $name",
+        needs: &[],
+    },
+    CommandInfo {
+        names: &["expect"],
+        args: &[],
+        usage: "[reply]",
+        help: "Fail unless the last reply matches",
+        needs: &[],
+    },
+    CommandInfo {
+        names: &["echo"],
+        args: &[],
+        usage: "[text]",
+        help: "Print a message",
+        needs: &[],
+    },
+    CommandInfo {
+        names: &["for"],
+        args: &[],
+        usage: "[var in files...]",
+        help: "Loop over board files until end (scripts only)",
+        needs: &[],
+    },
+    CommandInfo {
+        names: &["export"],
+        args: &[],
+        usage: "",
+        help: "Export Board",
+        needs: &[],
+    },
+    CommandInfo {
+        names: &["exit"],
+        args: &[],
+        usage: "",
+        help: "Exit",
+        needs: &[],
+    },
+    CommandInfo {
+        names: &["help", "?"],
+        args: &[Arg::Command],
+        usage: "[command]",
+        help: "Print this Help message",
+        needs: &[],
+    },
+];
+
+// Looks up a command by any of its names
+fn command_info(name: &str) -> Option<&'static CommandInfo> {
+    COMMANDS.iter().find(|info| info.names.contains(&name))
+}
+
+// Whether the firmware supports everything a command needs, unknown firmware supports all
+fn available(info: &CommandInfo, firmware: Option<&Firmware>) -> bool {
+    firmware.is_none_or(|firmware| info.needs.iter().all(|c| firmware.supports(c)))
+}
+
+// rustyline helper: completes commands and their arguments, colors the prompt
+struct ShellHelper {
+    /* Side length of the current board, for coordinates and values */
+    size: usize,
+    /* Number of boards in the session */
+    boards: usize,
+    /* Commands it does not support are not completed */
+    firmware: Option<Firmware>,
+    files: FilenameCompleter,
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let start = before.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let word = &before[start..];
+        let previous: Vec<&str> = before[..start].split_whitespace().collect();
+
+        let command_names = || {
+            COMMANDS
+                .iter()
+                .filter(|info| available(info, self.firmware.as_ref()))
+                .flat_map(|info| info.names.iter())
+                .map(|name| name.to_string())
+                .collect::<Vec<String>>()
+        };
+        let options: Vec<String> = match previous.split_first() {
+            None => command_names(),
+            Some((cmd, args)) => match command_info(cmd).and_then(|info| info.args.get(args.len()))
+            {
+                Some(Arg::File) => return self.files.complete(line, pos, ctx),
+                Some(Arg::Coord) => (1..=self.size).map(|v| v.to_string()).collect(),
+                Some(Arg::Value) => (0..=self.size).map(|v| v.to_string()).collect(),
+                Some(Arg::Command) => command_names(),
+                Some(Arg::Difficulty) => Difficulty::iter().map(|d| d.to_string()).collect(),
+                Some(Arg::Board) => (1..=self.boards).map(|n| n.to_string()).collect(),
+                None => Vec::new(),
+            },
+        };
+
+        let candidates = options
+            .into_iter()
+            .filter(|option| option.starts_with(word))
+            .map(|option| Pair {
+                display: option.clone(),
+                replacement: option,
+            })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {
+    fn highlight_prompt<'b, 's: 'b, 'p: 'b>(
+        &'s self,
+        prompt: &'p str,
+        _default: bool,
+    ) -> Cow<'b, str> {
+        Cow::Owned(prompt.green().bold().to_string())
+    }
+}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+// History file in the home directory, None if there is no home directory
+fn history_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(HISTORY_FILE))
+}
+
+// A parsed script line: a command, or a loop over files with its body
+enum Step {
+    Command(Vec<String>),
+    For {
+        var: String,
+        patterns: Vec<String>,
+        body: Vec<Step>,
+    },
+}
+
+// A board of the session and where it came from
+struct Entry {
+    /* Board file, or how the board was generated */
+    name: String,
+    sudoku: SudokuAvr,
+}
+
+// Interactive / scripted shell speaking the Hayes command set to the AVR
+// Interactive errors are logged and the shell keeps going, script errors stop the script
+pub struct Shell<'a> {
+    port: &'a mut Port,
+    /* Every board loaded or generated in this session, never empty */
+    boards: Vec<Entry>,
+    /* Index of the board the commands work on */
+    active: usize,
+    /* Index of the board on the AVR, None until one is downloaded or after clear */
+    sent: Option<usize>,
+    /* false while running a script: no prompts, countdowns or confirmations */
+    interactive: bool,
+    /* Variables set with `set`, expanded as $NAME or ${NAME} */
+    vars: HashMap<String, String>,
+    /* Last reply of the AVR, checked by `expect` */
+    reply: String,
+    /* Reply of the command running, None if it got none */
+    replied: Option<String>,
+    /* Where `export` writes boards */
+    export_dir: String,
+}
+
+impl<'a> Shell<'a> {
+    // name describes where sudoku came from, sent tells whether it is already on the AVR
+    pub fn new(port: &'a mut Port, name: &str, sudoku: SudokuAvr, sent: bool) -> Self {
+        Shell {
+            port,
+            boards: vec![Entry {
+                name: String::from(name),
+                sudoku,
+            }],
+            active: 0,
+            sent: sent.then_some(0),
+            interactive: true,
+            vars: HashMap::new(),
+            reply: String::new(),
+            replied: None,
+            export_dir: String::from(crate::config::EXPORT_DIR),
+        }
+    }
+
+    // Directory `export` writes to
+    pub fn set_export_dir(&mut self, dir: &str) {
+        self.export_dir = String::from(dir);
+    }
+
+    // The active board
+    pub fn sudoku(&self) -> &SudokuAvr {
+        &self.boards[self.active].sudoku
+    }
+
+    // true if the active board is the one on the AVR
+    fn is_sent(&self) -> bool {
+        self.sent == Some(self.active)
+    }
+
+    // Adds a board to the session and makes it active
+    // The AVR still holds the previous board, so the new one is not sent yet
+    fn add_board(&mut self, name: String, sudoku: SudokuAvr) {
+        info!("Board {}: {}", self.boards.len() + 1, name);
+        sudoku.print_unsolved();
+        self.boards.push(Entry { name, sudoku });
+        self.active = self.boards.len() - 1;
+    }
+
+    // Reads commands from the line editor until exit, Ctrl-D or end of input
+    // History is loaded from and saved to the home directory
+    pub fn run_interactive(&mut self) -> Result<()> {
+        self.interactive = true;
+        let mut editor: Editor<ShellHelper> = Editor::new();
+        editor.set_helper(Some(ShellHelper {
+            size: self.sudoku().size(),
+            boards: self.boards.len(),
+            firmware: self.port.firmware().cloned(),
+            files: FilenameCompleter::new(),
+        }));
+        let history = history_path();
+        if let Some(path) = &history {
+            if editor.load_history(path).is_err() {
+                debug!("No history at {}", path.display());
+            }
+        }
+
+        loop {
+            if let Some(helper) = editor.helper_mut() {
+                helper.size = self.sudoku().size();
+                helper.boards = self.boards.len();
+            }
+            let user_input = match editor.readline(PROMPT) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => {
+                    println!();
+                    break;
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    break;
+                }
+            };
+            if !user_input.trim().is_empty() {
+                editor.add_history_entry(user_input.trim());
+            }
+
+            let words = match shlex::split(&user_input) {
+                Some(words) => words,
+                None => {
+                    error!("Unbalanced quotes!");
+                    continue;
+                }
+            };
+            if words.first().is_some_and(|w| w == "for" || w == "end") {
+                error!("Loops are only available in scripts!");
+                continue;
+            }
+            let result = words
+                .iter()
+                .map(|word| self.expand(word))
+                .collect::<Result<Vec<String>>>()
+                .and_then(|words| self.execute(&words));
+            match result {
+                Ok(true) => break,
+                Ok(false) => (),
+                Err(e) => error!("{}", e),
+            }
+        }
+
+        if let Some(path) = &history {
+            if let Err(e) = editor.save_history(path) {
+                error!("Unable to save history to {}: {}", path.display(), e);
+            }
+        }
+        Ok(())
+    }
+
+    // Runs a script file, see run_script()
+    pub fn run_file(&mut self, path: &Path) -> Result<()> {
+        let script = fs::read_to_string(path)?;
+        info!("Running script {}", path.display());
+        self.run_script(&script)
+    }
+
+    // Runs commands separated by newlines or ';' without any prompts
+    // Besides the interactive commands scripts can use
+    //   set NAME VALUE      variables, expanded as $NAME / ${NAME}
+    //   for VAR in GLOB...  loop over matching files until `end`
+    //   expect REPLY        fail unless the last reply matches
+    // Stops at the first failing command or `exit`
+    pub fn run_script(&mut self, script: &str) -> Result<()> {
+        self.interactive = false;
+        let mut lines = script
+            .lines()
+            .flat_map(split_commands)
+            .map(str::trim)
+            .filter(|line| !line.is_empty());
+        let steps = parse(&mut lines, false)?;
+        self.run_steps(&steps)?;
+        Ok(())
+    }
+
+    // returns true once the script asked to exit
+    fn run_steps(&mut self, steps: &[Step]) -> Result<bool> {
+        for step in steps {
+            let exit = match step {
+                Step::Command(words) => {
+                    let words = words
+                        .iter()
+                        .map(|word| self.expand(word))
+                        .collect::<Result<Vec<String>>>()?;
+                    info!("{} {}", "»".cyan().bold(), words.join(" ").white().bold());
+                    self.execute(&words)?
+                }
+                Step::For {
+                    var,
+                    patterns,
+                    body,
+                } => {
+                    let mut exit = false;
+                    for file in self.files(patterns)? {
+                        self.vars.insert(var.clone(), file);
+                        if self.run_steps(body)? {
+                            exit = true;
+                            break;
+                        }
+                    }
+                    exit
+                }
+            };
+            if exit {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // Expands variables in every pattern and returns the sorted matching files
+    fn files(&self, patterns: &[String]) -> Result<Vec<String>> {
+        let mut files = Vec::new();
+        for pattern in patterns {
+            let pattern = self.expand(pattern)?;
+            let paths = glob::glob(&pattern)
+                .map_err(|e| Error::Script(format!("Invalid pattern '{}': {}", pattern, e)))?;
+            let mut matched: Vec<String> = paths
+                .filter_map(|path| path.ok())
+                .filter(|path| path.is_file())
+                .map(|path| path.display().to_string())
+                .collect();
+            if matched.is_empty() {
+                info!("No files match '{}'", pattern);
+            }
+            matched.sort();
+            files.extend(matched);
+        }
+        Ok(files)
+    }
+
+    // Replaces $NAME and ${NAME} with the value of the variable
+    fn expand(&self, word: &str) -> Result<String> {
+        let mut out = String::new();
+        let mut rest = word;
+
+        while let Some(pos) = rest.find('$') {
+            out.push_str(&rest[..pos]);
+            rest = &rest[pos + 1..];
+            let (name, len) = match rest.strip_prefix('{') {
+                Some(braced) => match braced.find('}') {
+                    Some(end) => (&braced[..end], end + 2),
+                    None => return Err(Error::Script(format!("Unclosed variable in '{}'", word))),
+                },
+                None => {
+                    let end = rest
+                        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                        .unwrap_or(rest.len());
+                    (&rest[..end], end)
+                }
+            };
+            match self.vars.get(name) {
+                Some(value) => out.push_str(value),
+                None => return Err(Error::Script(format!("Unknown variable '{}'", name))),
+            }
+            rest = &rest[len..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    // Parses x y (and num) arguments of fill / debug, 1 based
+    fn cell_args(&self, args: &[String], with_num: bool) -> Result<Vec<u8>> {
+        let size = self.sudoku().size() as u8;
+        let count = if with_num { 3 } else { 2 };
+        if args.len() != count {
+            return Err(Error::Parse(String::from("Invalid Command!")));
+        }
+        let values = args
+            .iter()
+            .map(|arg| arg.parse::<u8>())
+            .collect::<std::result::Result<Vec<u8>, _>>()
+            .map_err(|_| Error::Parse(format!("Arguments must be within 1-{}", size)))?;
+        let in_range = values[..2].iter().all(|&v| v >= 1 && v <= size)
+            && values.get(2).is_none_or(|&v| v <= size);
+        if !in_range {
+            return Err(Error::Parse(format!("Arguments must be within 1-{}", size)));
+        }
+        Ok(values)
+    }
+
+    // Keeps the AVR's reply for `expect` and the session log
+    fn set_reply(&mut self, reply: String) {
+        self.replied = Some(reply.clone());
+        self.reply = reply;
+    }
+
+    // Runs a single command and records it in the session log
+    // returns true if the shell should exit
+    fn execute(&mut self, words: &[String]) -> Result<bool> {
+        if words.is_empty() {
+            return Ok(false);
+        }
+        self.replied = None;
+        let started = Instant::now();
+        let result = self.dispatch(words);
+        if let Err(Error::Reconnected) = result {
+            // The AVR may have been reset with the device, its board can not be trusted
+            if self.sent.take().is_some() {
+                warn!(
+                    "{}",
+                    "Download the board again before playing".yellow().bold()
+                );
+            }
+        }
+        session::record(Event::Command {
+            words: words.to_vec(),
+            reply: self.replied.take(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            ms: started.elapsed().as_millis(),
+        });
+        result
+    }
+
+    // Runs a single command, returns true if the shell should exit
+    fn dispatch(&mut self, words: &[String]) -> Result<bool> {
+        let (cmd, args) = match words.split_first() {
+            Some((cmd, args)) => (cmd.as_str(), args),
+            None => return Ok(false),
+        };
+        if let Some(info) = command_info(cmd) {
+            if let Some(missing) = info.needs.iter().find(|c| !self.port.supports(c)) {
+                return Err(Error::Script(format!(
+                    "The firmware does not support {} ({})",
+                    cmd, missing
+                )));
+            }
+        }
+
+        match cmd {
+            "at" => {
+                write_uart(self.port, crate::AT)?;
+                wait_response(self.port, OK)?;
+                self.set_reply(String::from("OK"));
+            }
+            "clear" => {
+                write_uart(self.port, CLEAR)?;
+                wait_response(self.port, OK)?;
+                self.set_reply(String::from("OK"));
+                self.sent = None;
+            }
+            "break" => {
+                write_uart(self.port, BREAK)?;
+                wait_response(self.port, OK)?;
+                self.set_reply(String::from("OK"));
+            }
+            "play" => self.play()?,
+            "exit" => return Ok(true),
+            "download" => {
+                if self.is_sent() {
+                    return Err(Error::Script(String::from("Game Already Running!")));
+                }
+                self.sudoku().check_supported(self.port)?;
+
+                if self.interactive {
+                    info!("Ready to Send the Unsolved Board to the AVR?");
+                    ct_msg("Sending in ")?;
+                }
+                // The AVR still holds another board, its givens would mix with this one's
+                if let Some(other) = self.sent {
+                    info!("Clearing board {} from the AVR first", other + 1);
+                    write_uart(self.port, CLEAR)?;
+                    wait_response(self.port, OK)?;
+                    self.sent = None;
+                }
+                info!(
+                    "Sending Unsolved board to {:?}",
+                    crate::port_name(self.port)
+                );
+                self.boards[self.active].sudoku.send_board(self.port)?;
+                self.set_reply(String::from("OK"));
+                self.sent = Some(self.active);
+            }
+            "fill" => {
+                let v = self.cell_args(args, true)?;
+                let frame = cell_frame(b'N', (v[0] - 1) as usize, (v[1] - 1) as usize, v[2]);
+                write_uart(self.port, &frame)?;
+                wait_response(self.port, OK)?;
+                self.set_reply(String::from("OK"));
+            }
+            "debug" => {
+                let v = self.cell_args(args, false)?;
+                write_uart(
+                    self.port,
+                    &debug_frame((v[0] - 1) as usize, (v[1] - 1) as usize),
+                )?;
+                let data = read_frame(self.port)?;
+                let (row, col, num) = parse_cell_frame(&data, self.sudoku().size())?;
+
+                info!(
+                    "{}",
+                    format!("[{},{}]: {}", col + 1, row + 1, num)
+                        .yellow()
+                        .bold()
+                );
+                self.set_reply(num.to_string());
+            }
+            "dump" => self.dump()?,
+            "cages" => {
+                if !matches!(self.sudoku().variant(), Variant::Killer { .. }) {
+                    return Err(Error::Script(String::from("Not a Killer board!")));
+                }
+                match recv_cages(self.port, &self.boards[self.active].sudoku) {
+                    Ok(()) => {
+                        info!("{}", "Cages match!".green().bold());
+                        self.set_reply(String::from("match"));
+                    }
+                    Err(Error::CageMismatch) => {
+                        error!("{}", Error::CageMismatch);
+                        self.set_reply(String::from("mismatch"));
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            "load" => {
+                if args.len() != 1 {
+                    return Err(Error::Parse(String::from("Usage: load <board file>")));
+                }
+                let sudoku = SudokuAvr::load(Path::new(&args[0]))?;
+                self.add_board(args[0].clone(), sudoku);
+            }
+            "gen" => self.generate(args)?,
+            "list" => match args {
+                [] => self.list_session(),
+                [dir] => list_dir(Path::new(dir))?,
+                _ => return Err(Error::Parse(String::from("Usage: list [dir]"))),
+            },
+            "switch" => {
+                let index = match args {
+                    [n] => n.parse::<usize>().ok(),
+                    _ => return Err(Error::Parse(String::from("Usage: switch <n>"))),
+                };
+                match index {
+                    Some(n) if n >= 1 && n <= self.boards.len() => self.active = n - 1,
+                    _ => {
+                        return Err(Error::Parse(format!(
+                            "Boards are numbered 1-{}",
+                            self.boards.len()
+                        )))
+                    }
+                }
+                let entry = &self.boards[self.active];
+                info!("Board {}: {}", self.active + 1, entry.name);
+                entry.sudoku.print_unsolved();
+            }
+            "set" => {
+                if args.len() < 2 {
+                    return Err(Error::Parse(String::from("Usage: set <name> <value>")));
+                }
+                self.vars.insert(args[0].clone(), args[1..].join(" "));
+            }
+            "send" => {
+                if args.is_empty() {
+                    return Err(Error::Parse(String::from("Usage: send <frame>")));
+                }
+                let frame = format!("{}\r\n", args.join(" "));
+                write_uart(self.port, frame.as_bytes())?;
+                let reply = read_frame(self.port)?;
+                self.set_reply(String::from_utf8_lossy(&reply).trim_end().to_string());
+                info!("{}", format!("Reply: {}", self.reply).yellow().bold());
+            }
+            "expect" => {
+                if args.is_empty() {
+                    return Err(Error::Parse(String::from("Usage: expect <reply>")));
+                }
+                let expected = args.join(" ");
+                if !expected.eq_ignore_ascii_case(&self.reply) {
+                    return Err(Error::Script(format!(
+                        "Expected \"{}\", got \"{}\"",
+                        expected, self.reply
+                    )));
+                }
+            }
+            "echo" => println!("{}", args.join(" ")),
+            "solution" => self.sudoku().print_solved(),
+            "unsolved" => self.sudoku().print_unsolved(),
+            "help" | "?" => print_help(args.first().map(String::as_str), self.port.firmware()),
+            "export" => self.sudoku().export_board(&self.export_dir)?,
+            _ => return Err(Error::Script(format!("Invalid Command '{}'!", cmd))),
+        }
+        Ok(false)
+    }
+
+    // gen <difficulty> [seed]: a board of the active board's size and variant
+    // Without a seed one is picked at random and printed, so the board can be generated again
+    fn generate(&mut self, args: &[String]) -> Result<()> {
+        let usage = || Error::Parse(String::from("Usage: gen <difficulty> [seed]"));
+        let (diff, seed) = match args {
+            [diff] => (diff, thread_rng().gen::<u64>()),
+            [diff, seed] => (diff, seed.parse::<u64>().map_err(|_| usage())?),
+            _ => return Err(usage()),
+        };
+        let diff = Difficulty::from_str(diff)
+            .map_err(|_| Error::Parse(format!("Unknown difficulty '{}'", diff)))?;
+        let (shape, kind) = (self.sudoku().shape(), self.sudoku().variant().kind());
+
+        let sudoku = SudokuAvr::new_seeded(shape, kind, &diff, seed)?;
+        self.add_board(format!("{} {} {} seed {}", shape, kind, diff, seed), sudoku);
+        Ok(())
+    }
+
+    // Prints every board of the session, marking the active one and the one on the AVR
+    fn list_session(&self) {
+        for (i, entry) in self.boards.iter().enumerate() {
+            let active = if i == self.active { "*" } else { " " };
+            let sent = if self.sent == Some(i) {
+                " (on AVR)"
+            } else {
+                ""
+            };
+            let line = format!(
+                "{} {:>3}  {} {} {}{}",
+                active,
+                i + 1,
+                entry.sudoku.dif.to_string().pad_to_width(7),
+                entry.sudoku.variant().kind().to_string().pad_to_width(8),
+                entry.name,
+                sent
+            );
+            match i == self.active {
+                true => println!("{}", line.green().bold()),
+                false => println!("{}", line.white().bold()),
+            }
+        }
+    }
+
+    // Reads the AVR's current board Cell by Cell with D<X><Y> and prints it
+    // against the downloaded board and its solution
+    // The reply is the board read, one symbol per Cell
+    fn dump(&mut self) -> Result<()> {
+        let sent = self
+            .sent
+            .ok_or_else(|| Error::Script(String::from("No board Downloaded!")))?;
+        let size = self.boards[sent].sudoku.size();
+        let mut avr = self.boards[sent].sudoku.empty_board();
+
+        for (row, cells) in avr.iter_mut().enumerate() {
+            for (col, cell) in cells.iter_mut().enumerate() {
+                write_uart(self.port, &debug_frame(col, row))?;
+                let data = read_frame(self.port)?;
+                let (r, c, num) = parse_cell_frame(&data, size)?;
+                if (r, c) != (row, col) {
+                    return Err(Error::protocol(b"N<X><Y><NUM>\r\n", &data));
+                }
+                cell.value = num;
+            }
+        }
+
+        info!("{}", "AVR Board: ".white().bold());
+        let (board_diff, solution_diff) = self.boards[sent].sudoku.print_diff(&avr);
+        match (board_diff, solution_diff) {
+            (0, 0) => info!("{}", "AVR board matches".green().bold()),
+            _ => info!(
+                "{}",
+                format!(
+                    "{} Cells differ from the board, {} from the solution",
+                    board_diff, solution_diff
+                )
+                .yellow()
+                .bold()
+            ),
+        }
+        self.set_reply(
+            avr.iter()
+                .flatten()
+                .map(|cell| crate::grid::encode_symbol(cell.value) as char)
+                .collect(),
+        );
+        Ok(())
+    }
+
+    // Starts the AVR, times it until D and reads back the solved board
+    // The reply is "valid" or "invalid"
+    fn play(&mut self) -> Result<()> {
+        if !self.is_sent() {
+            return Err(Error::Script(String::from("No board Downloaded!")));
+        }
+        let timing = timing::play(self.port)?;
+        info!("{}", format!("Solved in: {}", timing).green().bold());
+        if self.interactive {
+            info!("Ready to Receive the Solved Board from the AVR?");
+            ct_msg("Receiving in ")?;
+        }
+        let valid = match recv_and_check(self.port, &self.boards[self.active].sudoku) {
+            Ok(()) => {
+                info!("{}", "Valid Solution!!".green().bold());
+                self.boards[self.active].sudoku.tts_ms = Some(timing.solve().as_millis() as u64);
+                self.set_reply(String::from("valid"));
+                true
+            }
+            Err(Error::InvalidSolution) => {
+                info!("{}", "Invalid Solution! :( ".red().bold());
+                self.set_reply(String::from("invalid"));
+                false
+            }
+            Err(e) => return Err(e),
+        };
+        session::record(Event::play(self.sudoku(), &timing, valid));
+        Ok(())
+    }
+}
+
+// Groups script lines into steps, for loops take every line up to their end
+fn parse<'l>(lines: &mut impl Iterator<Item = &'l str>, nested: bool) -> Result<Vec<Step>> {
+    let mut steps = Vec::new();
+
+    while let Some(line) = lines.next() {
+        let words = shlex::split(line)
+            .ok_or_else(|| Error::Script(format!("Unbalanced quotes in '{}'", line)))?;
+        match words.first().map(String::as_str) {
+            None => continue,
+            Some("end") => {
+                if nested {
+                    return Ok(steps);
+                }
+                return Err(Error::Script(String::from("'end' without 'for'")));
+            }
+            Some("for") => {
+                if words.len() < 4 || words[2] != "in" {
+                    return Err(Error::Script(format!(
+                        "Usage: for <var> in <files>... (got '{}')",
+                        line
+                    )));
+                }
+                let body = parse(lines, true)?;
+                steps.push(Step::For {
+                    var: words[1].clone(),
+                    patterns: words[3..].to_vec(),
+                    body,
+                });
+            }
+            Some(_) => steps.push(Step::Command(words)),
+        }
+    }
+
+    if nested {
+        return Err(Error::Script(String::from("'for' without 'end'")));
+    }
+    Ok(steps)
+}
+
+// Splits a script line into its commands at every ';' outside of quotes
+// Quoting follows shlex: nothing is escaped in '...', a backslash escapes the next character elsewhere
+fn split_commands(line: &str) -> Vec<&str> {
+    let mut commands = Vec::new();
+    let mut start = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => (),
+            (_, '\\') => escaped = true,
+            (Some('"'), '"') => quote = None,
+            (Some(_), _) => (),
+            (None, '\'' | '"') => quote = Some(c),
+            (None, ';') => {
+                commands.push(&line[start..i]);
+                start = i + 1;
+            }
+            (None, _) => (),
+        }
+    }
+    commands.push(&line[start..]);
+    commands
+}
+
+// Prints the board files in dir with their difficulty, variant and size
+// Files that are not boards are skipped
+fn list_dir(dir: &Path) -> Result<()> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+
+    let mut found = 0;
+    for path in paths {
+        match SudokuAvr::load(&path) {
+            Ok(sudoku) => {
+                found += 1;
+                println!(
+                    "{}",
+                    format!(
+                        "{} {} {} {}",
+                        sudoku.dif.to_string().pad_to_width(7),
+                        sudoku.variant().kind().to_string().pad_to_width(8),
+                        sudoku.shape().to_string().pad_to_width(6),
+                        path.display()
+                    )
+                    .white()
+                    .bold()
+                );
+            }
+            Err(e) => debug!("Skipping {}: {}", path.display(), e),
+        }
+    }
+    if found == 0 {
+        info!("No boards in {}", dir.display());
+    }
+    Ok(())
+}
+
+fn ct_msg(msg: &str) -> Result<()> {
+    info!("Hit Enter when Ready!");
+
+    let mut input = String::new();
+    stdin().read_line(&mut input)?;
+
+    print!("{}", msg);
+    for i in (0..=5).rev() {
+        print!("{}...", i);
+        std::io::stdout().flush()?;
+        thread::sleep(Duration::from_millis(450));
+    }
+    println!();
+
+    Ok(())
+}
+
+// Prints the command table (without the commands the firmware does not support), or a single command of it
+fn print_help(command: Option<&str>, firmware: Option<&Firmware>) {
+    let commands: Vec<&CommandInfo> = match command {
+        Some(name) => command_info(name).into_iter().collect(),
+        None => COMMANDS
+            .iter()
+            .filter(|info| available(info, firmware))
+            .collect(),
+    };
+    if commands.is_empty() {
+        error!("Unknown command!");
+        return;
+    }
+
+    println!("{}", "Available Commands: ".yellow().bold());
+    for info in commands {
+        let help = match info.usage {
+            "" => info.help.to_string(),
+            usage => format!("{} {}", info.help, usage),
+        };
+        println!(
+            "{}{}",
+            info.names.join(" or ").pad_to_width(20).white().bold(),
+            help.white().bold()
+        );
+    }
+}
@@ -0,0 +1,246 @@
+use log::{debug, info, warn};
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::port::{PortConfig, DEFAULT_TIMEOUT};
+use crate::{Error, Port, Result, DONE, PLAY};
+
+// Device name running a team's firmware under simavr: "simavr:<firmware.elf>"
+pub const DEVICE: &str = "simavr";
+// UART bridge launched for it, built from simavr/uart_bridge.c
+pub const BRIDGE: &str = "simavr-uart-bridge";
+// AVR and clock simulated unless the .elf says otherwise (an .mmcu section)
+pub const DEFAULT_MCU: &str = "atmega16";
+pub const DEFAULT_CLOCK_HZ: u64 = 8_000_000;
+// How long the bridge may take to load the firmware and open its pseudo terminal
+const START_TIMEOUT: Duration = Duration::from_secs(10);
+// How long the trace of a line may trail behind its bytes
+const TRACE_WAIT: Duration = Duration::from_secs(1);
+// How often the trace is looked at while waiting for it
+const TRACE_POLL: Duration = Duration::from_millis(10);
+
+// How to launch the simulator, from the config file
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /* Path of the UART bridge, BRIDGE on the PATH by default */
+    pub bridge: String,
+    pub mcu: String,
+    pub clock_hz: u64,
+}
+
+// Line that crossed the simulated UART
+#[derive(Debug, Clone)]
+struct Traced {
+    /* Sent by the PC (true) or by the AVR */
+    rx: bool,
+    /* Cycles of its first and last byte */
+    first: u64,
+    last: u64,
+    data: Vec<u8>,
+}
+
+// What the bridge reported so far
+#[derive(Debug, Default)]
+struct Trace {
+    lines: Vec<Traced>,
+    /* How the simulation ended, None while it runs */
+    exit: Option<String>,
+}
+
+// Firmware running under simavr, stopped when dropped
+pub struct Simulation {
+    child: Child,
+    trace: Arc<Mutex<Trace>>,
+    /* Path of the .elf */
+    firmware: String,
+    mcu: String,
+    clock_hz: u64,
+}
+
+// Firmware dev names, if it names the simulator
+pub fn firmware(dev: &str) -> Option<&str> {
+    dev.strip_prefix(DEVICE)?.strip_prefix(':')
+}
+
+// Runs the firmware dev names under simavr ("simavr:<firmware.elf>") and opens its UART
+// returns None for every other device name
+pub fn open(dev: &str, config: &PortConfig) -> Option<Result<Port>> {
+    let elf = firmware(dev)?;
+    Some(launch(elf, config))
+}
+
+fn launch(elf: &str, config: &PortConfig) -> Result<Port> {
+    let settings = &config.simavr;
+    if !Path::new(elf).is_file() {
+        return Err(Error::Simulator(format!("No firmware {}", elf)));
+    }
+    let mut child = Command::new(&settings.bridge)
+        .arg("-m")
+        .arg(&settings.mcu)
+        .arg("-f")
+        .arg(settings.clock_hz.to_string())
+        .arg(elf)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            Error::Simulator(format!(
+                "Unable to run {}: {} (build it with make -C simavr)",
+                settings.bridge, e
+            ))
+        })?;
+    let stdout = match child.stdout.take() {
+        Some(stdout) => stdout,
+        None => return Err(Error::Simulator(String::from("No output from the bridge"))),
+    };
+
+    // The first lines tell the AVR and the terminal, the rest is the trace
+    let trace = Arc::new(Mutex::new(Trace::default()));
+    let (started, start) = mpsc::channel();
+    let shared = Arc::clone(&trace);
+    thread::spawn(move || {
+        let mut mcu = None;
+        for line in BufReader::new(stdout).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next(), words.next(), words.next()) {
+                (Some("MCU"), Some(name), Some(hz), None) => {
+                    mcu = hz.parse().ok().map(|hz| (String::from(name), hz))
+                }
+                (Some("PTY"), Some(path), None, None) => {
+                    let _ = started.send((String::from(path), mcu.take()));
+                }
+                (Some(dir @ ("RX" | "TX")), Some(first), Some(last), Some(hex)) => {
+                    match traced(dir == "RX", first, last, hex) {
+                        Some(traced) => lock(&shared).lines.push(traced),
+                        None => debug!("Invalid trace line from the simulator: {}", line),
+                    }
+                }
+                (Some("EXIT"), ..) => lock(&shared).exit = Some(line),
+                _ => debug!("Simulator: {}", line),
+            }
+        }
+        let mut trace = lock(&shared);
+        if trace.exit.is_none() {
+            trace.exit = Some(String::from("EXIT closed"));
+        }
+    });
+
+    let (pty, mcu) = match start.recv_timeout(START_TIMEOUT) {
+        Ok(started) => started,
+        Err(_) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(Error::Simulator(format!(
+                "{} did not start {} within {:?}",
+                settings.bridge, elf, START_TIMEOUT
+            )));
+        }
+    };
+    let (mcu, clock_hz) = mcu.unwrap_or_else(|| (settings.mcu.clone(), settings.clock_hz));
+    let simulation = Simulation {
+        child,
+        trace,
+        firmware: String::from(elf),
+        mcu,
+        clock_hz,
+    };
+    let inner = serialport::new(&pty, config.baud_rate)
+        .timeout(config.timeout.unwrap_or(DEFAULT_TIMEOUT))
+        .open()?;
+    info!(
+        "Running {} under simavr on a simulated {} at {} MHz ({})",
+        elf,
+        simulation.mcu,
+        simulation.clock_hz as f64 / 1e6,
+        pty
+    );
+    let mut port = Port::new(inner);
+    port.set_simulation(Some(simulation));
+    Ok(port)
+}
+
+fn lock(trace: &Mutex<Trace>) -> MutexGuard<'_, Trace> {
+    trace.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Parses the fields of an RX / TX line of the bridge
+fn traced(rx: bool, first: &str, last: &str, hex: &str) -> Option<Traced> {
+    let data = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    Some(Traced {
+        rx,
+        first: first.parse().ok()?,
+        last: last.parse().ok()?,
+        data,
+    })
+}
+
+impl Simulation {
+    pub fn firmware(&self) -> &str {
+        &self.firmware
+    }
+
+    pub fn mcu(&self) -> &str {
+        &self.mcu
+    }
+
+    pub fn clock_hz(&self) -> u64 {
+        self.clock_hz
+    }
+
+    // Cycles from the last byte of the latest P reaching the AVR to the first byte of the D after it
+    // None if the trace has no such pair, e.g. in framed mode where lines are wrapped
+    pub fn solve_cycles(&self) -> Option<u64> {
+        let started = Instant::now();
+        loop {
+            {
+                let trace = lock(&self.trace);
+                let play = trace
+                    .lines
+                    .iter()
+                    .rposition(|line| line.rx && line.data == PLAY);
+                let done = play.and_then(|p| {
+                    let sent = trace.lines[p].last;
+                    trace.lines[p..]
+                        .iter()
+                        .find(|line| !line.rx && line.data == DONE && line.first >= sent)
+                        .map(|line| line.first - sent)
+                });
+                if done.is_some() || play.is_none() || trace.exit.is_some() {
+                    return done;
+                }
+            }
+            // The bridge traces a line right after its last byte, it may not be there yet
+            if started.elapsed() > TRACE_WAIT {
+                return None;
+            }
+            thread::sleep(TRACE_POLL);
+        }
+    }
+
+    // How the simulation ended, None while it runs
+    pub fn exit(&self) -> Option<String> {
+        lock(&self.trace).exit.clone()
+    }
+}
+
+impl Drop for Simulation {
+    fn drop(&mut self) {
+        if let Some(exit) = self.exit() {
+            warn!("The simulation stopped early: {}", exit);
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
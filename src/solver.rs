@@ -0,0 +1,1159 @@
+use colored::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+use strum_macros::{Display, EnumIter, EnumString};
+
+use crate::cycles::{Count, Counter, NoCount, Ops};
+use crate::grid::{self, Shape};
+use crate::killer::{self, Cage};
+use crate::session::{self, Event};
+use crate::variant::Variant;
+use crate::{Difficulty, Error, Result, SudokuAvr};
+
+// Search nodes a solve may visit before giving up, naive backtracking on 16x16 boards can take ages
+pub const DEFAULT_BUDGET: u64 = 20_000_000;
+
+// Host side solving algorithms, a baseline to judge the AVR's solve times by
+#[derive(Debug, Clone, Copy, EnumString, Display, EnumIter, PartialEq, Eq, Serialize)]
+pub enum Algorithm {
+    // Cells in row major order, values in ascending order: the first solver most teams write
+    #[strum(ascii_case_insensitive)]
+    Backtracking,
+    // Fills naked and hidden singles before every guess, guessing on the Cell with the fewest candidates
+    #[strum(ascii_case_insensitive)]
+    Propagation,
+    // Knuth's Algorithm X on dancing links, the board as an exact cover problem
+    #[strum(ascii_case_insensitive, serialize = "DancingLinks", serialize = "DLX")]
+    DancingLinks,
+}
+
+// What a solve cost
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct Stats {
+    /* Values placed while searching (rows picked for dancing links) */
+    pub nodes: u64,
+    /* Values taken back because they led to a dead end */
+    pub backtracks: u64,
+    /* Cells (columns for dancing links) picked among more than one candidate */
+    pub guesses: u64,
+    /* Values placed by propagation without a guess */
+    pub forced: u64,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} nodes, {} backtracks, {} guesses, {} forced",
+            self.nodes, self.backtracks, self.guesses, self.forced
+        )
+    }
+}
+
+// Outcome of a solve
+#[derive(Debug, Clone)]
+pub struct Solve {
+    pub algorithm: Algorithm,
+    /* First solution found, None if there is none or the budget ran out */
+    pub solution: Option<Vec<u8>>,
+    pub stats: Stats,
+    /* False if the search ran out of its node budget */
+    pub complete: bool,
+    pub elapsed: Duration,
+    /* Operations an ATmega16 would have executed, all zero unless counted (see solve_counted()) */
+    pub ops: Ops,
+    /* Bytes of SRAM an ATmega16 would have needed, the deepest stack included */
+    pub sram: u64,
+}
+
+// Solves a board (row major values, 0 for empty Cells) with algorithm, visiting up to budget nodes
+// Operations are not counted, elapsed is the time of the solver alone
+pub fn solve(
+    algorithm: Algorithm,
+    shape: Shape,
+    variant: &Variant,
+    givens: &[u8],
+    budget: u64,
+) -> Result<Solve> {
+    solve_with::<NoCount>(algorithm, shape, variant, givens, budget)
+}
+
+// Same as solve(), also counting the operations an ATmega16 would execute
+pub fn solve_counted(
+    algorithm: Algorithm,
+    shape: Shape,
+    variant: &Variant,
+    givens: &[u8],
+    budget: u64,
+) -> Result<Solve> {
+    solve_with::<Counter>(algorithm, shape, variant, givens, budget)
+}
+
+fn solve_with<C: Count>(
+    algorithm: Algorithm,
+    shape: Shape,
+    variant: &Variant,
+    givens: &[u8],
+    budget: u64,
+) -> Result<Solve> {
+    let rules = Rules::<C>::new(shape, variant);
+    let started = Instant::now();
+    let (solution, stats, complete, sram) = match algorithm {
+        Algorithm::Backtracking | Algorithm::Propagation => {
+            let mut search = Search::new(&rules, givens, budget)?;
+            let found = match algorithm {
+                Algorithm::Backtracking => search.backtrack(0),
+                _ => search.propagate_and_guess(),
+            };
+            let solution = found.then(|| search.grid.clone());
+            (solution, search.stats, !search.exhausted, search.sram())
+        }
+        Algorithm::DancingLinks => {
+            let mut links = Links::new(&rules, givens, budget)?;
+            let solution = links.search().then(|| links.grid.clone());
+            (solution, links.stats, !links.exhausted, links.sram())
+        }
+    };
+    Ok(Solve {
+        algorithm,
+        solution,
+        stats,
+        complete,
+        elapsed: started.elapsed(),
+        ops: rules.ops.ops(),
+        sram,
+    })
+}
+
+// Solves the original Cells of a board, see solve()
+pub fn solve_board(algorithm: Algorithm, sudoku: &SudokuAvr, budget: u64) -> Result<Solve> {
+    solve(
+        algorithm,
+        sudoku.shape(),
+        sudoku.variant(),
+        &sudoku.givens(),
+        budget,
+    )
+}
+
+// Solves a board like solve_board(), a board without a solution is an unsolved Solve rather than an
+// error. Also returns whether the solution is valid, None without one
+pub fn attempt(
+    algorithm: Algorithm,
+    sudoku: &SudokuAvr,
+    budget: u64,
+) -> Result<(Solve, Option<bool>)> {
+    attempt_with(algorithm, sudoku, budget, solve)
+}
+
+// Same as attempt(), also counting operations (see solve_counted())
+pub fn attempt_counted(
+    algorithm: Algorithm,
+    sudoku: &SudokuAvr,
+    budget: u64,
+) -> Result<(Solve, Option<bool>)> {
+    attempt_with(algorithm, sudoku, budget, solve_counted)
+}
+
+fn attempt_with(
+    algorithm: Algorithm,
+    sudoku: &SudokuAvr,
+    budget: u64,
+    solve: fn(Algorithm, Shape, &Variant, &[u8], u64) -> Result<Solve>,
+) -> Result<(Solve, Option<bool>)> {
+    let solved = solve(
+        algorithm,
+        sudoku.shape(),
+        sudoku.variant(),
+        &sudoku.givens(),
+        budget,
+    );
+    let solve = match solved {
+        Ok(solve) => solve,
+        Err(Error::Unsolvable) => Solve {
+            algorithm,
+            solution: None,
+            stats: Stats::default(),
+            complete: true,
+            elapsed: Duration::ZERO,
+            ops: Ops::default(),
+            sram: 0,
+        },
+        Err(e) => return Err(e),
+    };
+    let givens = sudoku.givens();
+    let valid = solve.solution.as_ref().map(|solution| {
+        grid::satisfies(sudoku.shape(), sudoku.variant(), solution)
+            && givens.iter().zip(solution).all(|(&g, &v)| g == 0 || g == v)
+    });
+    Ok((solve, valid))
+}
+
+// Note next to the statistics of a solve, empty if it found a valid solution
+pub fn note(valid: Option<bool>, complete: bool) -> ColoredString {
+    match (valid, complete) {
+        (Some(true), _) => "".normal(),
+        (Some(false), _) => "invalid solution".red().bold(),
+        (None, true) => "no solution".red().bold(),
+        (None, false) => "out of budget".yellow().bold(),
+    }
+}
+
+// Means of the solves of one algorithm over the boards of one difficulty
+#[derive(Default)]
+struct Totals {
+    boards: u64,
+    /* Boards left unsolved: no solution or out of budget */
+    unsolved: u64,
+    stats: Stats,
+    elapsed: Duration,
+}
+
+impl Totals {
+    fn add(&mut self, solve: &Solve) {
+        self.boards += 1;
+        if solve.solution.is_none() {
+            self.unsolved += 1;
+        }
+        self.stats.nodes += solve.stats.nodes;
+        self.stats.backtracks += solve.stats.backtracks;
+        self.stats.guesses += solve.stats.guesses;
+        self.stats.forced += solve.stats.forced;
+        self.elapsed += solve.elapsed;
+    }
+}
+
+fn print_header() {
+    println!(
+        "  {:<14}{:>12}{:>12}{:>10}{:>10}{:>14}",
+        "Algorithm", "Nodes", "Backtracks", "Guesses", "Forced", "Time"
+    );
+}
+
+// One line of statistics, means if divided by more than one board
+fn print_row(
+    algorithm: Algorithm,
+    stats: &Stats,
+    elapsed: Duration,
+    boards: u64,
+    note: ColoredString,
+) {
+    let boards = boards.max(1);
+    println!(
+        "  {:<14}{:>12}{:>12}{:>10}{:>10}{:>11.3} ms  {}",
+        algorithm.to_string(),
+        stats.nodes / boards,
+        stats.backtracks / boards,
+        stats.guesses / boards,
+        stats.forced / boards,
+        elapsed.as_secs_f64() * 1000.0 / boards as f64,
+        note
+    );
+}
+
+// Solves every board with every algorithm, printing the statistics of each solve
+// and their means per difficulty
+pub fn print_stats(
+    boards: &[(String, SudokuAvr)],
+    algorithms: &[Algorithm],
+    budget: u64,
+) -> Result<()> {
+    let mut totals: BTreeMap<(Difficulty, usize), Totals> = BTreeMap::new();
+
+    for (name, sudoku) in boards {
+        let givens = sudoku.givens();
+        println!(
+            "{}",
+            format!(
+                "{}: {} {} {}, {} givens",
+                name,
+                sudoku.dif,
+                sudoku.variant(),
+                sudoku.shape(),
+                givens.iter().filter(|&&v| v != 0).count()
+            )
+            .white()
+            .bold()
+        );
+        print_header();
+        for (a, &algorithm) in algorithms.iter().enumerate() {
+            let (solve, valid) = attempt(algorithm, sudoku, budget)?;
+            print_row(
+                algorithm,
+                &solve.stats,
+                solve.elapsed,
+                1,
+                note(valid, solve.complete),
+            );
+            session::record(Event::Solve {
+                board: name.clone(),
+                algorithm,
+                stats: solve.stats,
+                us: solve.elapsed.as_micros(),
+                solved: valid == Some(true),
+            });
+            totals
+                .entry((sudoku.dif.clone(), a))
+                .or_default()
+                .add(&solve);
+        }
+    }
+
+    let mut difficulty = None;
+    for ((dif, a), total) in &totals {
+        if difficulty != Some(dif) {
+            println!(
+                "{}",
+                format!("{}: mean of {} boards", dif, total.boards)
+                    .white()
+                    .bold()
+            );
+            print_header();
+            difficulty = Some(dif);
+        }
+        let note = match total.unsolved {
+            0 => "".normal(),
+            n => format!("{} unsolved", n).yellow().bold(),
+        };
+        print_row(
+            algorithms[*a],
+            &total.stats,
+            total.elapsed,
+            total.boards,
+            note,
+        );
+    }
+    Ok(())
+}
+
+// Bytes of a stack frame of the recursive searches on the ATmega16: return address, saved
+// registers and locals, the candidate mask of Search not included
+const FRAME: u64 = 8;
+// Bytes of a node index for dancing links, the matrix of a 9x9 board has thousands of nodes
+const INDEX: u64 = 2;
+
+// The rules of a board: houses no value repeats in and Killer cages with their sums
+// Also counts the operations of the search using them
+struct Rules<'a, C: Count> {
+    size: usize,
+    cells: usize,
+    houses: Vec<Vec<usize>>,
+    houses_of: Vec<Vec<usize>>,
+    cages: &'a [Cage],
+    cage_of: Vec<Option<usize>>,
+    /* Bytes of a candidate mask on the ATmega16 */
+    word: u64,
+    ops: C,
+}
+
+impl<'a, C: Count> Rules<'a, C> {
+    fn new(shape: Shape, variant: &'a Variant) -> Self {
+        let houses = variant.houses(shape);
+        let mut houses_of = vec![Vec::new(); shape.cells()];
+        for (h, house) in houses.iter().enumerate() {
+            for &cell in house {
+                houses_of[cell].push(h);
+            }
+        }
+        let mut cage_of = vec![None; shape.cells()];
+        for (i, cage) in variant.cages().iter().enumerate() {
+            for &cell in &cage.cells {
+                cage_of[cell] = Some(i);
+            }
+        }
+        Rules {
+            size: shape.size(),
+            cells: shape.cells(),
+            houses,
+            houses_of,
+            cages: variant.cages(),
+            cage_of,
+            // A byte holds the candidates of boards up to 8x8
+            word: if shape.size() <= 8 { 1 } else { 2 },
+            ops: C::default(),
+        }
+    }
+
+    fn all(&self) -> u32 {
+        (1u32 << self.size) - 1
+    }
+
+    // Values the cage of cell can still take, given the Cells of grid filled so far
+    fn cage_mask(&self, grid: &[u8], cell: usize) -> u32 {
+        let (ops, w) = (&self.ops, self.word);
+        ops.call();
+        ops.read(1);
+        ops.test(1);
+        let cage = match self.cage_of[cell] {
+            Some(i) => &self.cages[i],
+            None => return self.all(),
+        };
+        let (mut used, mut empty, mut left) = (0u32, 0, cage.sum);
+        for &c in &cage.cells {
+            ops.read(2);
+            ops.test(1);
+            match grid[c] {
+                0 => empty += 1,
+                v => {
+                    ops.alu(w + 1);
+                    ops.test(1);
+                    used |= 1 << (v - 1);
+                    left = match left.checked_sub(v as u32) {
+                        Some(left) => left,
+                        None => return 0,
+                    };
+                }
+            }
+        }
+        match empty {
+            0 if left == 0 => self.all(),
+            _ => {
+                let (mask, steps) = killer::sum_mask_counted(self.all() & !used, empty, left);
+                // Every step is a call, a candidate taken off the mask and the sum checked
+                for _ in 0..steps {
+                    ops.call();
+                    ops.alu(3 * w + 1);
+                    ops.test(1);
+                    ops.test(w);
+                }
+                mask
+            }
+        }
+    }
+
+    // Walks the bits of a candidate mask, as counting or listing its values does on the ATmega16
+    fn scan(&self) {
+        for _ in 0..self.size {
+            self.ops.alu(self.word);
+            self.ops.test(1);
+        }
+    }
+}
+
+// Backtracking and propagation search state
+struct Search<'a, C: Count> {
+    rules: &'a Rules<'a, C>,
+    grid: Vec<u8>,
+    /* Values taken in every house, one bit per value */
+    used: Vec<u32>,
+    /* Cells filled while searching, in order, so they can be emptied again */
+    trail: Vec<usize>,
+    budget: u64,
+    exhausted: bool,
+    stats: Stats,
+    /* Recursion depth now and at its deepest */
+    depth: u64,
+    max_depth: u64,
+}
+
+impl<'a, C: Count> Search<'a, C> {
+    fn new(rules: &'a Rules<'a, C>, givens: &[u8], budget: u64) -> Result<Self> {
+        if givens.len() != rules.cells {
+            return Err(Error::Parse(format!(
+                "A board with {} Cells needs {} values, got {}",
+                rules.cells,
+                rules.cells,
+                givens.len()
+            )));
+        }
+        let mut search = Search {
+            rules,
+            grid: vec![0; rules.cells],
+            used: vec![0; rules.houses.len()],
+            trail: Vec::new(),
+            budget,
+            exhausted: false,
+            stats: Stats::default(),
+            depth: 0,
+            max_depth: 0,
+        };
+        for (cell, &value) in givens.iter().enumerate() {
+            if value == 0 {
+                continue;
+            }
+            if value as usize > rules.size || search.candidates(cell) & (1 << (value - 1)) == 0 {
+                return Err(Error::Unsolvable);
+            }
+            search.set(cell, value);
+        }
+        search.trail.clear();
+        Ok(search)
+    }
+
+    fn candidates(&self, cell: usize) -> u32 {
+        let (ops, w) = (&self.rules.ops, self.rules.word);
+        ops.call();
+        let taken = self.rules.houses_of[cell].iter().fold(0, |acc, &h| {
+            ops.read(1 + w);
+            ops.alu(w);
+            ops.test(1);
+            acc | self.used[h]
+        });
+        ops.alu(2 * w);
+        self.rules.all() & !taken & self.rules.cage_mask(&self.grid, cell)
+    }
+
+    fn set(&mut self, cell: usize, value: u8) {
+        let (ops, w) = (&self.rules.ops, self.rules.word);
+        ops.call();
+        for &h in &self.rules.houses_of[cell] {
+            ops.read(1 + w);
+            ops.alu(w);
+            ops.write(w);
+            ops.test(1);
+            self.used[h] |= 1 << (value - 1);
+        }
+        ops.write(2);
+        ops.alu(1);
+        self.grid[cell] = value;
+        self.trail.push(cell);
+    }
+
+    // Empties the Cells filled since the trail was mark long
+    fn undo(&mut self, mark: usize) {
+        let (ops, w) = (&self.rules.ops, self.rules.word);
+        ops.call();
+        while self.trail.len() > mark {
+            ops.test(1);
+            ops.read(2 + w);
+            ops.alu(1);
+            let cell = self.trail.pop().unwrap_or_default();
+            let bit = 1 << (self.grid[cell] - 1);
+            for &h in &self.rules.houses_of[cell] {
+                ops.read(1 + w);
+                ops.alu(w);
+                ops.write(w);
+                ops.test(1);
+                self.used[h] &= !bit;
+            }
+            ops.write(1);
+            self.grid[cell] = 0;
+        }
+    }
+
+    // Counts a node, false once the budget is spent
+    fn visit(&mut self) -> bool {
+        self.rules.ops.alu(4);
+        self.rules.ops.test(4);
+        self.stats.nodes += 1;
+        if self.stats.nodes > self.budget {
+            self.exhausted = true;
+        }
+        !self.exhausted
+    }
+
+    // Counts a recursive call one level deeper
+    fn descend(&mut self) {
+        self.rules.ops.call();
+        self.depth += 1;
+        self.max_depth = self.max_depth.max(self.depth);
+    }
+
+    // SRAM the search needs on an ATmega16: the grid, the used masks, the trail and the stack
+    fn sram(&self) -> u64 {
+        let w = self.rules.word;
+        let cells = self.rules.cells as u64;
+        cells * 2 + self.used.len() as u64 * w + self.max_depth * (FRAME + w)
+    }
+
+    // Plain backtracking from Cell start on, true once the board is filled
+    fn backtrack(&mut self, start: usize) -> bool {
+        let ops = &self.rules.ops;
+        let cell = match (start..self.rules.cells).find(|&c| {
+            ops.read(1);
+            ops.test(1);
+            self.grid[c] == 0
+        }) {
+            Some(cell) => cell,
+            None => return true,
+        };
+        let cand = self.candidates(cell);
+        self.rules.scan();
+        if cand.count_ones() > 1 {
+            self.stats.guesses += 1;
+        }
+        for value in values(cand) {
+            if !self.visit() {
+                return false;
+            }
+            let mark = self.trail.len();
+            self.set(cell, value);
+            self.descend();
+            let found = self.backtrack(cell + 1);
+            self.depth -= 1;
+            if found {
+                return true;
+            }
+            self.undo(mark);
+            if self.exhausted {
+                return false;
+            }
+            self.stats.backtracks += 1;
+        }
+        false
+    }
+
+    // Fills naked singles (a Cell with one candidate) and hidden singles (a value with one Cell
+    // left in a house) until there are none, false on a contradiction
+    fn propagate(&mut self) -> bool {
+        let rules = self.rules;
+        let (ops, w) = (&rules.ops, rules.word);
+        ops.call();
+        loop {
+            let mut changed = false;
+            for cell in 0..rules.cells {
+                ops.read(1);
+                ops.test(1);
+                if self.grid[cell] != 0 {
+                    continue;
+                }
+                let cand = self.candidates(cell);
+                rules.scan();
+                match cand.count_ones() {
+                    0 => return false,
+                    1 => {
+                        self.set(cell, cand.trailing_zeros() as u8 + 1);
+                        self.stats.forced += 1;
+                        changed = true;
+                    }
+                    _ => (),
+                }
+            }
+            // Only houses covering every value (cages usually do not) must hold each of them
+            for (h, house) in rules.houses.iter().enumerate() {
+                ops.test(1);
+                if house.len() != rules.size {
+                    continue;
+                }
+                ops.read(w);
+                rules.scan();
+                for value in values(rules.all() & !self.used[h]) {
+                    let bit = 1 << (value - 1);
+                    let mut places = house.iter().filter(|&&c| {
+                        ops.read(2);
+                        ops.test(1);
+                        if self.grid[c] != 0 {
+                            return false;
+                        }
+                        ops.alu(w);
+                        ops.test(w);
+                        self.candidates(c) & bit != 0
+                    });
+                    match (places.next(), places.next()) {
+                        (None, _) => return false,
+                        (Some(&cell), None) => {
+                            self.set(cell, value);
+                            self.stats.forced += 1;
+                            changed = true;
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            if !changed {
+                return true;
+            }
+        }
+    }
+
+    // Propagates, then guesses on the Cell with the fewest candidates, true once the board is filled
+    fn propagate_and_guess(&mut self) -> bool {
+        let mark = self.trail.len();
+        if !self.propagate() {
+            self.undo(mark);
+            return false;
+        }
+        let rules = self.rules;
+        let mut best: Option<(usize, u32)> = None;
+        for cell in 0..rules.cells {
+            rules.ops.read(1);
+            rules.ops.test(1);
+            if self.grid[cell] != 0 {
+                continue;
+            }
+            let cand = self.candidates(cell);
+            rules.scan();
+            rules.ops.test(1);
+            if best.is_none_or(|(_, b)| cand.count_ones() < b.count_ones()) {
+                best = Some((cell, cand));
+            }
+        }
+        let (cell, cand) = match best {
+            Some(best) => best,
+            None => return true,
+        };
+        if cand.count_ones() > 1 {
+            self.stats.guesses += 1;
+        }
+        for value in values(cand) {
+            if !self.visit() {
+                break;
+            }
+            let guess = self.trail.len();
+            self.set(cell, value);
+            self.descend();
+            let found = self.propagate_and_guess();
+            self.depth -= 1;
+            if found {
+                return true;
+            }
+            self.undo(guess);
+            if self.exhausted {
+                break;
+            }
+            self.stats.backtracks += 1;
+        }
+        self.undo(mark);
+        false
+    }
+}
+
+// Values of a candidate mask in ascending order
+fn values(mask: u32) -> impl Iterator<Item = u8> {
+    (1..=32u8).filter(move |v| mask & (1 << (v - 1)) != 0)
+}
+
+// Exact cover matrix on dancing links
+// Primary columns: every Cell holds a value, every full house holds every value
+// Secondary columns (covered at most once): every value at most once in a smaller house (a cage)
+// A row places a value in a Cell, cage sums are checked as rows are picked
+struct Links<'a, C: Count> {
+    rules: &'a Rules<'a, C>,
+    left: Vec<usize>,
+    right: Vec<usize>,
+    up: Vec<usize>,
+    down: Vec<usize>,
+    /* Column header of every node */
+    column: Vec<usize>,
+    /* (Cell, value) placed by the row of every node */
+    row: Vec<(usize, u8)>,
+    /* Nodes left in every column */
+    len: Vec<usize>,
+    grid: Vec<u8>,
+    budget: u64,
+    exhausted: bool,
+    stats: Stats,
+    /* Recursion depth now and at its deepest */
+    depth: u64,
+    max_depth: u64,
+}
+
+// Header every primary column hangs off
+const ROOT: usize = 0;
+
+impl<'a, C: Count> Links<'a, C> {
+    fn new(rules: &'a Rules<'a, C>, givens: &[u8], budget: u64) -> Result<Self> {
+        let (n, cells) = (rules.size, rules.cells);
+        if givens.len() != cells {
+            return Err(Error::Parse(format!(
+                "A board with {} Cells needs {} values, got {}",
+                cells,
+                cells,
+                givens.len()
+            )));
+        }
+        // Columns 1..=cells for the Cells, then n for every house
+        let columns = cells + rules.houses.len() * n;
+        let mut links = Links {
+            rules,
+            left: Vec::new(),
+            right: Vec::new(),
+            up: Vec::new(),
+            down: Vec::new(),
+            column: Vec::new(),
+            row: Vec::new(),
+            len: vec![0; columns + 1],
+            grid: vec![0; cells],
+            budget,
+            exhausted: false,
+            stats: Stats::default(),
+            depth: 0,
+            max_depth: 0,
+        };
+        let mut primary = ROOT;
+        for c in 0..=columns {
+            links.up.push(c);
+            links.down.push(c);
+            links.column.push(c);
+            links.row.push((0, 0));
+            let house = c.checked_sub(cells + 1).map(|i| i / n);
+            let is_primary = c > ROOT && house.is_none_or(|h| rules.houses[h].len() == n);
+            match is_primary {
+                true => {
+                    links.left.push(primary);
+                    links.right.push(ROOT);
+                    links.right[primary] = c;
+                    links.left[ROOT] = c;
+                    primary = c;
+                }
+                false => {
+                    links.left.push(c);
+                    links.right.push(c);
+                }
+            }
+        }
+
+        for cell in 0..cells {
+            for value in 1..=n as u8 {
+                let mut columns = vec![cell + 1];
+                columns.extend(
+                    rules.houses_of[cell]
+                        .iter()
+                        .map(|&h| cells + 1 + h * n + value as usize - 1),
+                );
+                links.add_row(&columns, (cell, value));
+            }
+        }
+        // Every node is written once building the matrix
+        rules.ops.write(links.left.len() as u64 * 6 * INDEX);
+
+        for (cell, &value) in givens.iter().enumerate() {
+            if value == 0 {
+                continue;
+            }
+            if value as usize > n {
+                return Err(Error::Unsolvable);
+            }
+            // The given's row is still there as long as no other given took its columns
+            let node = links
+                .nodes_of(cell + 1)
+                .find(|&node| links.row[node].1 == value);
+            match node {
+                Some(node) => links.pick(node),
+                None => return Err(Error::Unsolvable),
+            }
+            if links.rules.cage_mask(&links.grid, cell) == 0 {
+                return Err(Error::Unsolvable);
+            }
+        }
+        Ok(links)
+    }
+
+    fn add_row(&mut self, columns: &[usize], placed: (usize, u8)) {
+        let first = self.left.len();
+        for (i, &c) in columns.iter().enumerate() {
+            let node = first + i;
+            self.left.push(if i == 0 {
+                first + columns.len() - 1
+            } else {
+                node - 1
+            });
+            self.right.push(if i == columns.len() - 1 {
+                first
+            } else {
+                node + 1
+            });
+            self.up.push(self.up[c]);
+            self.down.push(c);
+            let last = self.up[c];
+            self.down[last] = node;
+            self.up[c] = node;
+            self.column.push(c);
+            self.row.push(placed);
+            self.len[c] += 1;
+        }
+    }
+
+    // SRAM the matrix needs on an ATmega16: five indices and a (Cell, value) pair per node,
+    // a length per column, the grid and the stack
+    fn sram(&self) -> u64 {
+        let nodes = self.left.len() as u64;
+        nodes * (5 * INDEX + 2)
+            + self.len.len() as u64 * INDEX
+            + self.rules.cells as u64
+            + self.max_depth * (FRAME + 2 * INDEX)
+    }
+
+    // Nodes of column c, top to bottom
+    fn nodes_of(&self, c: usize) -> impl Iterator<Item = usize> + '_ {
+        let mut node = self.down[c];
+        std::iter::from_fn(move || {
+            let current = node;
+            node = self.down[node];
+            self.rules.ops.read(INDEX);
+            self.rules.ops.test(INDEX);
+            (current != c).then_some(current)
+        })
+    }
+
+    fn cover(&mut self, c: usize) {
+        let ops = &self.rules.ops;
+        ops.call();
+        ops.read(2 * INDEX);
+        ops.write(2 * INDEX);
+        let (l, r) = (self.left[c], self.right[c]);
+        self.right[l] = r;
+        self.left[r] = l;
+        let mut i = self.down[c];
+        while i != c {
+            ops.test(INDEX);
+            let mut j = self.right[i];
+            while j != i {
+                ops.test(INDEX);
+                ops.read(6 * INDEX);
+                ops.write(3 * INDEX);
+                ops.alu(INDEX);
+                let (u, d) = (self.up[j], self.down[j]);
+                self.down[u] = d;
+                self.up[d] = u;
+                self.len[self.column[j]] -= 1;
+                j = self.right[j];
+            }
+            ops.read(2 * INDEX);
+            i = self.down[i];
+        }
+    }
+
+    fn uncover(&mut self, c: usize) {
+        let ops = &self.rules.ops;
+        ops.call();
+        let mut i = self.up[c];
+        while i != c {
+            ops.test(INDEX);
+            let mut j = self.left[i];
+            while j != i {
+                ops.test(INDEX);
+                ops.read(6 * INDEX);
+                ops.write(3 * INDEX);
+                ops.alu(INDEX);
+                let (u, d) = (self.up[j], self.down[j]);
+                self.down[u] = j;
+                self.up[d] = j;
+                self.len[self.column[j]] += 1;
+                j = self.left[j];
+            }
+            ops.read(2 * INDEX);
+            i = self.up[i];
+        }
+        ops.read(2 * INDEX);
+        ops.write(2 * INDEX);
+        let (l, r) = (self.left[c], self.right[c]);
+        self.right[l] = c;
+        self.left[r] = c;
+    }
+
+    // Takes the row of node into the solution, covering all its columns
+    fn pick(&mut self, node: usize) {
+        let ops = &self.rules.ops;
+        ops.call();
+        self.cover(self.column[node]);
+        let mut j = self.right[node];
+        while j != node {
+            ops.test(INDEX);
+            ops.read(3 * INDEX);
+            self.cover(self.column[j]);
+            j = self.right[j];
+        }
+        ops.read(2);
+        ops.write(1);
+        let (cell, value) = self.row[node];
+        self.grid[cell] = value;
+    }
+
+    fn unpick(&mut self, node: usize) {
+        let ops = &self.rules.ops;
+        ops.call();
+        ops.read(1);
+        ops.write(1);
+        let (cell, _) = self.row[node];
+        self.grid[cell] = 0;
+        let mut j = self.left[node];
+        while j != node {
+            ops.test(INDEX);
+            ops.read(3 * INDEX);
+            self.uncover(self.column[j]);
+            j = self.left[j];
+        }
+        self.uncover(self.column[node]);
+    }
+
+    // Whether the cage of the row's Cell can still take its value (always true without cages)
+    fn allows(&self, node: usize) -> bool {
+        let (ops, w) = (&self.rules.ops, self.rules.word);
+        ops.read(2);
+        ops.alu(w);
+        ops.test(w);
+        let (cell, value) = self.row[node];
+        self.rules.cage_mask(&self.grid, cell) & (1 << (value - 1)) != 0
+    }
+
+    // Rows of column c that can still be picked
+    fn feasible(&self, c: usize) -> usize {
+        self.rules.ops.test(1);
+        match self.rules.cages.is_empty() {
+            true => {
+                self.rules.ops.read(INDEX);
+                self.len[c]
+            }
+            false => self.nodes_of(c).filter(|&node| self.allows(node)).count(),
+        }
+    }
+
+    // Algorithm X picking the primary column with the fewest rows, true once every one is covered
+    // Rows breaking a cage sum are skipped without being counted
+    fn search(&mut self) -> bool {
+        let ops = &self.rules.ops;
+        let mut best: Option<(usize, usize)> = None;
+        let mut c = self.right[ROOT];
+        while c != ROOT {
+            ops.test(INDEX);
+            ops.test(INDEX);
+            let rows = self.feasible(c);
+            if best.is_none_or(|(_, b)| rows < b) {
+                best = Some((c, rows));
+                if rows == 0 {
+                    return false;
+                }
+            }
+            ops.read(INDEX);
+            c = self.right[c];
+        }
+        let (c, rows) = match best {
+            Some(best) => best,
+            None => return true,
+        };
+        if rows > 1 {
+            self.stats.guesses += 1;
+        }
+        let mut node = self.down[c];
+        while node != c {
+            self.rules.ops.test(INDEX);
+            if !self.allows(node) {
+                self.rules.ops.read(INDEX);
+                node = self.down[node];
+                continue;
+            }
+            self.rules.ops.alu(4);
+            self.rules.ops.test(4);
+            self.stats.nodes += 1;
+            if self.stats.nodes > self.budget {
+                self.exhausted = true;
+                return false;
+            }
+            self.pick(node);
+            self.rules.ops.call();
+            self.depth += 1;
+            self.max_depth = self.max_depth.max(self.depth);
+            let found = self.search();
+            self.depth -= 1;
+            if found {
+                return true;
+            }
+            self.unpick(node);
+            if self.exhausted {
+                return false;
+            }
+            self.stats.backtracks += 1;
+            self.rules.ops.read(INDEX);
+            node = self.down[node];
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+    use crate::VariantKind;
+
+    // Every algorithm finds the board's one solution
+    fn solves_alike(kind: VariantKind, diff: Difficulty) {
+        let sudoku = SudokuAvr::new_seeded(Shape::from_size(9).unwrap(), kind, &diff, 411).unwrap();
+        let mut solutions = Vec::new();
+        for algorithm in Algorithm::iter() {
+            let (solve, valid) = attempt(algorithm, &sudoku, DEFAULT_BUDGET).unwrap();
+            assert!(
+                solve.complete,
+                "{} ran out of budget on {}",
+                algorithm, kind
+            );
+            assert_eq!(valid, Some(true), "{} on {}", algorithm, kind);
+            solutions.push(solve.solution.unwrap());
+        }
+        assert!(solutions.windows(2).all(|pair| pair[0] == pair[1]));
+    }
+
+    #[test]
+    fn classic() {
+        solves_alike(VariantKind::Classic, Difficulty::Hard);
+    }
+
+    #[test]
+    fn diagonal() {
+        solves_alike(VariantKind::Diagonal, Difficulty::Medium);
+    }
+
+    #[test]
+    fn killer() {
+        solves_alike(VariantKind::Killer, Difficulty::Medium);
+    }
+
+    #[test]
+    fn contradicting_givens_are_unsolvable() {
+        let shape = Shape::from_size(4).unwrap();
+        // Two 1s in the first row
+        let mut givens = vec![0; 16];
+        givens[0] = 1;
+        givens[3] = 1;
+        for algorithm in Algorithm::iter() {
+            let result = solve(algorithm, shape, &Variant::Classic, &givens, DEFAULT_BUDGET);
+            assert!(matches!(result, Err(Error::Unsolvable)), "{}", algorithm);
+        }
+    }
+
+    #[test]
+    fn dead_end_board_has_no_solution() {
+        let shape = Shape::from_size(4).unwrap();
+        // 1 2 . .    The third Cell of the first row can take neither 3 nor 4
+        // . . 3 .
+        // . . 4 .
+        // . . . .
+        let mut givens = vec![0; 16];
+        givens[0] = 1;
+        givens[1] = 2;
+        givens[6] = 3;
+        givens[10] = 4;
+        for algorithm in Algorithm::iter() {
+            let solve =
+                solve(algorithm, shape, &Variant::Classic, &givens, DEFAULT_BUDGET).unwrap();
+            assert!(solve.complete, "{}", algorithm);
+            assert_eq!(solve.solution, None, "{}", algorithm);
+        }
+    }
+
+    #[test]
+    fn exhausted_budget_is_incomplete() {
+        // An empty board can not be filled without guessing
+        let shape = Shape::from_size(9).unwrap();
+        let givens = vec![0; shape.cells()];
+        for algorithm in Algorithm::iter() {
+            let solve = solve(algorithm, shape, &Variant::Classic, &givens, 1).unwrap();
+            assert!(!solve.complete, "{}", algorithm);
+            assert_eq!(solve.solution, None, "{}", algorithm);
+        }
+    }
+
+    #[test]
+    fn counting_does_not_change_the_search() {
+        let sudoku = SudokuAvr::new_seeded(
+            Shape::from_size(9).unwrap(),
+            VariantKind::Killer,
+            &Difficulty::Easy,
+            411,
+        )
+        .unwrap();
+        for algorithm in Algorithm::iter() {
+            let (plain, _) = attempt(algorithm, &sudoku, DEFAULT_BUDGET).unwrap();
+            let (counted, _) = attempt_counted(algorithm, &sudoku, DEFAULT_BUDGET).unwrap();
+            assert_eq!(plain.solution, counted.solution, "{}", algorithm);
+            assert_eq!(plain.stats.nodes, counted.stats.nodes, "{}", algorithm);
+            assert_eq!(plain.sram, counted.sram, "{}", algorithm);
+            assert_eq!(plain.ops.cycles(), 0, "{}", algorithm);
+            assert!(counted.ops.cycles() > 0, "{}", algorithm);
+        }
+    }
+}
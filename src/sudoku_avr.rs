@@ -1,28 +1,70 @@
-use anyhow::{bail, Context, Result};
 use colored::*;
 use log::{debug, error, info};
-use rand::{thread_rng, Rng};
-use std::fs::{create_dir, OpenOptions};
-use std::io::{ErrorKind, Write};
-use std::path::PathBuf;
-use std::str;
+use rand::rngs::StdRng;
+use rand::{thread_rng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::thread;
-use std::time::Duration;
+use std::time::Instant;
 use strum_macros::{Display, EnumIter, EnumString};
 use sudoku::Sudoku;
 
 use crate as lib;
-
-type Port = Box<dyn serialport::SerialPort>;
+use crate::grid::{self, Shape};
+use crate::killer::{self, Cage};
+use crate::variant::{Variant, VariantKind};
+use crate::{Error, Port, Result};
+
+// Attempts at filling a variant board (new jigsaw layout each time) before giving up
+const LAYOUT_ATTEMPTS: usize = 50;
+// Node budget per attempt, restarting is cheaper than a long unlucky search
+const SOLUTION_BUDGET: u64 = 20_000;
+// Largest board the windoku, jigsaw and killer generators can fill in reasonable time
+// (killer cage sums are also sent as two digits)
+const MAX_REGION_VARIANT_SIZE: usize = 9;
+
+// Colors used to tell jigsaw regions apart
+const REGION_COLORS: [Color; 6] = [
+    Color::White,
+    Color::Yellow,
+    Color::Cyan,
+    Color::Magenta,
+    Color::Green,
+    Color::Blue,
+];
 
 // Declare Amount of Cells to be removed based on difficulty level
-const EASY: u8 = 35;
-const MEDIUM: u8 = 40;
-const HARD: u8 = 45;
-const ULTRA: u8 = 81;
+// Counts are for a 9x9 board and scaled to the number of Cells of other sizes
+const EASY: usize = 35;
+const MEDIUM: usize = 40;
+const HARD: usize = 45;
+const ULTRA: usize = 81;
+
+// Largest Killer cage based on difficulty level, Killer boards have no original Cells
+const KILLER_EASY: usize = 2;
+const KILLER_MEDIUM: usize = 3;
+const KILLER_HARD: usize = 4;
+const KILLER_ULTRA: usize = 5;
 
 // Implement Appropriate Traits for Difficulty Enum
-#[derive(Debug, EnumString, Clone, Display, EnumIter, PartialEq, Eq, Ord, PartialOrd)]
+#[derive(
+    Debug,
+    EnumString,
+    Clone,
+    Display,
+    EnumIter,
+    PartialEq,
+    Eq,
+    Ord,
+    PartialOrd,
+    Serialize,
+    Deserialize,
+)]
 pub enum Difficulty {
     #[strum(ascii_case_insensitive)]
     Easy,
@@ -35,138 +77,434 @@ pub enum Difficulty {
 }
 
 // Define Structs
-#[derive(Ord, PartialOrd, Eq, PartialEq, Debug)]
+// Deserialization goes through SudokuAvrData so that every board is validated
+// and serialization through SudokuAvrDoc, which also writes the legacy tts
+#[derive(Ord, PartialOrd, Eq, PartialEq, Debug, Deserialize)]
+#[serde(try_from = "SudokuAvrData")]
 pub struct SudokuAvr {
     pub dif: Difficulty,
+    /* Box dimensions, 3x3 for the classic board */
+    shape: Shape,
+    /* Extra constraints (diagonals, windows, jigsaw regions) */
+    variant: Variant,
     /* Hold the generated board */
-    board: [[Cell; 9]; 9],
+    board: Board,
     /* Holds the whole solved board */
-    solution: [[Cell; 9]; 9],
-    filled: u8,
-    pub tts: u64,
+    solution: Board,
+    filled: usize,
+    /* Time the AVR took to solve it in milliseconds, once it did */
+    pub tts_ms: Option<u64>,
 }
 
-#[derive(Default, Debug, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Default, Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Cell {
     pub value: u8,
     orig: bool,
 }
 
+// Rows of Cells, size x size
+pub type Board = Vec<Vec<Cell>>;
+
+// Unchecked mirror of SudokuAvr as it comes out of a serialized document
+// Documents written before sizes were configurable have no shape and are 9x9
+#[derive(Deserialize)]
+struct SudokuAvrData {
+    dif: Difficulty,
+    #[serde(default)]
+    shape: Shape,
+    #[serde(default)]
+    variant: Variant,
+    board: Board,
+    solution: Board,
+    filled: usize,
+    /* Whole seconds, written before tts_ms */
+    #[serde(default)]
+    tts: u64,
+    #[serde(default)]
+    tts_ms: Option<u64>,
+}
+
+// Borrowed view of SudokuAvr as it is written to a document
+// tts keeps the whole seconds older readers expect, 0 until solved
+#[derive(Serialize)]
+struct SudokuAvrDoc<'a> {
+    dif: &'a Difficulty,
+    shape: &'a Shape,
+    variant: &'a Variant,
+    board: &'a Board,
+    solution: &'a Board,
+    filled: usize,
+    tts: u64,
+    tts_ms: Option<u64>,
+}
+
+impl Serialize for SudokuAvr {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        SudokuAvrDoc {
+            dif: &self.dif,
+            shape: &self.shape,
+            variant: &self.variant,
+            board: &self.board,
+            solution: &self.solution,
+            filled: self.filled,
+            tts: self.tts_ms.map_or(0, |ms| ms / 1000),
+            tts_ms: self.tts_ms,
+        }
+        .serialize(serializer)
+    }
+}
+
 impl SudokuAvr {
     // Constructor for struct Sudoku
     // Takes as argument the level of Difficulty and removes Cells accordingly
     // Cells are removed randomly, but still keeping the board uniquely solvable
     // returns instantiated Struct
-    pub fn new(diff: &Difficulty) -> Self {
-        let sudoku = Sudoku::generate_unique();
-        let sudoku_bytes = sudoku.to_bytes();
+    pub fn new(diff: &Difficulty) -> Result<Self> {
+        SudokuAvr::new_with_shape(Shape::CLASSIC, diff)
+    }
+
+    // Same as new() for any supported board size
+    pub fn new_with_shape(shape: Shape, diff: &Difficulty) -> Result<Self> {
+        SudokuAvr::new_variant(shape, VariantKind::Classic, diff)
+    }
 
-        let solution = sudoku
-            .solve_unique()
-            .expect("Unable to find Unique Solution!")
-            .to_bytes();
+    // Same as new() for any supported board size and variant
+    // Classic 9x9 boards come from the sudoku crate, everything else from the grid module
+    pub fn new_variant(shape: Shape, kind: VariantKind, diff: &Difficulty) -> Result<Self> {
+        if shape == Shape::CLASSIC && kind == VariantKind::Classic {
+            debug!("Generating {} {} Board!", shape, kind);
+            let sudoku = Sudoku::generate_unique();
+            let solution = sudoku.solve_unique().ok_or(Error::Unsolvable)?;
+            return SudokuAvr::from_puzzle(
+                shape,
+                Variant::Classic,
+                &sudoku.to_bytes(),
+                &solution.to_bytes(),
+                diff,
+                &mut thread_rng(),
+            );
+        }
+        SudokuAvr::generate(shape, kind, diff, &mut thread_rng())
+    }
+
+    // Same as new_variant(), but the same seed always gives the same board
+    // Every size and variant (classic 9x9 included) comes from the grid module
+    pub fn new_seeded(
+        shape: Shape,
+        kind: VariantKind,
+        diff: &Difficulty,
+        seed: u64,
+    ) -> Result<Self> {
+        SudokuAvr::generate(shape, kind, diff, &mut StdRng::seed_from_u64(seed))
+    }
+
+    // Generates a board using the grid module, all randomness comes from rng
+    fn generate<R: Rng>(
+        shape: Shape,
+        kind: VariantKind,
+        diff: &Difficulty,
+        rng: &mut R,
+    ) -> Result<Self> {
+        debug!("Generating {} {} Board!", shape, kind);
+        if matches!(
+            kind,
+            VariantKind::Windoku | VariantKind::Jigsaw | VariantKind::Killer
+        ) && shape.size() > MAX_REGION_VARIANT_SIZE
+        {
+            return Err(Error::Parse(format!(
+                "{} boards can not be generated at {}",
+                kind, shape
+            )));
+        }
 
-        debug!("Generating Board!");
+        let (variant, puzzle, solution) = if kind == VariantKind::Killer {
+            let solution = grid::random_solution(shape, &Variant::Classic, u64::MAX, rng)?;
+            let max_size = match diff {
+                Difficulty::Easy => KILLER_EASY,
+                Difficulty::Medium => KILLER_MEDIUM,
+                Difficulty::Hard => KILLER_HARD,
+                Difficulty::Ultra => KILLER_ULTRA,
+            };
+            let cages = killer::unique_cages(shape, &solution, max_size, rng);
+            (Variant::Killer { cages }, vec![0; shape.cells()], solution)
+        } else {
+            let (variant, solution) = SudokuAvr::random_layout(shape, kind, rng)?;
+            let puzzle = grid::minimal_puzzle(shape, &variant, &solution, rng);
+            (variant, puzzle, solution)
+        };
+        SudokuAvr::from_puzzle(shape, variant, &puzzle, &solution, diff, rng)
+    }
 
+    // Builds the board from a uniquely solvable puzzle and its solution
+    // Cells are removed from the solved board according to diff
+    fn from_puzzle<R: Rng>(
+        shape: Shape,
+        variant: Variant,
+        puzzle: &[u8],
+        solution: &[u8],
+        diff: &Difficulty,
+        rng: &mut R,
+    ) -> Result<Self> {
+        let kind = variant.kind();
         let mut board = SudokuAvr {
-            board: SudokuAvr::parse_board(&sudoku_bytes),
-            solution: SudokuAvr::parse_board(&solution),
+            board: SudokuAvr::parse_board(shape, puzzle),
+            solution: SudokuAvr::parse_board(shape, solution),
+            shape,
+            variant,
             dif: diff.clone(),
             filled: 0,
-            tts: 0,
+            tts_ms: None,
         };
 
         board.filled = SudokuAvr::count_filled(&board.board);
-        // println!("Filled Before: {}", SudokuAvr::count_filled(&board.board));
         debug!("Solving Board");
         SudokuAvr::solve_board(&mut board);
 
         debug!("Removing Cells");
-        match diff {
-            Difficulty::Easy => SudokuAvr::remove_cells(&mut board, EASY),
-            Difficulty::Medium => SudokuAvr::remove_cells(&mut board, MEDIUM),
-            Difficulty::Hard => SudokuAvr::remove_cells(&mut board, HARD),
-            Difficulty::Ultra => SudokuAvr::remove_cells(&mut board, ULTRA),
+        let no_cells = match diff {
+            Difficulty::Easy => EASY,
+            Difficulty::Medium => MEDIUM,
+            Difficulty::Hard => HARD,
+            Difficulty::Ultra => ULTRA,
+        };
+        let no_cells = match kind {
+            VariantKind::Killer => shape.cells(),
+            _ => no_cells * shape.cells() / 81,
         };
+        SudokuAvr::remove_cells(&mut board, no_cells, rng);
 
         board.filled = SudokuAvr::count_filled(&board.board);
-        board
+        Ok(board)
+    }
+
+    // Picks the variant's layout and a random solution for it
+    // Searches that run out of budget (or jigsaw layouts without a solution) start over
+    fn random_layout<R: Rng>(
+        shape: Shape,
+        kind: VariantKind,
+        rng: &mut R,
+    ) -> Result<(Variant, Vec<u8>)> {
+        for _ in 0..LAYOUT_ATTEMPTS {
+            let variant = Variant::random(kind, shape, rng);
+            match grid::random_solution(shape, &variant, SOLUTION_BUDGET, rng) {
+                Ok(solution) => return Ok((variant, solution)),
+                Err(_) => debug!("No solution for this {} layout, retrying", kind),
+            }
+        }
+        Err(Error::Unsolvable)
     }
 
     // Constructor using a string slice as argument
-    // Removes Cells depending on Difficulty level
+    // The board size is taken from the length of the line
     // Returns Instantiated Struct
-    pub fn new_from_str(line: &str, diff: Difficulty) -> Self {
+    pub fn new_from_str(line: &str, diff: Difficulty) -> Result<Self> {
+        SudokuAvr::new_from_str_variant(line, Variant::Classic, diff)
+    }
+
+    // Same as new_from_str() with the rules of variant
+    pub fn new_from_str_variant(line: &str, variant: Variant, diff: Difficulty) -> Result<Self> {
+        let (shape, values) = grid::parse_line(line)?;
+        SudokuAvr::from_values(shape, variant, &values, diff)
+    }
+
+    // Constructor using a grid of values (0 for empty Cells)
+    // The grid must be square with a supported size
+    // Every non-zero value is treated as an original Cell
+    // Fails if a value is out of range or the board is not uniquely solvable under variant
+    pub fn from_grid(grid: &[Vec<u8>], variant: Variant, diff: Difficulty) -> Result<Self> {
+        let shape = Shape::from_size(grid.len())?;
+        if grid.iter().any(|row| row.len() != shape.size()) {
+            return Err(Error::Parse(String::from("Board grid is not square")));
+        }
+        let values: Vec<u8> = grid.iter().flatten().copied().collect();
+        if let Some(value) = values.iter().find(|&&v| v as usize > shape.size()) {
+            return Err(Error::Parse(format!("Invalid Cell value {}", value)));
+        }
+        SudokuAvr::from_values(shape, variant, &values, diff)
+    }
+
+    // Constructor from JSON produced by to_json()
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).map_err(|e| Error::Parse(format!("Invalid board JSON: {}", e)))
+    }
+
+    // Serializes board, solution, difficulty, filled cells and time to solve
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    // Shared tail of the string and grid constructors
+    fn from_values(
+        shape: Shape,
+        variant: Variant,
+        values: &[u8],
+        diff: Difficulty,
+    ) -> Result<Self> {
         debug!("Generating Board");
 
-        let sudoku = Sudoku::from_str_line(line).expect("Unable to Create board from File");
-        let solution = sudoku.solve_unique().expect("Unsolvable Board").to_bytes();
+        variant.validate(shape)?;
+        let solution = grid::solve_unique(shape, &variant, values)?;
 
         let mut board = SudokuAvr {
-            board: SudokuAvr::parse_board(&sudoku.to_bytes()),
-            solution: SudokuAvr::parse_board(&solution),
-            dif: diff.clone(),
+            board: SudokuAvr::parse_board(shape, values),
+            solution: SudokuAvr::parse_board(shape, &solution),
+            shape,
+            variant,
+            dif: diff,
             filled: 0,
-            tts: 0,
+            tts_ms: None,
         };
 
         board.filled = SudokuAvr::count_filled(&board.board);
 
-        board
+        Ok(board)
     }
 
-    // Counts filled cells
-    fn count_filled(board: &[[Cell; 9]; 9]) -> u8 {
-        let mut count: u8 = 0;
-        for i in 0..board.len() {
-            for j in 0..board[i].len() {
-                if board[i][j].value != 0 {
-                    count += 1;
-                }
+    // Constructor reading a board file
+    // First line holds the Difficulty and optionally the variant (e.g. "Easy Diagonal")
+    // second line the board string, and for jigsaw / killer boards a third line with the
+    // regions / cages
+    // Files ending in .json are read with from_json() instead
+    pub fn load(path: &Path) -> Result<Self> {
+        if path.extension().is_some_and(|ext| ext == "json") {
+            return SudokuAvr::from_json(&fs::read_to_string(path)?);
+        }
+
+        let file = File::open(path)?;
+        let mut reader = BufReader::new(file);
+        let mut header = String::new();
+        let mut line = String::new();
+
+        reader.read_line(&mut header)?;
+        let invalid = || {
+            Error::Parse(format!(
+                "Invalid header '{}' in {}",
+                header.trim(),
+                path.display()
+            ))
+        };
+        let mut words = header.split_whitespace();
+        let diff = words
+            .next()
+            .and_then(|word| Difficulty::from_str(word).ok())
+            .ok_or_else(invalid)?;
+        let kind = match words.next() {
+            Some(word) => VariantKind::from_str(word).map_err(|_| invalid())?,
+            None => VariantKind::Classic,
+        };
+
+        reader.read_line(&mut line)?;
+        let (shape, values) = grid::parse_line(&line)?;
+
+        let variant = match kind {
+            VariantKind::Classic => Variant::Classic,
+            VariantKind::Diagonal => Variant::Diagonal,
+            VariantKind::Windoku => Variant::Windoku,
+            VariantKind::Jigsaw => {
+                let mut regions = String::new();
+                reader.read_line(&mut regions)?;
+                Variant::jigsaw_from_line(shape, &regions)?
+            }
+            VariantKind::Killer => {
+                let mut cages = String::new();
+                reader.read_line(&mut cages)?;
+                Variant::killer_from_line(shape, &cages)?
             }
+        };
+        SudokuAvr::from_values(shape, variant, &values, diff)
+    }
+
+    // Contents of a board file as read by load()
+    pub fn file_contents(&self) -> String {
+        let mut contents = match self.variant {
+            Variant::Classic => format!("{}\n{}", self.dif, self),
+            _ => format!("{} {}\n{}", self.dif, self.variant, self),
+        };
+        if let Some(layout) = self.variant.layout_line(self.shape) {
+            contents.push('\n');
+            contents.push_str(&layout);
         }
-        count
+        contents
+    }
+
+    // Extra constraints of the board
+    pub fn variant(&self) -> &Variant {
+        &self.variant
+    }
+
+    // Board dimensions
+    pub fn shape(&self) -> Shape {
+        self.shape
+    }
+
+    // Side length of the board
+    pub fn size(&self) -> usize {
+        self.shape.size()
+    }
+
+    // Returns an empty board of the same size, e.g. to receive the AVR's board into
+    pub fn empty_board(&self) -> Board {
+        vec![vec![Cell::default(); self.size()]; self.size()]
+    }
+
+    // Values of the original Cells, row major, 0 for empty Cells
+    pub fn givens(&self) -> Vec<u8> {
+        self.board.iter().flatten().map(|cell| cell.value).collect()
+    }
+
+    // Counts filled cells
+    fn count_filled(board: &[Vec<Cell>]) -> usize {
+        board
+            .iter()
+            .flatten()
+            .filter(|cell| cell.value != 0)
+            .count()
     }
 
     // Copies the solution from one array to the other
     // SKIPPING original cells
     // Clone Trait would not have worked
     fn solve_board(sud: &mut SudokuAvr) {
-        for i in 0..sud.board.len() {
-            for j in 0..sud.board[i].len() {
-                if sud.board[i][j].orig {
-                    continue;
-                } else {
-                    sud.board[i][j].value = sud.solution[i][j].value;
+        for (row, sol_row) in sud.board.iter_mut().zip(sud.solution.iter()) {
+            for (cell, sol) in row.iter_mut().zip(sol_row.iter()) {
+                if !cell.orig {
+                    cell.value = sol.value;
                 }
             }
         }
     }
 
-    pub fn check(&self, board: &[[Cell; 9]; 9]) -> bool {
-        for i in 0..self.solution.len() {
-            for j in 0..self.solution[i].len() {
-                if self.solution[i][j].value != board[i][j].value {
-                    return false;
-                }
-            }
+    // Checks a board received from the AVR
+    // It must keep every Cell of the downloaded board and follow all rules of the variant
+    pub fn check(&self, board: &[Vec<Cell>]) -> bool {
+        if board.len() != self.size() || board.iter().any(|row| row.len() != self.size()) {
+            return false;
         }
-        true
+        let kept = self
+            .board
+            .iter()
+            .flatten()
+            .zip(board.iter().flatten())
+            .all(|(given, cell)| given.value == 0 || given.value == cell.value);
+        let values: Vec<u8> = board.iter().flatten().map(|cell| cell.value).collect();
+
+        kept && grid::satisfies(self.shape, &self.variant, &values)
     }
 
     // Removes Cells based on an RNG
     // Skip Cell if original so that board will not loose uniqueness
-    fn remove_cells(board: &mut SudokuAvr, no_cells: u8) {
+    fn remove_cells<R: Rng>(board: &mut SudokuAvr, no_cells: usize, rng: &mut R) {
         let mut limit = 0;
-        let mut rng = thread_rng();
+        let size = board.size();
 
-        while limit < no_cells && limit != 81 - board.filled {
-            let i: usize = rng.gen_range(0..9) as usize;
-            let j: usize = rng.gen_range(0..9) as usize;
+        while limit < no_cells && limit != board.shape.cells() - board.filled {
+            let i: usize = rng.gen_range(0..size);
+            let j: usize = rng.gen_range(0..size);
 
-            if board.board[i][j].orig {
-                continue;
-            } else if board.board[i][j].value == 0 {
+            if board.board[i][j].orig || board.board[i][j].value == 0 {
                 continue;
             } else {
                 board.board[i][j].value = 0;
@@ -187,9 +525,13 @@ impl SudokuAvr {
             Difficulty::Hard => println!("{}", "HARD".red().bold()),
             Difficulty::Ultra => println!("{}", "ULTRA".red().bold()),
         }
+        self.print_variant();
         print!("{}", "Filled Cells: ".green().bold());
         println!("{}", format!("{}", self.filled).white().bold());
-        SudokuAvr::print_board(&self.board);
+        match &self.variant {
+            Variant::Killer { cages } => self.print_cages(cages),
+            _ => self.print_board(&self.board),
+        }
     }
 
     // Wrapper around print_board() Method that prints the unsolved Board
@@ -201,142 +543,365 @@ impl SudokuAvr {
             Difficulty::Hard => println!("{}", "HARD".red().bold()),
             Difficulty::Ultra => println!("{}", "ULTRA".red().bold()),
         }
-        SudokuAvr::print_board(&self.solution);
+        self.print_variant();
+        self.print_board(&self.solution);
+    }
+
+    // Prints the variant for anything but classic boards
+    fn print_variant(&self) {
+        if self.variant != Variant::Classic {
+            print!("{}", "Variant: ".green().bold());
+            println!("{}", self.variant.to_string().cyan().bold());
+        }
+    }
+
+    // Prints the cage layout of a Killer board
+    // Every cage gets its own color, its sum is printed in its top left Cell
+    fn print_cages(&self, cages: &[Cage]) {
+        let shape = self.shape;
+        let size = shape.size();
+        let width = 3 * size + 2 * (size / shape.box_cols) + 1;
+        let colors = killer::cage_colors(shape, cages, REGION_COLORS.len());
+
+        let mut labels: Vec<(String, Color)> =
+            vec![(String::from(" · "), Color::White); shape.cells()];
+        for (cage, &color) in cages.iter().zip(colors.iter()) {
+            for &cell in &cage.cells {
+                labels[cell].1 = REGION_COLORS[color];
+            }
+            labels[cage.anchor()].0 = format!("{:>2} ", cage.sum);
+        }
+
+        println!("\n\t{}", "-".repeat(width).bold().white());
+        for (i, row) in labels.chunks(size).enumerate() {
+            let label = grid::encode_symbol(i as u8 + 1) as char;
+            print!("{}", format!("\t{} | ", label).white().bold());
+            for (j, (text, color)) in row.iter().enumerate() {
+                print!("{}", text.color(*color).bold());
+                if (j + 1) % shape.box_cols == 0 && (j + 1) != size {
+                    print!("{}", "| ".white().bold());
+                }
+            }
+            print!("{}", "|".white().bold());
+            if (i + 1) % shape.box_rows == 0 && (i + 1) != size {
+                print!("\n\t{}", "=".repeat(width).white().bold());
+            }
+            println!();
+        }
+        println!("\t{}", "-".repeat(width).white().bold());
+
+        let mut footer = String::from("\t🤘|");
+        for j in 0..size {
+            footer.push_str("  ");
+            footer.push(grid::encode_symbol(j as u8 + 1) as char);
+            if (j + 1) % shape.box_cols == 0 {
+                footer.push_str(" |");
+            }
+        }
+        println!("{}\n", footer.white().bold());
     }
 
     // Prints board with correct formatting
-    pub fn print_board(board: &[[Cell; 9]; 9]) {
-        println!("{}", "\n\t---------------------------".bold().white());
-        for i in 0..board.len() {
-            print!("{}", format!("\t{} | ", i + 1).white().bold());
-            for j in 0..board[i].len() {
-                if board[i][j].value == 0 {
-                    print!("{}", "_ ".white().bold());
+    // Rows and columns are labelled with the symbols used on the wire
+    // Diagonal and window Cells are highlighted, jigsaw regions and killer cages get their own color
+    pub fn print_board(&self, board: &[Vec<Cell>]) {
+        let shape = self.shape;
+        let size = shape.size();
+        let cage_colors = killer::cage_colors(shape, self.variant.cages(), REGION_COLORS.len());
+        let mut cage_of = vec![0; shape.cells()];
+        for (cage, &color) in self.variant.cages().iter().zip(cage_colors.iter()) {
+            for &cell in &cage.cells {
+                cage_of[cell] = color;
+            }
+        }
+
+        self.print_grid(board, |i, j, _| match &self.variant {
+            Variant::Jigsaw { .. } => {
+                let region = self.variant.region_of(shape, i, j);
+                REGION_COLORS[region % REGION_COLORS.len()]
+            }
+            Variant::Killer { .. } => REGION_COLORS[cage_of[i * size + j]],
+            v if v.is_marked(shape, i, j) => Color::Cyan,
+            _ => Color::White,
+        });
+    }
+
+    // Prints a board read from the AVR against this one
+    // Red: an original Cell was changed, Yellow: a Cell differs from the solution
+    // Green: a Cell the AVR filled in correctly, White: original Cells kept
+    // returns the number of Cells that differ from the board and from the solution
+    pub fn print_diff(&self, board: &[Vec<Cell>]) -> (usize, usize) {
+        let (mut board_diff, mut solution_diff) = (0, 0);
+        for (i, row) in board.iter().enumerate() {
+            for (j, cell) in row.iter().enumerate() {
+                let given = &self.board[i][j];
+                if given.value != 0 && given.value != cell.value {
+                    board_diff += 1;
+                }
+                if cell.value != 0 && cell.value != self.solution[i][j].value {
+                    solution_diff += 1;
+                }
+            }
+        }
+
+        self.print_grid(board, |i, j, cell| {
+            let given = &self.board[i][j];
+            if given.value != 0 && given.value != cell.value {
+                Color::Red
+            } else if cell.value != 0 && cell.value != self.solution[i][j].value {
+                Color::Yellow
+            } else if given.value == 0 && cell.value != 0 {
+                Color::Green
+            } else {
+                Color::White
+            }
+        });
+        (board_diff, solution_diff)
+    }
+
+    // Prints board with the box separators and labels, asking color for the color of each Cell
+    fn print_grid(&self, board: &[Vec<Cell>], color: impl Fn(usize, usize, &Cell) -> Color) {
+        let shape = self.shape;
+        let size = shape.size();
+        let width = 2 * size + 2 * (size / shape.box_cols) + 1;
+
+        println!("\n\t{}", "-".repeat(width).bold().white());
+        for (i, row) in board.iter().enumerate() {
+            let label = grid::encode_symbol(i as u8 + 1) as char;
+            print!("{}", format!("\t{} | ", label).white().bold());
+            for (j, cell) in row.iter().enumerate() {
+                let color = color(i, j, cell);
+                if cell.value == 0 {
+                    print!("{}", "_ ".color(color).bold());
                 } else {
-                    print!("{}", format!("{} ", board[i][j].value).white().bold());
+                    let symbol = grid::encode_symbol(cell.value) as char;
+                    print!("{}", format!("{} ", symbol).color(color).bold());
                 }
-                if (j + 1) % 3 == 0 && (j + 1) != 9 {
+                if (j + 1) % shape.box_cols == 0 && (j + 1) != size {
                     print!("{}", "| ".white().bold());
                 }
             }
             print!("{}", "|".white().bold());
-            if (i + 1) % 3 == 0 && (i + 1) != 9 {
-                print!("{}", "\n\t===========================".white().bold());
+            if (i + 1) % shape.box_rows == 0 && (i + 1) != size {
+                print!("\n\t{}", "=".repeat(width).white().bold());
             }
             println!();
         }
-        println!("{}", "\t---------------------------".white().bold());
-        println!("{}", "\t🤘| 1 2 3 | 4 5 6 | 7 8 9 |\n".white().bold());
+        println!("\t{}", "-".repeat(width).white().bold());
+
+        let mut footer = String::from("\t🤘|");
+        for j in 0..size {
+            footer.push(' ');
+            footer.push(grid::encode_symbol(j as u8 + 1) as char);
+            if (j + 1) % shape.box_cols == 0 {
+                footer.push_str(" |");
+            }
+        }
+        println!("{}\n", footer.white().bold());
     }
 
-    // Parses a 81-byte array into a 9x9 Cell array
+    // Parses a row major value array into a Cell board
     // Marks the original Cells
-    fn parse_board(bytes: &[u8]) -> [[Cell; 9]; 9] {
-        let mut board: [[Cell; 9]; 9] = Default::default();
-        let mut byte = 0;
-
-        for i in 0..board.len() {
-            for j in 0..board[i].len() {
-                board[i][j].value = bytes[byte];
-                board[i][j].orig = if bytes[byte] != 0 { true } else { false };
-                byte += 1;
-            }
+    fn parse_board(shape: Shape, bytes: &[u8]) -> Board {
+        let mut board: Board = vec![vec![Cell::default(); shape.size()]; shape.size()];
+
+        for (cell, &byte) in board.iter_mut().flatten().zip(bytes.iter()) {
+            cell.value = byte;
+            cell.orig = byte != 0;
         }
 
         board
     }
 
-    pub fn export_board(&self) -> Result<()> {
-        if self.tts == 0 {
-            error!("No Solution Time Found");
-            return Ok(());
-        }
-        let dir = "exports";
-        match create_dir(dir) {
-            Ok(_) => (),
-            Err(e) => match e.kind() {
-                ErrorKind::AlreadyExists => (),
-                _ => {
-                    error!("Unable to Create directory!");
-                    bail!("{}", format!("{:#}", e));
-                }
-            },
-        }
+    // Writes the board (with its solution time in the name) to dir
+    pub fn export_board(&self, dir: &str) -> Result<()> {
+        let tts_ms = match self.tts_ms {
+            Some(tts_ms) => tts_ms,
+            None => {
+                error!("No Solution Time Found");
+                return Ok(());
+            }
+        };
+        lib::ensure_dir(dir)?;
 
-        let filename = format!("{}_{}s.txt", self.dif, self.tts);
-        let path = PathBuf::from(format!("./{}", dir)).join(filename.clone());
+        let filename = format!("{}_{}.{:03}s.txt", self.dif, tts_ms / 1000, tts_ms % 1000);
+        let path = PathBuf::from(dir).join(&filename);
 
         let mut f = OpenOptions::new()
             .create(true)
             .write(true)
-            .open(&path)
-            .with_context(|| format!("Failed to create {}", path.display()))?;
+            .truncate(true)
+            .open(&path)?;
 
-        write!(f, "{}\n{}", self.dif, self.to_string())?;
-        info!("{}", format!("{}: {}", filename, "Exported Successfully"));
+        write!(f, "{}", self.file_contents())?;
+        info!("{}: Exported Successfully", filename);
         Ok(())
     }
 
-    // returns a String representation of the 9x9 Array
-    // 0,0 -> 1st, 0,1 -> 2nd etc
-    pub fn to_string(&self) -> String {
-        let mut string = String::new();
-        for i in 0..self.board.len() {
-            for j in 0..self.board[i].len() {
-                string.push_str(self.board[i][j].value.to_string().as_str());
-            }
+    // Fails unless the firmware lists every command the download needs
+    // Killer boards need K for their cages, the I reply tells if it is there
+    pub fn check_supported(&self, port: &Port) -> Result<()> {
+        if matches!(self.variant, Variant::Killer { .. }) && !port.supports("K") {
+            let commands = port
+                .firmware()
+                .map(|firmware| firmware.commands.join(" "))
+                .unwrap_or_default();
+            return Err(Error::Protocol {
+                expected: String::from("K (Killer cages) in the I reply"),
+                got: commands,
+            });
         }
-        // println!("{}", string);
-        string
+        Ok(())
     }
 
     // Wrapper around do_send() Method
     // Will count the amount of cells to send to the MCU
+    // Killer boards send their cages afterwards
+    // Reports the throughput of the download once it is done
     pub fn send_board(&self, port: &mut Port) -> Result<()> {
+        self.check_supported(port)?;
+        lib::session::record(lib::Event::board(self));
         debug!("Will send {} chunks to AVR!", self.filled);
-        thread::sleep(Duration::from_millis(50));
-        SudokuAvr::do_send(&self.board, port)?;
+        if let Some(emulator) = port.emulator() {
+            emulator.set_variant(&self.variant);
+        }
+        if let Some(delay) = port.pacing().delay {
+            thread::sleep(delay);
+        }
+        let bulk = lib::bulk::supported(port)?;
+        let started = Instant::now();
+        let mut frames = if bulk {
+            let values: Vec<u8> = self.board.iter().flatten().map(|c| c.value).collect();
+            let frame = lib::bulk::send(port, &values)?;
+            info!("{}", "Done Sending!".white().bold());
+            vec![frame]
+        } else {
+            SudokuAvr::do_send(&self.board, port)?
+        };
+        if let Variant::Killer { cages } = &self.variant {
+            debug!("Will send {} cages to AVR!", cages.len());
+            frames.extend(SudokuAvr::do_send_cages(cages, self.size(), port)?);
+        }
+        let elapsed = started.elapsed();
+
+        let bytes: usize = frames.iter().map(Vec::len).sum();
+        let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+        info!(
+            "Sent {} frames ({} bytes) in {:.3} s: {:.0} frames/s, {:.0} bytes/s",
+            frames.len(),
+            bytes,
+            elapsed.as_secs_f64(),
+            frames.len() as f64 / secs,
+            bytes as f64 / secs
+        );
+        lib::session::record(lib::Event::Download {
+            frames: frames.len(),
+            bytes,
+            ms: elapsed.as_millis(),
+        });
         Ok(())
     }
 
     // Loops over the given board and sends each Cell in the correct format
-    // [N<X><Y><NUM><CR><LF>]: 6 bytes
+    // [N<X><Y><NUM><CR><LF>]: 6 bytes, coordinates and value as symbols
     // Skip empty cells
-    // Will flush the buffer and sleep for 50ms
-    // Wait for the correct response from the MCU
-    fn do_send(board: &[[Cell; 9]; 9], port: &mut Port) -> Result<()> {
-        for i in 0..board.len() {
-            for j in 0..board.len() {
-                if board[i][j].value == 0 {
-                    continue;
-                }
+    // Every frame is answered with OK, paced as the port says (see lib::send_frames())
+    // returns the frames sent
+    fn do_send(board: &[Vec<Cell>], port: &mut Port) -> Result<Vec<Vec<u8>>> {
+        let frames: Vec<Vec<u8>> = board
+            .iter()
+            .enumerate()
+            .flat_map(|(i, row)| {
+                row.iter()
+                    .enumerate()
+                    .filter(|(_, cell)| cell.value != 0)
+                    .map(move |(j, cell)| lib::cell_frame(b'N', j, i, cell.value).to_vec())
+            })
+            .collect();
+        lib::send_frames(port, &frames)?;
+        info!("{}", "Done Sending!".white().bold());
+        Ok(frames)
+    }
+
+    // Sends every Killer cage as a [K<SUM><X1><Y1>...<Xn><Yn><CR><LF>] frame
+    // Paced just like do_send()
+    fn do_send_cages(cages: &[Cage], size: usize, port: &mut Port) -> Result<Vec<Vec<u8>>> {
+        let frames: Vec<Vec<u8>> = cages
+            .iter()
+            .map(|cage| lib::cage_frame(cage, size))
+            .collect();
+        lib::send_frames(port, &frames)?;
+        info!("{}", "Done Sending Cages!".white().bold());
+        Ok(frames)
+    }
+}
 
-                let chunk = &[
-                    b'N',
-                    (j as u8 + 1) + 0x30,
-                    (i as u8 + 1) + 0x30,
-                    board[i][j].value + 0x30,
-                    b'\x0D',
-                    b'\x0A',
-                ];
-                match port.write(chunk) {
-                    Ok(_) => {
-                        debug!(
-                            "Wrote {} to {:?}",
-                            str::from_utf8(chunk)?,
-                            port.name().expect("Failed to get UART Name")
-                        );
-                        port.flush().expect("Unable to Flush!");
-                        thread::sleep(Duration::from_millis(50));
-                        lib::wait_response(port, b"OK\x0D\x0A")?;
-                    }
-                    Err(_) => {
-                        bail!("Unable to Write!");
-                    }
+// returns a String representation of the board, one symbol per Cell
+// 0,0 -> 1st, 0,1 -> 2nd etc
+impl fmt::Display for SudokuAvr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for cell in self.board.iter().flatten() {
+            write!(f, "{}", grid::encode_symbol(cell.value) as char)?;
+        }
+        Ok(())
+    }
+}
+
+// Validates a deserialized board against the solution of its original Cells
+impl TryFrom<SudokuAvrData> for SudokuAvr {
+    type Error = Error;
+
+    fn try_from(data: SudokuAvrData) -> Result<Self> {
+        let size = data.shape.size();
+        if Shape::from_size(size)? != data.shape
+            || data.board.len() != size
+            || data.board.iter().any(|row| row.len() != size)
+        {
+            return Err(Error::Parse(format!(
+                "Board does not match its {} shape",
+                data.shape
+            )));
+        }
+
+        data.variant.validate(data.shape)?;
+
+        let mut givens = vec![vec![0u8; size]; size];
+        for (given, cell) in givens.iter_mut().flatten().zip(data.board.iter().flatten()) {
+            if cell.orig {
+                if cell.value == 0 {
+                    return Err(Error::Parse(String::from("Original Cell without a value")));
                 }
+                *given = cell.value;
             }
         }
-        info!("{}", "Done Sending!".white().bold());
-        Ok(())
+
+        let mut sudoku = SudokuAvr::from_grid(&givens, data.variant, data.dif)?;
+        if !sudoku.check(&data.solution) {
+            return Err(Error::Parse(String::from(
+                "Solution does not match the original Cells",
+            )));
+        }
+
+        let consistent = data
+            .board
+            .iter()
+            .flatten()
+            .zip(sudoku.solution.iter().flatten())
+            .all(|(cell, sol)| cell.value == 0 || cell.value == sol.value);
+        if !consistent {
+            return Err(Error::Parse(String::from("Board contradicts its solution")));
+        }
+
+        sudoku.board = data.board;
+        sudoku.filled = SudokuAvr::count_filled(&sudoku.board);
+        if sudoku.filled != data.filled {
+            return Err(Error::Parse(format!(
+                "Board has {} filled Cells, expected {}",
+                sudoku.filled, data.filled
+            )));
+        }
+        sudoku.tts_ms = data.tts_ms.or((data.tts > 0).then(|| data.tts * 1000));
+
+        Ok(sudoku)
     }
 }
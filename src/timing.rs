@@ -0,0 +1,227 @@
+use colored::*;
+use log::{debug, warn};
+use serde::Serialize;
+use serialport::ClearBuffer;
+use std::fmt;
+use std::io::{stdout, Write};
+use std::time::{Duration, Instant};
+
+use crate::session::{self, Event};
+use crate::{read_line, read_uart, send_uart, wait_response, Error, Port, Result, AT, OK, PLAY};
+
+// AT round trips measured by latency(), the fastest one is kept
+const SAMPLES: usize = 5;
+// Longest progress report read
+const MAX_PROGRESS: usize = 32;
+
+// Solve time of a single play
+#[derive(Debug, Clone)]
+pub struct Timing {
+    /* From the last byte of P leaving to the first byte of D arriving */
+    pub raw: Duration,
+    /* Round trip of AT / OK right before P, spent on the wire rather than solving */
+    pub latency: Duration,
+    /* Progress reports the AVR sent while solving, if any */
+    pub progress: Vec<Progress>,
+    /* Cycles a simulated AVR spent solving, taking the place of the measured time */
+    pub simulated: Option<Simulated>,
+}
+
+// Solve time of firmware running under simavr, counted in cycles (see simavr::Simulation)
+#[derive(Debug, Clone, Copy)]
+pub struct Simulated {
+    pub cycles: u64,
+    pub clock_hz: u64,
+}
+
+impl Simulated {
+    // Time the cycles take on the real AVR
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.cycles as f64 / self.clock_hz as f64)
+    }
+}
+
+// Progress report the firmware may send while solving: [U<FILLED>;<BACKTRACKS><CR><LF>]
+// Both numbers in decimal, FILLED counting the Cells filled so far (givens included)
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Progress {
+    /* Time since P */
+    pub ms: u128,
+    pub filled: u32,
+    pub backtracks: u64,
+}
+
+impl Progress {
+    // Parses a U frame that arrived ms after P
+    pub fn parse(frame: &[u8], ms: u128) -> Result<Self> {
+        let bad = || {
+            Error::Parse(format!(
+                "Invalid progress report {}",
+                String::from_utf8_lossy(frame).escape_debug()
+            ))
+        };
+        let (filled, backtracks) = std::str::from_utf8(frame)
+            .ok()
+            .and_then(|line| line.strip_prefix('U'))
+            .and_then(|line| line.strip_suffix("\r\n"))
+            .and_then(|line| line.split_once(';'))
+            .ok_or_else(bad)?;
+        Ok(Progress {
+            ms,
+            filled: filled.parse().map_err(|_| bad())?,
+            backtracks: backtracks.parse().map_err(|_| bad())?,
+        })
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} Cells filled, {} backtracks after {}",
+            self.filled,
+            self.backtracks,
+            format_ms(Duration::from_millis(self.ms as u64))
+        )
+    }
+}
+
+impl Timing {
+    // Time the AVR spent solving: raw without the round trip, or the simulated cycles
+    pub fn solve(&self) -> Duration {
+        match self.simulated {
+            Some(simulated) => simulated.duration(),
+            None => self.raw.saturating_sub(self.latency),
+        }
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(simulated) = self.simulated {
+            return write!(
+                f,
+                "{} ({} cycles at {} MHz, simulated)",
+                format_ms(self.solve()),
+                simulated.cycles,
+                simulated.clock_hz as f64 / 1e6
+            );
+        }
+        write!(
+            f,
+            "{} (raw {}, latency {:.3} ms)",
+            format_ms(self.solve()),
+            format_ms(self.raw),
+            self.latency.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+// Duration in seconds with millisecond resolution, e.g. 1.523 s
+pub fn format_ms(duration: Duration) -> String {
+    format!("{:.3} s", duration.as_secs_f64())
+}
+
+// Waits for the next byte, returning it with the time it arrived
+fn first_byte(port: &mut Port) -> Result<(u8, Instant)> {
+    loop {
+        let data = read_uart(port, 1)?;
+        if let Some(&byte) = data.first() {
+            return Ok((byte, Instant::now()));
+        }
+    }
+}
+
+// Measures the round trip from the last byte of AT leaving to the first byte of OK arriving
+// Sends AT SAMPLES times and keeps the fastest, everything slower is scheduling noise
+pub fn latency(port: &mut Port) -> Result<Duration> {
+    port.clear(ClearBuffer::All)?;
+    let mut best = Duration::MAX;
+    for _ in 0..SAMPLES {
+        send_uart(port, AT)?;
+        let sent = Instant::now();
+        let (byte, arrived) = first_byte(port)?;
+        let mut reply = vec![byte];
+        while reply.len() < OK.len() {
+            reply.extend(read_uart(port, (OK.len() - reply.len()) as i32)?);
+        }
+        if reply != OK {
+            return Err(Error::protocol(OK, &reply));
+        }
+        best = best.min(arrived - sent);
+    }
+    debug!("Round trip latency: {:?}", best);
+    Ok(best)
+}
+
+// Starts the AVR with P and times it until the first byte of D
+// Progress reports are shown on a single live line and recorded in the session log,
+// anything else arriving while it solves, and read timeouts, are skipped
+pub fn play(port: &mut Port) -> Result<Timing> {
+    let latency = latency(port)?;
+
+    send_uart(port, PLAY)?;
+    let sent = Instant::now();
+    wait_response(port, OK)?;
+
+    let mut progress = Vec::new();
+    let arrived = loop {
+        match first_byte(port) {
+            Ok((b'D', arrived)) => {
+                let rest = read_uart(port, 2)?;
+                if rest == b"\r\n" {
+                    break arrived;
+                }
+                debug!("Skipping D{:?}", rest);
+            }
+            Ok((b'U', arrived)) => {
+                let ms = (arrived - sent).as_millis();
+                let report = read_line(port, MAX_PROGRESS).and_then(|rest| {
+                    let frame: Vec<u8> = b"U".iter().chain(&rest).copied().collect();
+                    Progress::parse(&frame, ms)
+                });
+                match report {
+                    Ok(report) => {
+                        show_progress(&report);
+                        session::record(Event::Progress(report));
+                        progress.push(report);
+                    }
+                    Err(e @ Error::Io(_)) | Err(e @ Error::Reconnected) => return Err(e),
+                    Err(e) => debug!("{}", e),
+                }
+            }
+            Ok((byte, _)) => debug!("Skipping {:?} while solving", byte as char),
+            Err(Error::Timeout) => (),
+            Err(e) => return Err(e),
+        }
+    };
+
+    if !progress.is_empty() {
+        println!();
+    }
+
+    // Wall clock times of a simulation say nothing about the real AVR, its cycles do
+    let simulated = port.simulation().and_then(|simulation| {
+        let cycles = simulation.solve_cycles();
+        if cycles.is_none() {
+            warn!("No P / D in the simulator's trace, using the measured time");
+        }
+        cycles.map(|cycles| Simulated {
+            cycles,
+            clock_hz: simulation.clock_hz(),
+        })
+    });
+
+    Ok(Timing {
+        raw: arrived - sent,
+        latency,
+        progress,
+        simulated,
+    })
+}
+
+// Overwrites the live progress line
+fn show_progress(report: &Progress) {
+    print!("\r{}", format!("Solving: {}", report).cyan().bold());
+    let _ = stdout().flush();
+}
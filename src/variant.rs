@@ -0,0 +1,358 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use strum_macros::{Display, EnumIter, EnumString};
+
+use crate::grid::{self, Shape};
+use crate::killer::{self, Cage};
+use crate::{Error, Result};
+
+// Random swaps attempted per Cell when shuffling jigsaw regions
+const JIGSAW_SWAPS: usize = 4;
+
+// Variant names as used on the command line and in board files
+#[derive(Debug, Clone, Copy, EnumString, Display, EnumIter, PartialEq, Eq)]
+pub enum VariantKind {
+    #[strum(ascii_case_insensitive)]
+    Classic,
+    #[strum(ascii_case_insensitive, serialize = "Diagonal", serialize = "X")]
+    Diagonal,
+    #[strum(ascii_case_insensitive)]
+    Windoku,
+    #[strum(ascii_case_insensitive)]
+    Jigsaw,
+    #[strum(ascii_case_insensitive)]
+    Killer,
+}
+
+// Extra rules on top of rows and columns
+// Classic: boxes
+// Diagonal: boxes and both main diagonals (X-Sudoku)
+// Windoku: boxes and the extra windows between them
+// Jigsaw: arbitrary regions (one region index per Cell, row major) instead of boxes
+// Killer: boxes and cages covering the board, no value twice in a cage and the sum given
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Variant {
+    #[default]
+    Classic,
+    Diagonal,
+    Windoku,
+    Jigsaw {
+        regions: Vec<u8>,
+    },
+    Killer {
+        cages: Vec<Cage>,
+    },
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind())
+    }
+}
+
+impl Variant {
+    pub fn kind(&self) -> VariantKind {
+        match self {
+            Variant::Classic => VariantKind::Classic,
+            Variant::Diagonal => VariantKind::Diagonal,
+            Variant::Windoku => VariantKind::Windoku,
+            Variant::Jigsaw { .. } => VariantKind::Jigsaw,
+            Variant::Killer { .. } => VariantKind::Killer,
+        }
+    }
+
+    // Builds a variant of the given kind
+    // Jigsaw regions are shuffled randomly from the boxes of shape
+    // Killer cages depend on the solution, they start out empty (see killer::unique_cages())
+    pub fn random<R: Rng>(kind: VariantKind, shape: Shape, rng: &mut R) -> Self {
+        match kind {
+            VariantKind::Classic => Variant::Classic,
+            VariantKind::Diagonal => Variant::Diagonal,
+            VariantKind::Windoku => Variant::Windoku,
+            VariantKind::Jigsaw => Variant::Jigsaw {
+                regions: random_regions(shape, rng),
+            },
+            VariantKind::Killer => Variant::Killer { cages: Vec::new() },
+        }
+    }
+
+    // Builds a jigsaw variant from a line of region symbols (1 based, as in board files)
+    pub fn jigsaw_from_line(shape: Shape, line: &str) -> Result<Self> {
+        let regions = line
+            .trim()
+            .bytes()
+            .map(|b| match grid::decode_symbol(b) {
+                Some(v) if v >= 1 => Ok(v - 1),
+                _ => Err(Error::Parse(format!(
+                    "Invalid region symbol '{}'",
+                    b as char
+                ))),
+            })
+            .collect::<Result<Vec<u8>>>()?;
+        let variant = Variant::Jigsaw { regions };
+        variant.validate(shape)?;
+        Ok(variant)
+    }
+
+    // Builds a killer variant from a line of cages as written by layout_line()
+    pub fn killer_from_line(shape: Shape, line: &str) -> Result<Self> {
+        let variant = Variant::Killer {
+            cages: killer::parse_cages(shape, line)?,
+        };
+        variant.validate(shape)?;
+        Ok(variant)
+    }
+
+    // Layout line for board files: jigsaw regions or killer cages, empty for everything else
+    pub fn layout_line(&self, shape: Shape) -> Option<String> {
+        match self {
+            Variant::Jigsaw { regions } => Some(
+                regions
+                    .iter()
+                    .map(|&r| grid::encode_symbol(r + 1) as char)
+                    .collect(),
+            ),
+            Variant::Killer { cages } => Some(killer::cages_line(shape, cages)),
+            _ => None,
+        }
+    }
+
+    // Region of a Cell for printing: box for classic rules, the jigsaw region otherwise
+    pub fn region_of(&self, shape: Shape, row: usize, col: usize) -> usize {
+        match self {
+            Variant::Jigsaw { regions } => regions[row * shape.size() + col] as usize,
+            _ => shape.box_of(row, col),
+        }
+    }
+
+    // True if the Cell lies on an extra house (diagonal or window)
+    pub fn is_marked(&self, shape: Shape, row: usize, col: usize) -> bool {
+        let n = shape.size();
+        match self {
+            Variant::Diagonal => row == col || row + col == n - 1,
+            Variant::Windoku => windows(shape)
+                .iter()
+                .any(|window| window.contains(&(row * n + col))),
+            _ => false,
+        }
+    }
+
+    // Killer cages, empty for every other variant
+    pub fn cages(&self) -> &[Cage] {
+        match self {
+            Variant::Killer { cages } => cages,
+            _ => &[],
+        }
+    }
+
+    // Checks that jigsaw regions cover the board with size regions of size Cells
+    // and that killer cages cover every Cell exactly once with a reachable sum a K frame can carry
+    pub fn validate(&self, shape: Shape) -> Result<()> {
+        if let Variant::Killer { cages } = self {
+            let n = shape.size();
+            let mut covered = vec![false; shape.cells()];
+            for cage in cages {
+                let largest: usize = (n + 1 - cage.cells.len().min(n)..=n).sum();
+                if cage.cells.is_empty()
+                    || cage.cells.len() > n
+                    || cage.sum < (1..=cage.cells.len()).sum::<usize>() as u32
+                    || cage.sum > largest as u32
+                {
+                    return Err(Error::Parse(format!(
+                        "Invalid cage of {} Cells adding up to {}",
+                        cage.cells.len(),
+                        cage.sum
+                    )));
+                }
+                if cage.sum > killer::MAX_SUM {
+                    return Err(Error::Parse(format!(
+                        "Cage sum {} does not fit a K frame (at most {})",
+                        cage.sum,
+                        killer::MAX_SUM
+                    )));
+                }
+                for &cell in &cage.cells {
+                    match covered.get_mut(cell) {
+                        Some(seen) if !*seen => *seen = true,
+                        Some(_) => {
+                            return Err(Error::Parse(format!(
+                                "Cell {} is in more than one cage",
+                                cell
+                            )))
+                        }
+                        None => {
+                            return Err(Error::Parse(format!(
+                                "Cage Cell {} is outside the {} board",
+                                cell, shape
+                            )))
+                        }
+                    }
+                }
+            }
+            if covered.iter().any(|&seen| !seen) {
+                return Err(Error::Parse(String::from(
+                    "Killer cages must cover every Cell",
+                )));
+            }
+        }
+        if let Variant::Jigsaw { regions } = self {
+            let n = shape.size();
+            if regions.len() != shape.cells() {
+                return Err(Error::Parse(format!(
+                    "Jigsaw needs {} regions, got {}",
+                    shape.cells(),
+                    regions.len()
+                )));
+            }
+            let mut sizes = vec![0usize; n];
+            for &r in regions {
+                match sizes.get_mut(r as usize) {
+                    Some(count) => *count += 1,
+                    None => return Err(Error::Parse(format!("Invalid jigsaw region {}", r + 1))),
+                }
+            }
+            if sizes.iter().any(|&count| count != n) {
+                return Err(Error::Parse(format!(
+                    "Every jigsaw region must have {} Cells",
+                    n
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // Every house of the variant as a list of Cell indices
+    pub fn houses(&self, shape: Shape) -> Vec<Vec<usize>> {
+        let n = shape.size();
+        let mut houses = shape.lines();
+
+        match self {
+            Variant::Jigsaw { regions } => {
+                let mut jigsaw: Vec<Vec<usize>> = vec![Vec::with_capacity(n); n];
+                for (cell, &r) in regions.iter().enumerate() {
+                    jigsaw[r as usize].push(cell);
+                }
+                houses.extend(jigsaw);
+            }
+            _ => houses.extend(shape.boxes()),
+        }
+
+        match self {
+            Variant::Diagonal => {
+                houses.push((0..n).map(|i| i * n + i).collect());
+                houses.push((0..n).map(|i| i * n + (n - 1 - i)).collect());
+            }
+            Variant::Windoku => houses.extend(windows(shape)),
+            Variant::Killer { cages } => houses.extend(cages.iter().map(|cage| cage.cells.clone())),
+            _ => (),
+        }
+        houses
+    }
+}
+
+// Windoku windows: boxes shifted by one Cell with a one Cell gutter between them
+fn windows(shape: Shape) -> Vec<Vec<usize>> {
+    let n = shape.size();
+    let starts = |len: usize| -> Vec<usize> {
+        (0..)
+            .map(|k| 1 + k * (len + 1))
+            .take_while(|start| start + len < n)
+            .collect()
+    };
+
+    let mut windows = Vec::new();
+    for &row in &starts(shape.box_rows) {
+        for &col in &starts(shape.box_cols) {
+            let mut window = Vec::with_capacity(n);
+            for r in row..row + shape.box_rows {
+                for c in col..col + shape.box_cols {
+                    window.push(r * n + c);
+                }
+            }
+            windows.push(window);
+        }
+    }
+    windows
+}
+
+// Returns true if every Cell of region can reach every other one
+fn connected(n: usize, regions: &[u8], region: u8) -> bool {
+    let cells: Vec<usize> = (0..regions.len())
+        .filter(|&c| regions[c] == region)
+        .collect();
+    let mut seen = vec![false; regions.len()];
+    let mut stack = vec![cells[0]];
+    seen[cells[0]] = true;
+    let mut reached = 0;
+
+    while let Some(cell) = stack.pop() {
+        reached += 1;
+        for next in neighbours(n, cell) {
+            if regions[next] == region && !seen[next] {
+                seen[next] = true;
+                stack.push(next);
+            }
+        }
+    }
+    reached == cells.len()
+}
+
+// Orthogonal neighbours of a Cell
+pub(crate) fn neighbours(n: usize, cell: usize) -> Vec<usize> {
+    let (row, col) = (cell / n, cell % n);
+    let mut out = Vec::with_capacity(4);
+    if row > 0 {
+        out.push(cell - n);
+    }
+    if row + 1 < n {
+        out.push(cell + n);
+    }
+    if col > 0 {
+        out.push(cell - 1);
+    }
+    if col + 1 < n {
+        out.push(cell + 1);
+    }
+    out
+}
+
+// Shuffles the boxes of shape into connected jigsaw regions of equal size
+// Cells are traded in pairs across region borders so sizes never change
+fn random_regions<R: Rng>(shape: Shape, rng: &mut R) -> Vec<u8> {
+    let n = shape.size();
+    let mut regions: Vec<u8> = (0..shape.cells())
+        .map(|cell| shape.box_of(cell / n, cell % n) as u8)
+        .collect();
+
+    for _ in 0..JIGSAW_SWAPS * shape.cells() {
+        // a moves from region ra into rb, b moves from rb into ra
+        let a = rng.gen_range(0..regions.len());
+        let ra = regions[a];
+        let mut border: Vec<usize> = neighbours(n, a)
+            .into_iter()
+            .filter(|&c| regions[c] != ra)
+            .collect();
+        border.shuffle(rng);
+        let rb = match border.first() {
+            Some(&c) => regions[c],
+            None => continue,
+        };
+        let candidates: Vec<usize> = (0..regions.len())
+            .filter(|&c| regions[c] == rb && neighbours(n, c).iter().any(|&x| regions[x] == ra))
+            .collect();
+        let b = match candidates.choose(rng) {
+            Some(&b) => b,
+            None => continue,
+        };
+
+        regions[a] = rb;
+        regions[b] = ra;
+        if !connected(n, &regions, ra) || !connected(n, &regions, rb) {
+            regions[a] = ra;
+            regions[b] = rb;
+        }
+    }
+    regions
+}